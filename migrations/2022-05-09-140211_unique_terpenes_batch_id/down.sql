@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE terpenes DROP CONSTRAINT terpenes_batch_id_key;
+
+-- Profiles of batches deleted since have nothing left to belong to
+INSERT INTO terpenes
+SELECT s.* FROM terpenes_superseded s
+WHERE EXISTS (SELECT 1 FROM batches b WHERE b.id = s.batch_id);
+
+DROP TABLE terpenes_superseded;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Keep only the newest profile of each batch so the constraint can be added. The older
+-- ones are moved aside rather than deleted, and down.sql puts them back.
+CREATE TABLE terpenes_superseded AS
+SELECT t.* FROM terpenes t
+WHERE EXISTS (SELECT 1 FROM terpenes newer WHERE newer.batch_id = t.batch_id AND newer.id > t.id);
+
+DELETE FROM terpenes t
+USING terpenes_superseded s
+WHERE s.id = t.id;
+
+ALTER TABLE terpenes ADD CONSTRAINT terpenes_batch_id_key UNIQUE (batch_id);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TYPE consumption_method AS ENUM('flower', 'vape', 'edible', 'concentrate');
+
+CREATE TABLE sessions (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    batch_id INT NOT NULL,
+    consumed_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    method CONSUMPTION_METHOD NOT NULL,
+    amount FLOAT4 NULL,
+    notes TEXT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id, consumed_at);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE reviews (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    batch_id INT NOT NULL,
+    rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    body TEXT NULL,
+    effects TEXT[] NOT NULL DEFAULT '{}',
+    flavors TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, batch_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE
+);
+
+CREATE INDEX reviews_batch_id_idx ON reviews (batch_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE inventory;
+DROP TYPE inventory_unit;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TYPE inventory_unit AS ENUM('grams', 'eighths', 'units');
+
+CREATE TABLE inventory (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL,
+    batch_id INT NOT NULL,
+    quantity FLOAT4 NOT NULL CHECK (quantity >= 0),
+    unit INVENTORY_UNIT NOT NULL,
+    purchase_price_cents INT NULL CHECK (purchase_price_cents >= 0),
+    purchase_date DATE NULL,
+    location VARCHAR(255) NULL,
+    low_stock_threshold FLOAT4 NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE
+);
+
+CREATE INDEX inventory_user_id_idx ON inventory (user_id, batch_id);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE cannabinoids (
+    id SERIAL PRIMARY KEY,
+    batch_id INT NOT NULL UNIQUE,
+    thca FLOAT4 NULL,
+    cbda FLOAT4 NULL,
+    cbg FLOAT4 NULL,
+    cbga FLOAT4 NULL,
+    cbn FLOAT4 NULL,
+    cbc FLOAT4 NULL,
+    thcv FLOAT4 NULL,
+    FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE
+);
@@ -0,0 +1,32 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE terpenes
+    ADD COLUMN caryophyllene FLOAT4 NULL,
+    ADD COLUMN humulene FLOAT4 NULL,
+    ADD COLUMN limonene FLOAT4 NULL,
+    ADD COLUMN linalool FLOAT4 NULL,
+    ADD COLUMN myrcene FLOAT4 NULL,
+    ADD COLUMN pinene FLOAT4 NULL;
+
+UPDATE terpenes t SET
+    caryophyllene = m.caryophyllene,
+    humulene = m.humulene,
+    limonene = m.limonene,
+    linalool = m.linalool,
+    myrcene = m.myrcene,
+    pinene = m.pinene
+FROM (
+    SELECT tm.terpenes_id,
+        MAX(tm.amount) FILTER (WHERE c.name = 'caryophyllene') AS caryophyllene,
+        MAX(tm.amount) FILTER (WHERE c.name = 'humulene') AS humulene,
+        MAX(tm.amount) FILTER (WHERE c.name = 'limonene') AS limonene,
+        MAX(tm.amount) FILTER (WHERE c.name = 'linalool') AS linalool,
+        MAX(tm.amount) FILTER (WHERE c.name = 'myrcene') AS myrcene,
+        MAX(tm.amount) FILTER (WHERE c.name = 'pinene') AS pinene
+    FROM terpene_measurements tm
+    INNER JOIN terpene_compounds c ON tm.compound_id = c.id
+    GROUP BY tm.terpenes_id
+) m
+WHERE m.terpenes_id = t.id;
+
+DROP TABLE terpene_measurements;
+DROP TABLE terpene_compounds;
@@ -0,0 +1,44 @@
+-- Your SQL goes here
+CREATE TABLE terpene_compounds (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE
+);
+
+INSERT INTO terpene_compounds (name) VALUES
+    ('bisabolol'), ('borneol'), ('camphene'), ('carene'), ('caryophyllene'),
+    ('caryophyllene_oxide'), ('cedrene'), ('eucalyptol'), ('farnesene'), ('fenchol'),
+    ('geraniol'), ('guaiol'), ('humulene'), ('isopulegol'), ('limonene'), ('linalool'),
+    ('myrcene'), ('nerolidol'), ('ocimene'), ('phellandrene'), ('phytol'), ('pinene'),
+    ('pulegone'), ('sabinene'), ('terpineol'), ('terpinene'), ('terpinolene'), ('valencene');
+
+CREATE TABLE terpene_measurements (
+    id SERIAL PRIMARY KEY,
+    terpenes_id INT NOT NULL,
+    compound_id INT NOT NULL,
+    amount FLOAT4 NOT NULL,
+    FOREIGN KEY (terpenes_id) REFERENCES terpenes (id) ON DELETE CASCADE,
+    FOREIGN KEY (compound_id) REFERENCES terpene_compounds (id),
+    UNIQUE (terpenes_id, compound_id)
+);
+
+INSERT INTO terpene_measurements (terpenes_id, compound_id, amount)
+SELECT t.id, c.id, v.amount
+FROM terpenes t
+CROSS JOIN LATERAL (VALUES
+    ('caryophyllene', t.caryophyllene),
+    ('humulene', t.humulene),
+    ('limonene', t.limonene),
+    ('linalool', t.linalool),
+    ('myrcene', t.myrcene),
+    ('pinene', t.pinene)
+) AS v (name, amount)
+INNER JOIN terpene_compounds c ON c.name = v.name
+WHERE v.amount IS NOT NULL;
+
+ALTER TABLE terpenes
+    DROP COLUMN caryophyllene,
+    DROP COLUMN humulene,
+    DROP COLUMN limonene,
+    DROP COLUMN linalool,
+    DROP COLUMN myrcene,
+    DROP COLUMN pinene;
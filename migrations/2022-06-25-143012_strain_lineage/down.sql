@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE strain_parents;
+
+ALTER TABLE strains
+    DROP COLUMN breeder,
+    DROP COLUMN phenotype;
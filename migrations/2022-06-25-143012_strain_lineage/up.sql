@@ -0,0 +1,13 @@
+-- Your SQL goes here
+ALTER TABLE strains
+    ADD COLUMN breeder VARCHAR(255),
+    ADD COLUMN phenotype VARCHAR(255);
+
+CREATE TABLE strain_parents (
+    strain_id INT NOT NULL REFERENCES strains (id) ON DELETE CASCADE,
+    parent_id INT NOT NULL REFERENCES strains (id) ON DELETE CASCADE,
+    PRIMARY KEY (strain_id, parent_id),
+    CHECK (strain_id <> parent_id)
+);
+
+CREATE INDEX strain_parents_parent_id_idx ON strain_parents (parent_id);
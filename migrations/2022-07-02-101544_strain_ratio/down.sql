@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE strains
+    DROP COLUMN indica_percent,
+    DROP COLUMN sativa_percent;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE strains
+    ADD COLUMN indica_percent INT CHECK (indica_percent BETWEEN 0 AND 100),
+    ADD COLUMN sativa_percent INT CHECK (sativa_percent BETWEEN 0 AND 100),
+    ADD CONSTRAINT strains_ratio_check CHECK (
+        (indica_percent IS NULL) = (sativa_percent IS NULL)
+        AND COALESCE(indica_percent + sativa_percent, 100) = 100
+    );
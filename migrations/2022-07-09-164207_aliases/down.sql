@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+-- pg_trgm is left installed: other objects in the database may depend on it
+DROP INDEX strains_name_trgm_idx;
+DROP INDEX growers_name_trgm_idx;
+DROP TABLE grower_aliases;
+DROP TABLE strain_aliases;
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE TABLE strain_aliases (
+    id SERIAL PRIMARY KEY,
+    strain_id INT NOT NULL REFERENCES strains (id) ON DELETE CASCADE,
+    alias VARCHAR(255) NOT NULL
+);
+
+CREATE TABLE grower_aliases (
+    id SERIAL PRIMARY KEY,
+    grower_id INT NOT NULL REFERENCES growers (id) ON DELETE CASCADE,
+    alias VARCHAR(255) NOT NULL
+);
+
+-- An alias names one strain (or grower) only, whatever its case
+CREATE UNIQUE INDEX strain_aliases_alias_key ON strain_aliases (LOWER(alias));
+CREATE UNIQUE INDEX grower_aliases_alias_key ON grower_aliases (LOWER(alias));
+CREATE INDEX strain_aliases_strain_id_idx ON strain_aliases (strain_id);
+CREATE INDEX grower_aliases_grower_id_idx ON grower_aliases (grower_id);
+
+CREATE INDEX strains_name_trgm_idx ON strains USING GIN (name gin_trgm_ops);
+CREATE INDEX growers_name_trgm_idx ON growers USING GIN (name gin_trgm_ops);
+CREATE INDEX strain_aliases_alias_trgm_idx ON strain_aliases USING GIN (alias gin_trgm_ops);
+CREATE INDEX grower_aliases_alias_trgm_idx ON grower_aliases USING GIN (alias gin_trgm_ops);
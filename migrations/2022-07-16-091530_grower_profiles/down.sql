@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE growers
+    DROP COLUMN license_number,
+    DROP COLUMN state,
+    DROP COLUMN city,
+    DROP COLUMN website,
+    DROP COLUMN cultivation_type,
+    DROP COLUMN active;
+
+DROP TYPE cultivation_type;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TYPE cultivation_type AS ENUM('indoor', 'outdoor', 'greenhouse', 'living_soil');
+
+ALTER TABLE growers
+    ADD COLUMN license_number VARCHAR(255),
+    ADD COLUMN state VARCHAR(2),
+    ADD COLUMN city VARCHAR(255),
+    ADD COLUMN website VARCHAR(255),
+    ADD COLUMN cultivation_type cultivation_type,
+    ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD CONSTRAINT growers_license_state_check CHECK (license_number IS NULL OR state IS NOT NULL);
+
+CREATE INDEX growers_state_idx ON growers (state);
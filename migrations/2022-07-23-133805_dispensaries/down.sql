@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE listings;
+DROP TABLE dispensaries;
+DROP TYPE product_format;
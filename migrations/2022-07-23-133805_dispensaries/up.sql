@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TYPE product_format AS ENUM('flower', 'pre_roll', 'vape', 'concentrate', 'edible');
+
+CREATE TABLE dispensaries (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    address VARCHAR(255) NULL,
+    license_number VARCHAR(255) NULL
+);
+
+CREATE TABLE listings (
+    id SERIAL PRIMARY KEY,
+    dispensary_id INT NOT NULL,
+    batch_id INT NOT NULL,
+    format PRODUCT_FORMAT NOT NULL DEFAULT 'flower',
+    weight_grams FLOAT4 NOT NULL CHECK (weight_grams > 0),
+    price_cents INT NOT NULL CHECK (price_cents >= 0),
+    in_stock BOOLEAN NOT NULL DEFAULT TRUE,
+    observed_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (dispensary_id) REFERENCES dispensaries (id) ON DELETE CASCADE,
+    FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE
+);
+
+CREATE INDEX listings_dispensary_id_idx ON listings (dispensary_id);
+CREATE INDEX listings_batch_id_idx ON listings (batch_id);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE price_observations (
+    id SERIAL PRIMARY KEY,
+    batch_id INT NOT NULL,
+    listing_id INT NULL,
+    weight_grams FLOAT4 NOT NULL CHECK (weight_grams > 0),
+    price_cents INT NOT NULL CHECK (price_cents >= 0),
+    observed_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE,
+    FOREIGN KEY (listing_id) REFERENCES listings (id) ON DELETE SET NULL
+);
+
+CREATE INDEX price_observations_batch_id_observed_at_idx
+    ON price_observations (batch_id, observed_at);
+
+INSERT INTO price_observations (batch_id, listing_id, weight_grams, price_cents, observed_at)
+SELECT batch_id, id, weight_grams, price_cents, observed_at FROM listings;
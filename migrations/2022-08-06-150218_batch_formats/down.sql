@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX batches_parent_batch_id_idx;
+
+ALTER TABLE batches
+    DROP COLUMN parent_batch_id,
+    DROP COLUMN format;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE batches
+    ADD COLUMN format PRODUCT_FORMAT NOT NULL DEFAULT 'flower',
+    ADD COLUMN parent_batch_id INT NULL REFERENCES batches (id) ON DELETE SET NULL,
+    ADD CONSTRAINT batches_parent_batch_id_check CHECK (parent_batch_id <> id);
+
+CREATE INDEX batches_parent_batch_id_idx ON batches (parent_batch_id);
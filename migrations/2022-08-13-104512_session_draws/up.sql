@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- What each session took out of each inventory item, in the item's unit, so that editing
+-- or deleting the session gives back exactly that. Sessions logged before this table
+-- existed have no draws and give nothing back.
+CREATE TABLE session_draws (
+    session_id INT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+    inventory_id INT NOT NULL REFERENCES inventory (id) ON DELETE CASCADE,
+    quantity FLOAT4 NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (session_id, inventory_id)
+);
+
+CREATE INDEX session_draws_inventory_id_idx ON session_draws (inventory_id);
@@ -0,0 +1,869 @@
+use super::models::{
+    Batch, BatchResponse, Cannabinoids, Grower, GrowerAlias, NewBatch, NewCannabinoids, NewGrower,
+    NewGrowerAlias, NewStrain, NewStrainAlias, NewTerpenes, PotencyUnit, ProductFormat, Strain,
+    StrainAlias, StrainParent, TerpeneCompound,
+};
+use super::schema::batches::dsl::{
+    batches, cbd_content, final_test_date, format as batch_format, grower_id, harvest_date,
+    id as bid, package_date, parent_batch_id, strain_id, thc_content,
+};
+use super::schema::cannabinoids::dsl::{cannabinoids, id as cannabinoids_id};
+use super::schema::grower_aliases::dsl::{
+    alias as grower_alias, grower_aliases, grower_id as aliased_grower, id as grower_alias_id,
+};
+use super::schema::growers::dsl::{
+    active, city, cultivation_type, growers, id as gid, license_number, name as grower_name, state,
+    website,
+};
+use super::schema::strain_aliases::dsl::{
+    alias as strain_alias, id as strain_alias_id, strain_aliases, strain_id as aliased_strain,
+};
+use super::schema::strain_parents::dsl::{parent_id, strain_id as child_id, strain_parents};
+use super::schema::strains::dsl::{
+    breeder, id as sid, indica_percent, name as strain_name, phenotype, sativa_percent, species,
+    strains,
+};
+use super::schema::terpene_compounds::dsl::{
+    id as compound_id, name as compound_name, terpene_compounds,
+};
+use super::schema::terpene_measurements::dsl::{
+    amount, id as measurement_id, terpene_measurements,
+};
+use super::schema::terpenes::dsl::{batch_id as terpenes_batch, terpenes};
+use super::validation::{Validate, ValidationErrors};
+
+use super::db::Creatable;
+
+use chrono::NaiveDate;
+use diesel::connection::TransactionManager;
+use diesel::pg::PgConnection;
+use diesel::result::Error;
+use diesel::{sql_query, Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Deref;
+
+/// Records fetched per query while exporting, and inserted per statement while importing
+pub const CHUNK_SIZE: i64 = 500;
+
+/// Serialization of an export. JSON Lines can hold the whole archive; a CSV file holds
+/// a single table.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Csv,
+    #[default]
+    Jsonl,
+}
+
+impl ArchiveFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Csv => "text/csv",
+            ArchiveFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Csv => "csv",
+            ArchiveFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// A table of the catalog archive. `BatchResponses` is the joined batch view: it can be
+/// exported but not imported.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveTable {
+    TerpeneCompounds,
+    Strains,
+    StrainParents,
+    StrainAliases,
+    Growers,
+    GrowerAliases,
+    Batches,
+    Terpenes,
+    Cannabinoids,
+    BatchResponses,
+}
+
+impl ArchiveTable {
+    /// Tables of a full archive, in the order they have to be restored
+    pub const ALL: [ArchiveTable; 9] = [
+        ArchiveTable::TerpeneCompounds,
+        ArchiveTable::Strains,
+        ArchiveTable::StrainParents,
+        ArchiveTable::StrainAliases,
+        ArchiveTable::Growers,
+        ArchiveTable::GrowerAliases,
+        ArchiveTable::Batches,
+        ArchiveTable::Terpenes,
+        ArchiveTable::Cannabinoids,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveTable::TerpeneCompounds => "terpene_compounds",
+            ArchiveTable::Strains => "strains",
+            ArchiveTable::StrainParents => "strain_parents",
+            ArchiveTable::StrainAliases => "strain_aliases",
+            ArchiveTable::Growers => "growers",
+            ArchiveTable::GrowerAliases => "grower_aliases",
+            ArchiveTable::Batches => "batches",
+            ArchiveTable::Terpenes => "terpenes",
+            ArchiveTable::Cannabinoids => "cannabinoids",
+            ArchiveTable::BatchResponses => "batch_responses",
+        }
+    }
+}
+
+/// One measurement of a terpene profile. Profiles are archived one compound per row so
+/// every table has fixed columns.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct TerpeneRecord {
+    pub batch_id: i32,
+    pub compound: String,
+    pub amount: f32,
+}
+
+/// The columns of `BatchResponse` that fit in a CSV row. The profile, panel and ratings
+/// are left out; they are archived in their own tables.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResponseRow {
+    pub id: i32,
+    pub strain_id: i32,
+    pub strain: String,
+    pub grower_id: i32,
+    pub grower: String,
+    pub harvest_date: Option<NaiveDate>,
+    pub final_test_date: Option<NaiveDate>,
+    pub package_date: Option<NaiveDate>,
+    pub thc_content: f32,
+    pub cbd_content: f32,
+    pub format: ProductFormat,
+    pub potency_unit: PotencyUnit,
+    pub parent_batch_id: Option<i32>,
+    pub total_thc: f32,
+    pub total_cbd: f32,
+}
+
+impl From<&BatchResponse> for BatchResponseRow {
+    fn from(b: &BatchResponse) -> Self {
+        BatchResponseRow {
+            id: b.id,
+            strain_id: b.strain_id,
+            strain: b.strain.clone(),
+            grower_id: b.grower_id,
+            grower: b.grower.clone(),
+            harvest_date: b.harvest_date,
+            final_test_date: b.final_test_date,
+            package_date: b.package_date,
+            thc_content: b.thc_content,
+            cbd_content: b.cbd_content,
+            format: b.format,
+            potency_unit: b.potency_unit,
+            parent_batch_id: b.parent_batch_id,
+            total_thc: b.total_thc,
+            total_cbd: b.total_cbd,
+        }
+    }
+}
+
+/// One line of a JSON Lines archive: the table and one of its records
+///
+/// Ex:
+///     `{"table": "strains", "record": {"id": 3, "name": "Blackwater OG", "species": "Indica"}}`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "table", content = "record", rename_all = "snake_case")]
+pub enum ArchiveRecord {
+    TerpeneCompounds(TerpeneCompound),
+    Strains(Strain),
+    StrainParents(StrainParent),
+    StrainAliases(StrainAlias),
+    Growers(Grower),
+    GrowerAliases(GrowerAlias),
+    Batches(Batch),
+    Terpenes(TerpeneRecord),
+    Cannabinoids(Cannabinoids),
+    BatchResponses(Box<BatchResponse>),
+}
+
+impl ArchiveRecord {
+    pub fn table(&self) -> ArchiveTable {
+        match self {
+            ArchiveRecord::TerpeneCompounds(_) => ArchiveTable::TerpeneCompounds,
+            ArchiveRecord::Strains(_) => ArchiveTable::Strains,
+            ArchiveRecord::StrainParents(_) => ArchiveTable::StrainParents,
+            ArchiveRecord::StrainAliases(_) => ArchiveTable::StrainAliases,
+            ArchiveRecord::Growers(_) => ArchiveTable::Growers,
+            ArchiveRecord::GrowerAliases(_) => ArchiveTable::GrowerAliases,
+            ArchiveRecord::Batches(_) => ArchiveTable::Batches,
+            ArchiveRecord::Terpenes(_) => ArchiveTable::Terpenes,
+            ArchiveRecord::Cannabinoids(_) => ArchiveTable::Cannabinoids,
+            ArchiveRecord::BatchResponses(_) => ArchiveTable::BatchResponses,
+        }
+    }
+
+    /// Write the record itself (without its table) as a CSV row
+    fn write_csv<W: std::io::Write>(&self, writer: &mut csv::Writer<W>) -> csv::Result<()> {
+        match self {
+            ArchiveRecord::TerpeneCompounds(r) => writer.serialize(r),
+            ArchiveRecord::Strains(r) => writer.serialize(r),
+            ArchiveRecord::StrainParents(r) => writer.serialize(r),
+            ArchiveRecord::StrainAliases(r) => writer.serialize(r),
+            ArchiveRecord::Growers(r) => writer.serialize(r),
+            ArchiveRecord::GrowerAliases(r) => writer.serialize(r),
+            ArchiveRecord::Batches(r) => writer.serialize(r),
+            ArchiveRecord::Terpenes(r) => writer.serialize(r),
+            ArchiveRecord::Cannabinoids(r) => writer.serialize(r),
+            ArchiveRecord::BatchResponses(r) => {
+                writer.serialize(BatchResponseRow::from(r.as_ref()))
+            }
+        }
+    }
+}
+
+/// Up to `CHUNK_SIZE` records of `table` that come after key `after`, in key order,
+/// along with the key of the last one. Walking a table this way never holds more than
+/// one chunk in memory.
+///
+/// Example:
+/// let (first, last) = export_chunk(&conn, ArchiveTable::Strains, 0).unwrap();
+/// let (next, _) = export_chunk(&conn, ArchiveTable::Strains, last.unwrap()).unwrap();
+pub fn export_chunk(
+    conn: &PgConnection,
+    table: ArchiveTable,
+    after: i32,
+) -> Result<(Vec<ArchiveRecord>, Option<i32>), Error> {
+    let keyed: Vec<(i32, ArchiveRecord)> = match table {
+        ArchiveTable::TerpeneCompounds => terpene_compounds
+            .filter(compound_id.gt(after))
+            .order(compound_id)
+            .limit(CHUNK_SIZE)
+            .load::<TerpeneCompound>(conn)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::TerpeneCompounds(r)))
+            .collect(),
+        ArchiveTable::Strains => strains
+            .filter(sid.gt(after))
+            .order(sid)
+            .limit(CHUNK_SIZE)
+            .load::<Strain>(conn)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::Strains(r)))
+            .collect(),
+        // Keyed by strain, so every parent of a strain is sent in the same chunk
+        ArchiveTable::StrainParents => {
+            let children: Vec<i32> = strain_parents
+                .select(child_id)
+                .filter(child_id.gt(after))
+                .distinct()
+                .order(child_id)
+                .limit(CHUNK_SIZE)
+                .load(conn)?;
+            strain_parents
+                .filter(child_id.eq_any(children))
+                .order((child_id, parent_id))
+                .load::<StrainParent>(conn)?
+                .into_iter()
+                .map(|r| (r.strain_id, ArchiveRecord::StrainParents(r)))
+                .collect()
+        }
+        ArchiveTable::StrainAliases => strain_aliases
+            .filter(strain_alias_id.gt(after))
+            .order(strain_alias_id)
+            .limit(CHUNK_SIZE)
+            .load::<StrainAlias>(conn)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::StrainAliases(r)))
+            .collect(),
+        ArchiveTable::Growers => growers
+            .filter(gid.gt(after))
+            .order(gid)
+            .limit(CHUNK_SIZE)
+            .load::<Grower>(conn)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::Growers(r)))
+            .collect(),
+        ArchiveTable::GrowerAliases => grower_aliases
+            .filter(grower_alias_id.gt(after))
+            .order(grower_alias_id)
+            .limit(CHUNK_SIZE)
+            .load::<GrowerAlias>(conn)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::GrowerAliases(r)))
+            .collect(),
+        ArchiveTable::Batches => batches
+            .filter(bid.gt(after))
+            .order(bid)
+            .limit(CHUNK_SIZE)
+            .load::<Batch>(conn)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::Batches(r)))
+            .collect(),
+        ArchiveTable::Terpenes => terpene_measurements
+            .inner_join(terpenes)
+            .inner_join(terpene_compounds)
+            .filter(measurement_id.gt(after))
+            .order(measurement_id)
+            .limit(CHUNK_SIZE)
+            .select((measurement_id, terpenes_batch, compound_name, amount))
+            .load::<(i32, i32, String, f32)>(conn)?
+            .into_iter()
+            .map(|(key, batch, compound, amt)| {
+                let record = TerpeneRecord {
+                    batch_id: batch,
+                    compound,
+                    amount: amt,
+                };
+                (key, ArchiveRecord::Terpenes(record))
+            })
+            .collect(),
+        ArchiveTable::Cannabinoids => cannabinoids
+            .filter(cannabinoids_id.gt(after))
+            .order(cannabinoids_id)
+            .limit(CHUNK_SIZE)
+            .load::<Cannabinoids>(conn)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::Cannabinoids(r)))
+            .collect(),
+        ArchiveTable::BatchResponses => Batch::responses_after(conn, after, CHUNK_SIZE)?
+            .into_iter()
+            .map(|r| (r.id, ArchiveRecord::BatchResponses(Box::new(r))))
+            .collect(),
+    };
+    let last = keyed.last().map(|(key, _)| *key);
+    Ok((keyed.into_iter().map(|(_, r)| r).collect(), last))
+}
+
+/// One connection reading a single snapshot of the catalog for a whole export, so the
+/// tables of an archive agree with each other even while the catalog changes under it.
+/// The snapshot is a `REPEATABLE READ READ ONLY` transaction; an export dropped before it
+/// is finished rolls the transaction back, so the connection goes back to its pool clean.
+///
+/// Example:
+/// let snapshot = ExportSnapshot::begin(pool.get()?)?;
+/// let (strains, _) = snapshot.chunk(ArchiveTable::Strains, 0)?;
+/// snapshot.finish()?;
+pub struct ExportSnapshot<C: Deref<Target = PgConnection>> {
+    conn: C,
+    open: bool,
+}
+
+impl<C: Deref<Target = PgConnection>> ExportSnapshot<C> {
+    pub fn begin(conn: C) -> Result<Self, Error> {
+        conn.transaction_manager()
+            .begin_transaction_sql(&*conn, "BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")?;
+        Ok(ExportSnapshot { conn, open: true })
+    }
+
+    /// `export_chunk` as of the moment the export began
+    pub fn chunk(
+        &self,
+        table: ArchiveTable,
+        after: i32,
+    ) -> Result<(Vec<ArchiveRecord>, Option<i32>), Error> {
+        export_chunk(&self.conn, table, after)
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.open = false;
+        self.conn
+            .transaction_manager()
+            .commit_transaction(&*self.conn)
+    }
+}
+
+impl<C: Deref<Target = PgConnection>> Drop for ExportSnapshot<C> {
+    fn drop(&mut self) {
+        if self.open {
+            let _ = self
+                .conn
+                .transaction_manager()
+                .rollback_transaction(&*self.conn);
+        }
+    }
+}
+
+/// Render a chunk of one table's records. CSV chunks carry the header row only when
+/// `first` is set.
+pub fn render(
+    format: ArchiveFormat,
+    records: &[ArchiveRecord],
+    first: bool,
+) -> Result<Vec<u8>, String> {
+    match format {
+        ArchiveFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(first)
+                .from_writer(vec![]);
+            for r in records {
+                r.write_csv(&mut writer).map_err(|e| e.to_string())?;
+            }
+            writer.into_inner().map_err(|e| e.to_string())
+        }
+        ArchiveFormat::Jsonl => {
+            let mut out = vec![];
+            for r in records {
+                serde_json::to_writer(&mut out, r).map_err(|e| e.to_string())?;
+                out.push(b'\n');
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// Read an archive produced by `render`. A CSV file holds the records of `table` only.
+pub fn parse(
+    format: ArchiveFormat,
+    table: Option<ArchiveTable>,
+    body: &[u8],
+) -> Result<Vec<ArchiveRecord>, String> {
+    match (format, table) {
+        (ArchiveFormat::Jsonl, _) => body
+            .split(|b| *b == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+            .map(|(i, line)| {
+                serde_json::from_slice(line).map_err(|e| format!("line {}: {}", i + 1, e))
+            })
+            .collect(),
+        (ArchiveFormat::Csv, None) => Err("a CSV archive needs a table".to_owned()),
+        (ArchiveFormat::Csv, Some(t)) => {
+            let mut reader = csv::Reader::from_reader(body);
+            let records: Result<Vec<ArchiveRecord>, csv::Error> = match t {
+                ArchiveTable::TerpeneCompounds => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::TerpeneCompounds))
+                    .collect(),
+                ArchiveTable::Strains => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::Strains))
+                    .collect(),
+                ArchiveTable::StrainParents => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::StrainParents))
+                    .collect(),
+                ArchiveTable::StrainAliases => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::StrainAliases))
+                    .collect(),
+                ArchiveTable::Growers => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::Growers))
+                    .collect(),
+                ArchiveTable::GrowerAliases => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::GrowerAliases))
+                    .collect(),
+                ArchiveTable::Batches => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::Batches))
+                    .collect(),
+                ArchiveTable::Terpenes => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::Terpenes))
+                    .collect(),
+                ArchiveTable::Cannabinoids => reader
+                    .deserialize()
+                    .map(|r| r.map(ArchiveRecord::Cannabinoids))
+                    .collect(),
+                ArchiveTable::BatchResponses => return Err(read_only()),
+            };
+            records.map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn read_only() -> String {
+    "batch_responses is a view and can't be imported".to_owned()
+}
+
+/// Number of records restored into each table
+pub type RestoreReport = BTreeMap<&'static str, usize>;
+
+/// Whether `table` already has records. The terpene catalog is seeded by a migration, so
+/// it is never considered.
+fn is_populated(conn: &PgConnection, table: ArchiveTable) -> Result<bool, Error> {
+    let count: i64 = match table {
+        ArchiveTable::Strains => strains.count().get_result(conn)?,
+        ArchiveTable::StrainParents => strain_parents.count().get_result(conn)?,
+        ArchiveTable::StrainAliases => strain_aliases.count().get_result(conn)?,
+        ArchiveTable::Growers => growers.count().get_result(conn)?,
+        ArchiveTable::GrowerAliases => grower_aliases.count().get_result(conn)?,
+        ArchiveTable::Batches => batches.count().get_result(conn)?,
+        ArchiveTable::Terpenes => terpenes.count().get_result(conn)?,
+        ArchiveTable::Cannabinoids => cannabinoids.count().get_result(conn)?,
+        ArchiveTable::TerpeneCompounds | ArchiveTable::BatchResponses => 0,
+    };
+    Ok(count > 0)
+}
+
+/// Point a table's id sequence past the ids that were restored into it
+fn reset_sequence(conn: &PgConnection, table: &str) -> Result<(), Error> {
+    sql_query(format!(
+        "SELECT setval(pg_get_serial_sequence('{0}', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {0}",
+        table
+    ))
+    .execute(conn)
+    .map(|_| ())
+}
+
+/// `records` reordered so that a batch comes after the batch it was made from whenever
+/// both are in the archive; chunks of an export may hold a product before its parent.
+/// Batches left in a cycle keep their order, and the foreign key then reports them.
+fn parents_first(records: &[Batch]) -> Vec<&Batch> {
+    let archived: BTreeSet<i32> = records.iter().map(|b| b.id).collect();
+    let mut placed = BTreeSet::new();
+    let mut ordered = Vec::with_capacity(records.len());
+    let mut pending: Vec<&Batch> = records.iter().collect();
+    while !pending.is_empty() {
+        let (ready, waiting): (Vec<&Batch>, Vec<&Batch>) =
+            pending.into_iter().partition(|b| match b.parent_batch_id {
+                Some(p) => !archived.contains(&p) || placed.contains(&p),
+                None => true,
+            });
+        if ready.is_empty() {
+            ordered.extend(waiting);
+            break;
+        }
+        placed.extend(ready.iter().map(|b| b.id));
+        ordered.extend(ready);
+        pending = waiting;
+    }
+    ordered
+}
+
+/// Load an exported archive into a catalog with no strains, growers or batches yet.
+/// Strains, growers, their aliases and batches keep their ids so the references between them still
+/// hold. Lineage links are checked for cycles as they are added, and terpene compounds
+/// missing from the catalog are added by name.
+///
+/// Every record is validated first, with errors named after the table and the record's
+/// position in it (`batches[4].thc_content`), and nothing is written unless all of them
+/// pass. The restore runs in one transaction.
+///
+/// Example:
+/// let records = parse(ArchiveFormat::Jsonl, None, &body).unwrap();
+/// let report = restore(&conn, records).unwrap();
+/// assert_eq!(report["strains"], 6);
+pub fn restore(conn: &PgConnection, records: Vec<ArchiveRecord>) -> Result<RestoreReport, Error> {
+    let mut compounds = vec![];
+    let mut new_strains = vec![];
+    let mut links = vec![];
+    let mut new_strain_aliases = vec![];
+    let mut new_growers = vec![];
+    let mut new_grower_aliases = vec![];
+    let mut new_batches = vec![];
+    let mut profiles: BTreeMap<i32, NewTerpenes> = BTreeMap::new();
+    let mut panels = vec![];
+    let mut errors = ValidationErrors::default();
+
+    for r in records {
+        match r {
+            ArchiveRecord::TerpeneCompounds(c) => compounds.push(c.name),
+            ArchiveRecord::Strains(s) => new_strains.push(s),
+            ArchiveRecord::StrainParents(l) => links.push(l),
+            ArchiveRecord::StrainAliases(a) => new_strain_aliases.push(a),
+            ArchiveRecord::Growers(g) => new_growers.push(g),
+            ArchiveRecord::GrowerAliases(a) => new_grower_aliases.push(a),
+            ArchiveRecord::Batches(b) => new_batches.push(b),
+            ArchiveRecord::Terpenes(t) => {
+                let profile = profiles.entry(t.batch_id).or_insert_with(|| NewTerpenes {
+                    batch_id: t.batch_id,
+                    ..Default::default()
+                });
+                profile.compounds.insert(t.compound, t.amount);
+            }
+            ArchiveRecord::Cannabinoids(c) => panels.push(NewCannabinoids {
+                batch_id: c.batch_id,
+                thca: c.thca,
+                cbda: c.cbda,
+                cbg: c.cbg,
+                cbga: c.cbga,
+                cbn: c.cbn,
+                cbc: c.cbc,
+                thcv: c.thcv,
+            }),
+            ArchiveRecord::BatchResponses(_) => errors.add("batch_responses", read_only()),
+        }
+    }
+
+    for (i, s) in new_strains.iter().enumerate() {
+        let new = NewStrain {
+            name: s.name.clone(),
+            species: s.species.clone(),
+            breeder: s.breeder.clone(),
+            phenotype: s.phenotype.clone(),
+            indica_percent: s.indica_percent,
+            sativa_percent: s.sativa_percent,
+        };
+        if let Err(e) = new.validate() {
+            errors.nest(&format!("strains[{}]", i), e);
+        }
+    }
+    for (i, g) in new_growers.iter().enumerate() {
+        let new = NewGrower {
+            name: g.name.clone(),
+            license_number: g.license_number.clone(),
+            state: g.state.clone(),
+            city: g.city.clone(),
+            website: g.website.clone(),
+            cultivation_type: g.cultivation_type,
+            active: g.active,
+        };
+        if let Err(e) = new.validate() {
+            errors.nest(&format!("growers[{}]", i), e);
+        }
+    }
+    for (i, a) in new_strain_aliases.iter().enumerate() {
+        let new = NewStrainAlias {
+            strain_id: a.strain_id,
+            alias: a.alias.clone(),
+        };
+        if let Err(e) = new.validate() {
+            errors.nest(&format!("strain_aliases[{}]", i), e);
+        }
+    }
+    for (i, a) in new_grower_aliases.iter().enumerate() {
+        let new = NewGrowerAlias {
+            grower_id: a.grower_id,
+            alias: a.alias.clone(),
+        };
+        if let Err(e) = new.validate() {
+            errors.nest(&format!("grower_aliases[{}]", i), e);
+        }
+    }
+    for (i, b) in new_batches.iter().enumerate() {
+        if let Err(e) = NewBatch::from(b).validate() {
+            errors.nest(&format!("batches[{}]", i), e);
+        }
+    }
+
+    conn.transaction(|| {
+        let present = [
+            (ArchiveTable::Strains, !new_strains.is_empty()),
+            (ArchiveTable::StrainParents, !links.is_empty()),
+            (ArchiveTable::StrainAliases, !new_strain_aliases.is_empty()),
+            (ArchiveTable::Growers, !new_growers.is_empty()),
+            (ArchiveTable::GrowerAliases, !new_grower_aliases.is_empty()),
+            (ArchiveTable::Batches, !new_batches.is_empty()),
+            (ArchiveTable::Terpenes, !profiles.is_empty()),
+            (ArchiveTable::Cannabinoids, !panels.is_empty()),
+        ];
+        for (table, _) in present.iter().filter(|p| p.1) {
+            if is_populated(conn, *table)? {
+                errors.add(
+                    table.name(),
+                    "already has records; import into an empty catalog",
+                );
+            }
+        }
+        std::mem::take(&mut errors).into_result()?;
+
+        let mut report = RestoreReport::new();
+        let names: Vec<_> = compounds
+            .iter()
+            .map(|c| compound_name.eq(c.trim().to_lowercase()))
+            .collect();
+        let added = match names.is_empty() {
+            true => 0,
+            false => diesel::insert_into(terpene_compounds)
+                .values(&names)
+                .on_conflict(compound_name)
+                .do_nothing()
+                .execute(conn)?,
+        };
+        report.insert(ArchiveTable::TerpeneCompounds.name(), added);
+
+        for chunk in new_strains.chunks(CHUNK_SIZE as usize) {
+            let rows: Vec<_> = chunk
+                .iter()
+                .map(|s| {
+                    (
+                        sid.eq(s.id),
+                        strain_name.eq(&s.name),
+                        species.eq(s.species.clone()),
+                        breeder.eq(&s.breeder),
+                        phenotype.eq(&s.phenotype),
+                        indica_percent.eq(s.indica_percent),
+                        sativa_percent.eq(s.sativa_percent),
+                    )
+                })
+                .collect();
+            diesel::insert_into(strains).values(&rows).execute(conn)?;
+        }
+        for chunk in new_growers.chunks(CHUNK_SIZE as usize) {
+            let rows: Vec<_> = chunk
+                .iter()
+                .map(|g| {
+                    (
+                        gid.eq(g.id),
+                        grower_name.eq(&g.name),
+                        license_number.eq(&g.license_number),
+                        state.eq(&g.state),
+                        city.eq(&g.city),
+                        website.eq(&g.website),
+                        cultivation_type.eq(g.cultivation_type),
+                        active.eq(g.active),
+                    )
+                })
+                .collect();
+            diesel::insert_into(growers).values(&rows).execute(conn)?;
+        }
+        for chunk in parents_first(&new_batches).chunks(CHUNK_SIZE as usize) {
+            let rows: Vec<_> = chunk
+                .iter()
+                .map(|b| {
+                    (
+                        bid.eq(b.id),
+                        strain_id.eq(b.strain_id),
+                        harvest_date.eq(b.harvest_date),
+                        final_test_date.eq(b.final_test_date),
+                        package_date.eq(b.package_date),
+                        grower_id.eq(b.grower_id),
+                        thc_content.eq(b.thc_content),
+                        cbd_content.eq(b.cbd_content),
+                        batch_format.eq(b.format),
+                        parent_batch_id.eq(b.parent_batch_id),
+                    )
+                })
+                .collect();
+            diesel::insert_into(batches).values(&rows).execute(conn)?;
+        }
+        for chunk in new_strain_aliases.chunks(CHUNK_SIZE as usize) {
+            let rows: Vec<_> = chunk
+                .iter()
+                .map(|a| {
+                    (
+                        strain_alias_id.eq(a.id),
+                        aliased_strain.eq(a.strain_id),
+                        strain_alias.eq(&a.alias),
+                    )
+                })
+                .collect();
+            diesel::insert_into(strain_aliases)
+                .values(&rows)
+                .execute(conn)?;
+        }
+        for chunk in new_grower_aliases.chunks(CHUNK_SIZE as usize) {
+            let rows: Vec<_> = chunk
+                .iter()
+                .map(|a| {
+                    (
+                        grower_alias_id.eq(a.id),
+                        aliased_grower.eq(a.grower_id),
+                        grower_alias.eq(&a.alias),
+                    )
+                })
+                .collect();
+            diesel::insert_into(grower_aliases)
+                .values(&rows)
+                .execute(conn)?;
+        }
+        reset_sequence(conn, "strains")?;
+        reset_sequence(conn, "strain_aliases")?;
+        reset_sequence(conn, "growers")?;
+        reset_sequence(conn, "grower_aliases")?;
+        reset_sequence(conn, "batches")?;
+        report.insert(ArchiveTable::Strains.name(), new_strains.len());
+        for link in &links {
+            link.create(conn)?;
+        }
+        report.insert(ArchiveTable::StrainParents.name(), links.len());
+        report.insert(ArchiveTable::StrainAliases.name(), new_strain_aliases.len());
+        report.insert(ArchiveTable::Growers.name(), new_growers.len());
+        report.insert(ArchiveTable::GrowerAliases.name(), new_grower_aliases.len());
+        report.insert(ArchiveTable::Batches.name(), new_batches.len());
+
+        for profile in profiles.values() {
+            profile.create(conn)?;
+        }
+        report.insert(ArchiveTable::Terpenes.name(), profiles.len());
+        for panel in &panels {
+            panel.create(conn)?;
+        }
+        report.insert(ArchiveTable::Cannabinoids.name(), panels.len());
+        Ok(report)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Species;
+
+    #[test]
+    fn jsonl_round_trips() {
+        let records = vec![
+            ArchiveRecord::Strains(Strain {
+                id: 3,
+                name: "Blackwater OG".to_owned(),
+                species: Species::Indica,
+                breeder: None,
+                phenotype: None,
+                indica_percent: None,
+                sativa_percent: None,
+            }),
+            ArchiveRecord::Terpenes(TerpeneRecord {
+                batch_id: 2,
+                compound: "limonene".to_owned(),
+                amount: 0.42,
+            }),
+        ];
+        let body = render(ArchiveFormat::Jsonl, &records, true).unwrap();
+        assert!(body.starts_with(br#"{"table":"strains","record":{"id":3"#));
+        let parsed = parse(ArchiveFormat::Jsonl, None, &body).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].table(), ArchiveTable::Terpenes);
+    }
+
+    #[test]
+    fn csv_header_only_on_first_chunk() {
+        let records = vec![ArchiveRecord::Terpenes(TerpeneRecord {
+            batch_id: 2,
+            compound: "limonene".to_owned(),
+            amount: 0.42,
+        })];
+        let first = render(ArchiveFormat::Csv, &records, true).unwrap();
+        let next = render(ArchiveFormat::Csv, &records, false).unwrap();
+        assert_eq!(first, b"batch_id,compound,amount\n2,limonene,0.42\n");
+        assert_eq!(next, b"2,limonene,0.42\n");
+
+        let parsed = parse(ArchiveFormat::Csv, Some(ArchiveTable::Terpenes), &first).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert!(parse(ArchiveFormat::Csv, None, &first).is_err());
+    }
+
+    #[test]
+    fn products_restored_after_their_parents() {
+        let batch = |id, parent| Batch {
+            id,
+            strain_id: 1,
+            harvest_date: None,
+            final_test_date: None,
+            package_date: None,
+            grower_id: 1,
+            thc_content: 20.0,
+            cbd_content: 0.1,
+            format: ProductFormat::default(),
+            parent_batch_id: parent,
+        };
+        // 4 is made from 9, which is made from 7; 2 names a batch outside the archive
+        let records = vec![
+            batch(4, Some(9)),
+            batch(2, Some(600)),
+            batch(9, Some(7)),
+            batch(7, None),
+        ];
+        let order: Vec<i32> = parents_first(&records).iter().map(|b| b.id).collect();
+        assert_eq!(order, vec![2, 7, 9, 4]);
+
+        let cycle = vec![batch(5, Some(6)), batch(6, Some(5)), batch(1, None)];
+        let order: Vec<i32> = parents_first(&cycle).iter().map(|b| b.id).collect();
+        assert_eq!(order, vec![1, 5, 6]);
+    }
+}
@@ -0,0 +1,173 @@
+use super::errors::ApiError;
+use super::models::{AuthToken, NewAuthToken, User};
+use super::DbPool;
+
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpRequest};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// How long a bearer token issued by `/auth/login` stays valid
+pub const TOKEN_TTL_HOURS: i64 = 24 * 7;
+
+/// Minimum length accepted for new passwords
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Secret used to sign bearer tokens. Read from `TOKEN_SECRET` at startup.
+#[derive(Clone)]
+pub struct TokenSecret(pub String);
+
+/// Claims carried by a bearer token. `jti` identifies the matching `auth_tokens` row,
+/// which is deleted on logout so the token stops being accepted before it expires.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Claims {
+    pub sub: i32,
+    pub jti: String,
+    pub exp: i64,
+}
+
+/// Hash `password` with argon2 and a random salt
+pub fn hash_password(password: &str) -> Result<String, argon2::Error> {
+    let salt: [u8; 16] = rand::thread_rng().gen();
+    argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())
+}
+
+/// Check `password` against an encoded argon2 hash
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    argon2::verify_encoded(hash, password.as_bytes()).unwrap_or(false)
+}
+
+/// Sign a new token for `user_id`. The returned `NewAuthToken` must be stored
+/// for the token to be accepted.
+pub fn issue_token(
+    secret: &TokenSecret,
+    user_id: i32,
+) -> Result<(String, NewAuthToken), jsonwebtoken::errors::Error> {
+    let jti: String = rand::thread_rng()
+        .gen::<[u8; 16]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let expires_at = Utc::now().naive_utc() + Duration::hours(TOKEN_TTL_HOURS);
+    let claims = Claims {
+        sub: user_id,
+        jti: jti.clone(),
+        exp: expires_at.timestamp(),
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.0.as_bytes()),
+    )?;
+
+    Ok((
+        token,
+        NewAuthToken {
+            user_id,
+            jti,
+            expires_at,
+        },
+    ))
+}
+
+/// Verify the signature and expiry of `token` and return its claims
+pub fn decode_token(
+    secret: &TokenSecret,
+    token: &str,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.0.as_bytes()),
+        &Validation::default(),
+    )
+    .map(|data| data.claims)
+}
+
+fn unauthorized(message: &str) -> ApiError {
+    ApiError::Unauthorized(message.to_owned())
+}
+
+/// The user making the request, taken from an `Authorization: Bearer <token>` header.
+/// Adding it as a handler argument makes the route reject anonymous requests with a 401.
+///
+/// Ex:
+///     `async fn post_new_strain(user: AuthUser, ...) -> impl Responder`
+pub struct AuthUser {
+    pub user: User,
+    pub jti: String,
+}
+
+impl FromRequest for AuthUser {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let pool = req.app_data::<web::Data<DbPool>>().cloned();
+        let secret = req.app_data::<web::Data<TokenSecret>>().cloned();
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .map(|t| t.trim().to_owned());
+
+        Box::pin(async move {
+            let (pool, secret) = match (pool, secret) {
+                (Some(p), Some(s)) => (p, s),
+                _ => {
+                    return Err(ApiError::Internal(
+                        "Authentication is not configured".to_owned(),
+                    ))
+                }
+            };
+            let token = token.ok_or_else(|| unauthorized("Missing bearer token"))?;
+            let claims = decode_token(&secret, &token)
+                .map_err(|_| unauthorized("Invalid or expired token"))?;
+            let conn = pool.get()?;
+            let jti = claims.jti.clone();
+
+            web::block(move || AuthToken::user(&conn, &claims.jti, claims.sub))
+                .await
+                .map(|user| AuthUser { user, jti })
+                .map_err(|e| match ApiError::from(e) {
+                    ApiError::NotFound(_) => unauthorized("Token has been revoked"),
+                    e => e,
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_verified() {
+        let hash = hash_password("hunter22").unwrap();
+        assert_ne!(hash, "hunter22");
+        assert!(verify_password(&hash, "hunter22"));
+        assert!(!verify_password(&hash, "hunter23"));
+    }
+
+    #[test]
+    fn token_round_trips() {
+        let secret = TokenSecret("test secret".to_owned());
+        let (token, row) = issue_token(&secret, 42).unwrap();
+        let claims = decode_token(&secret, &token).unwrap();
+        assert_eq!(claims.sub, 42);
+        assert_eq!(claims.jti, row.jti);
+    }
+
+    #[test]
+    fn token_with_wrong_secret_rejected() {
+        let (token, _) = issue_token(&TokenSecret("one".to_owned()), 42).unwrap();
+        assert!(decode_token(&TokenSecret("two".to_owned()), &token).is_err());
+    }
+}
@@ -1,35 +1,210 @@
 use super::models::*;
+use super::schema::auth_tokens::dsl::{auth_tokens, expires_at, jti, user_id};
 use super::schema::batches::dsl::{
-    batches, cbd_content, final_test_date, grower_id, harvest_date, id as bid, package_date,
-    strain_id, thc_content,
+    batches, cbd_content, final_test_date, format as batch_format, grower_id, harvest_date,
+    id as bid, package_date, parent_batch_id, strain_id, thc_content,
+};
+use super::schema::cannabinoids::dsl::{
+    batch_id as cannabinoids_batch, cannabinoids, id as cannabinoids_id,
+};
+use super::schema::dispensaries::dsl::dispensaries;
+use super::schema::grower_aliases::dsl::{
+    alias as grower_alias, grower_aliases, grower_id as aliased_grower,
+};
+use super::schema::growers::dsl::{
+    active, city, cultivation_type, growers, id as gid, license_number, name as grower_name, state,
+    website,
+};
+use super::schema::inventory::dsl::{
+    batch_id as inventory_batch, id as inventory_id, inventory, purchase_date as purchased_on,
+    quantity as stock_quantity, user_id as inventory_user,
+};
+use super::schema::listings::dsl::listings;
+use super::schema::price_observations::dsl::price_observations;
+use super::schema::reviews::dsl::{
+    batch_id as review_batch, id as review_id, reviews, user_id as review_user,
+};
+use super::schema::session_draws::dsl::{session_draws, session_id as drawing_session};
+use super::schema::sessions::dsl::{
+    batch_id as session_batch, consumed_at, id as session_id, sessions, user_id as session_user,
+};
+use super::schema::strain_aliases::dsl::{
+    alias as strain_alias, strain_aliases, strain_id as aliased_strain,
+};
+use super::schema::strain_parents::dsl::{parent_id, strain_id as child_id, strain_parents};
+use super::schema::strains::dsl::{
+    breeder, id as sid, indica_percent, name, phenotype, sativa_percent, species, strains,
+};
+use super::schema::terpene_compounds::dsl::{name as compound_name, terpene_compounds};
+use super::schema::terpene_measurements::dsl::{
+    amount as measured_amount, terpene_measurements, terpenes_id,
 };
-use super::schema::growers::dsl::{growers, id as gid, name as grower_name};
-use super::schema::strains::dsl::{id as sid, name, species, strains};
 use super::schema::terpenes::dsl::*;
+use super::schema::users::dsl::{id as uid, username, users};
+use super::validation::{validate_each, Validate, ValidationErrors};
 
-use chrono::NaiveDate;
+use chrono::{NaiveDate, Utc};
 use diesel::expression::sql_literal::sql;
+use diesel::pg::upsert::excluded;
 use diesel::pg::PgConnection;
+use diesel::query_builder::SqlQuery;
+use diesel::query_dsl::LoadQuery;
 use diesel::result::Error;
-use diesel::sql_types::{Date, Integer, VarChar, Varchar};
+use diesel::sql_types::{BigInt, Bool, Date, Float4, Integer, Nullable, VarChar};
 use diesel::{
-    sql_query, Connection, ConnectionError, ExpressionMethods, QueryDsl, Queryable, RunQueryDsl,
+    sql_query, Connection, ConnectionError, ExpressionMethods, OptionalExtension, QueryDsl,
+    Queryable, RunQueryDsl,
 };
 use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::env;
 
-#[derive(Copy, Debug, Clone)]
-pub enum BatchField<'b> {
-    Id(i32),
-    Strain(&'b str),
-    StrainID(i32),
-    HarvestDate(NaiveDate),
-    FinalTestDate(NaiveDate),
-    PackageDate(NaiveDate),
-    GrowerID(i32),
-    Grower(&'b str),
-    THCContent(f32),
-    CBDContent(f32),
+/// Criteria for searching batches. Every field that is set must match (they are ANDed);
+/// fields left as `None` are ignored. Date and potency bounds are inclusive.
+///
+/// Example:
+/// let filter = BatchFilter {
+///     strain_id: Some(15),
+///     grower_id: Some(3),
+///     thc_min: Some(20.0),
+///     ..Default::default()
+/// };
+/// let res = Batch::filter(&conn, filter).unwrap();
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BatchFilter {
+    pub strain_id: Option<i32>,
+    pub strain: Option<String>,
+    pub grower_id: Option<i32>,
+    pub grower: Option<String>,
+    pub species: Option<Species>,
+    pub harvest_date_min: Option<NaiveDate>,
+    pub harvest_date_max: Option<NaiveDate>,
+    pub final_test_date_min: Option<NaiveDate>,
+    pub final_test_date_max: Option<NaiveDate>,
+    pub package_date_min: Option<NaiveDate>,
+    pub package_date_max: Option<NaiveDate>,
+    pub thc_min: Option<f32>,
+    pub thc_max: Option<f32>,
+    pub cbd_min: Option<f32>,
+    pub cbd_max: Option<f32>,
+    pub format: Option<ProductFormat>,
+    pub parent_batch_id: Option<i32>,
+}
+
+/// Criteria for searching strains, ANDed like `BatchFilter`. Ratio bounds are inclusive
+/// and skip strains without a recorded ratio; `chemotype` is derived from the strain's
+/// batches, so strains without batches never match it.
+///
+/// Example:
+/// let filter = StrainFilter {
+///     indica_min: Some(60),
+///     chemotype: Some(Chemotype::TypeI),
+///     ..Default::default()
+/// };
+/// let res = Strain::page(&conn, filter, &PageQuery::default()).unwrap();
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StrainFilter {
+    pub name: Option<String>,
+    pub species: Option<Species>,
+    pub indica_min: Option<i32>,
+    pub indica_max: Option<i32>,
+    pub sativa_min: Option<i32>,
+    pub sativa_max: Option<i32>,
+    pub chemotype: Option<Chemotype>,
+}
+
+/// What the grower leaderboard ranks by. Lower THC variance and package lag rank higher;
+/// for every other metric more is better.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreMetric {
+    #[default]
+    Consistency,
+    Batches,
+    Strains,
+    PackageLag,
+    Profiled,
+    CompleteDates,
+}
+
+impl ScoreMetric {
+    /// `ORDER BY` expression over the columns of `GrowerScore`
+    fn order(&self) -> &'static str {
+        match self {
+            ScoreMetric::Consistency => "mean_thc_variance ASC",
+            ScoreMetric::Batches => "batch_count DESC",
+            ScoreMetric::Strains => "strain_count DESC",
+            ScoreMetric::PackageLag => "package_lag_days ASC",
+            ScoreMetric::Profiled => "profiled_share DESC",
+            ScoreMetric::CompleteDates => "complete_dates_share DESC",
+        }
+    }
+}
+
+/// Criteria for searching growers, ANDed like `BatchFilter`. `state` is matched without
+/// regard to case.
+///
+/// Example:
+/// let filter = GrowerFilter {
+///     state: Some("CA".to_owned()),
+///     cultivation_type: Some(CultivationType::LivingSoil),
+///     ..Default::default()
+/// };
+/// let res = Grower::page(&conn, filter, &PageQuery::default()).unwrap();
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GrowerFilter {
+    pub name: Option<String>,
+    pub state: Option<String>,
+    pub cultivation_type: Option<CultivationType>,
+    pub active: Option<bool>,
+}
+
+/// Criteria for searching dispensaries. `name` is matched like a strain or grower name.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DispensaryFilter {
+    pub name: Option<String>,
+}
+
+/// Criteria for searching listings, ANDed like `BatchFilter`
+///
+/// Example:
+/// let filter = ListingFilter {
+///     strain_id: Some(3),
+///     in_stock: Some(true),
+///     ..Default::default()
+/// };
+/// let res = Listing::page(&conn, filter, &PageQuery::default()).unwrap();
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ListingFilter {
+    pub dispensary_id: Option<i32>,
+    pub batch_id: Option<i32>,
+    pub strain_id: Option<i32>,
+    pub format: Option<ProductFormat>,
+    pub in_stock: Option<bool>,
+}
+
+/// Observation dates of a batch's price history, both inclusive
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PriceHistoryFilter {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// Which in-stock listings to rank by value, ANDed like `ListingFilter`
+///
+/// Example:
+/// let filter = ValueFilter {
+///     species: Some(Species::Indica),
+///     ..Default::default()
+/// };
+/// let best = Listing::by_value(&conn, filter, 10).unwrap();
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ValueFilter {
+    pub species: Option<Species>,
+    pub strain_id: Option<i32>,
+    pub format: Option<ProductFormat>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -38,6 +213,49 @@ pub enum GrowerField<I = i32, N = String> {
     Name(N),
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum TerpenesField {
+    Id(i32),
+    BatchID(i32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CannabinoidsField {
+    Id(i32),
+    BatchID(i32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum InventoryField {
+    Id(i32),
+    UserID(i32),
+    /// A user's items that are at or below their low-stock threshold
+    LowStock(i32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReviewField {
+    Id(i32),
+    UserID(i32),
+    BatchID(i32),
+    /// Reviews of every batch of a strain
+    StrainID(i32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SessionField {
+    Id(i32),
+    UserID(i32),
+    /// Sessions of one user (first) for one batch (second)
+    UserBatch(i32, i32),
+}
+
+#[derive(Debug, Clone)]
+pub enum UserField {
+    Id(i32),
+    Username(String),
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum StrainField<I = i32, N = String, S = Species> {
     Id(I),
@@ -45,6 +263,162 @@ pub enum StrainField<I = i32, N = String, S = Species> {
     Species(S),
 }
 
+/// What a bulk insert does with a record whose name is already taken: fail the whole
+/// request, leave the existing record alone, or overwrite it
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    #[default]
+    Error,
+    Skip,
+    Update,
+}
+
+/// Number of records returned by list endpoints when no `limit` is given
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// Upper bound on `limit` so a single request can't pull the whole catalog
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// Pagination and sorting options shared by every list endpoint.
+/// `sort` takes the form `field:asc` or `field:desc`; the order defaults to `asc`.
+///
+/// Ex: `/strains?species=Indica&limit=20&offset=40&sort=name:desc`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+    pub sort: Option<String>,
+}
+
+/// A field that list endpoints can be sorted on, named as in `sort`. Each listing
+/// accepts only the fields it knows a column for.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Id,
+    Name,
+    Species,
+    Breeder,
+    Phenotype,
+    IndicaPercent,
+    SativaPercent,
+    LicenseNumber,
+    State,
+    City,
+    CultivationType,
+    Active,
+    Address,
+    StrainId,
+    Strain,
+    GrowerId,
+    Grower,
+    BatchId,
+    DispensaryId,
+    HarvestDate,
+    FinalTestDate,
+    PackageDate,
+    ThcContent,
+    CbdContent,
+    Format,
+    ReviewCount,
+    Rating,
+    CreatedAt,
+    ConsumedAt,
+    Method,
+    Amount,
+    Quantity,
+    Unit,
+    PurchasePriceCents,
+    PurchaseDate,
+    Location,
+    WeightGrams,
+    PriceCents,
+    InStock,
+    ObservedAt,
+}
+
+/// One page of records along with the total number of records that matched
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+impl PageQuery {
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    /// The field and direction named in `self.sort`, `None` when unsorted.
+    /// An unknown field or order is reported as `Error::QueryBuilderError`.
+    fn order(&self) -> Result<Option<(SortField, bool)>, Error> {
+        let sort = match &self.sort {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        let (field, order) = sort.split_once(':').unwrap_or((sort, "asc"));
+        let desc = match order.to_ascii_lowercase().as_str() {
+            "asc" => false,
+            "desc" => true,
+            _ => return Err(invalid_sort(sort)),
+        };
+        let field = serde_json::from_value(Value::String(field.to_owned()))
+            .map_err(|_| invalid_sort(sort))?;
+        Ok(Some((field, desc)))
+    }
+
+    /// `ORDER BY` expression for `self.sort`, given the column behind each field that can
+    /// be sorted on. `default` always comes last so that ties keep a stable order.
+    fn order_by(&self, sortable: &[(SortField, &str)], default: &str) -> Result<String, Error> {
+        let (field, desc) = match self.order()? {
+            Some(order) => order,
+            None => return Ok(default.to_owned()),
+        };
+        let column = sortable
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, c)| c)
+            .ok_or_else(|| invalid_sort(self.sort.as_deref().unwrap_or_default()))?;
+        let direction = if desc {
+            "DESC NULLS LAST"
+        } else {
+            "ASC NULLS FIRST"
+        };
+        Ok(format!("{} {}, {}", column, direction, default))
+    }
+}
+
+impl<T> Page<T> {
+    /// Convert each record on this page, keeping the paging metadata
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> Page<U> {
+        Page {
+            data: self.data.into_iter().map(f).collect(),
+            total: self.total,
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.offset + self.limit < self.total
+    }
+
+    pub fn has_prev(&self) -> bool {
+        self.offset > 0
+    }
+}
+
+fn invalid_sort(sort: &str) -> Error {
+    Error::QueryBuilderError(format!("cannot sort by `{}`", sort).into())
+}
+
 /// A utility function to establish DB connection
 /// Example:
 /// let conn = establish_connection();
@@ -74,6 +448,52 @@ where
     fn create(&self, conn: &C) -> Result<Self::Output, E>;
 }
 
+/// Trait for creating many objects with one INSERT. Every record is checked first and
+/// the broken ones are reported by index (`[3].name`); the rows are only written if all
+/// of them pass, inside a transaction, so either every record is created or none is.
+///
+/// Example:
+/// let conn = establish_connection().unwrap();
+/// let new = vec![
+///     NewGrower { name: "Cresco".to_owned(), ..Default::default() },
+///     NewGrower { name: "Verano".to_owned(), ..Default::default() },
+/// ];
+/// let created = NewGrower::create_all(&conn, &new, OnConflict::Skip).unwrap();
+/// assert!(created.iter().all(|g| g.name != "Cresco")); // already existed
+pub trait BulkCreatable<C = PgConnection, E = Error>
+where
+    C: Connection,
+    Self: Sized,
+{
+    type Output;
+
+    fn create_all(
+        conn: &C,
+        records: &[Self],
+        on_conflict: OnConflict,
+    ) -> Result<Vec<Self::Output>, E>;
+}
+
+/// Trait for updating existing objects. Implemented by changeset structs: the `New*`
+/// structs replace every column while the `*Changes` structs only touch the fields that are set.
+///
+/// Example:
+/// let conn = establish_connection().unwrap();
+/// let changes = StrainChanges {
+///     species: Some(Species::Hybrid),
+///     ..Default::default()
+/// };
+/// let strain = changes.update(&conn, 3).unwrap();
+/// assert_eq!(strain.species, Species::Hybrid);
+pub trait Updatable<C = PgConnection, E = Error>
+where
+    C: Connection,
+{
+    type Output;
+
+    fn update(&self, conn: &C, id: i32) -> Result<Self::Output, E>;
+}
+
 /// Trait for deleting objects
 pub trait Deletable<C = PgConnection, E = Error> {
     type Output;
@@ -121,16 +541,234 @@ pub trait Retrievable<'a, Output = Self, C = PgConnection, E = Error> {
     fn filter(conn: &C, field: Self::Field) -> Result<Vec<Output>, Error>;
 }
 
+/// Trait for list endpoints: one sorted page of the records matching a filter
+pub trait Pageable<Output = Self, C = PgConnection, E = Error> {
+    type Filter;
+
+    /// Fields the records can be sorted on, each with the column it sorts by
+    const SORTABLE: &'static [(SortField, &'static str)];
+
+    /// Order of the records when no sort is given, which also breaks ties
+    const DEFAULT_ORDER: &'static str;
+
+    /// Retrieve the page of records matching `filter` that `query` asks for, sorted and cut
+    /// by the database. An unknown sort field or order is reported as
+    /// `Error::QueryBuilderError`.
+    ///
+    /// Example:
+    /// let conn = establish_connection().unwrap();
+    /// let query = PageQuery {
+    ///     limit: Some(2),
+    ///     sort: Some("name:desc".to_owned()),
+    ///     ..Default::default()
+    /// };
+    /// let page = Strain::page(&conn, StrainFilter::default(), &query).unwrap();
+    /// assert_eq!(page.data.len(), 2);
+    fn page(conn: &C, filter: Self::Filter, query: &PageQuery) -> Result<Page<Output>, E>;
+}
+
+/// Number of rows a listing's `SELECT` matches
+#[derive(QueryableByName)]
+struct Total {
+    #[sql_type = "BigInt"]
+    total: i64,
+}
+
+/// Count the rows of `select` and load the window of them `query` asks for, sorted on one
+/// of the `sortable` columns. `bind` supplies the parameters of `select` to both statements.
+fn load_page<T, Q, B>(
+    conn: &PgConnection,
+    query: &PageQuery,
+    sortable: &[(SortField, &str)],
+    default_order: &str,
+    select: &str,
+    bind: B,
+) -> Result<Page<T>, Error>
+where
+    B: Fn(SqlQuery) -> Q,
+    Q: RunQueryDsl<PgConnection> + LoadQuery<PgConnection, T> + LoadQuery<PgConnection, Total>,
+{
+    let order = query.order_by(sortable, default_order)?;
+    let (limit, offset) = (query.limit(), query.offset());
+    let total = bind(sql_query(format!(
+        "SELECT COUNT(*) AS total FROM ({}) matched",
+        select
+    )))
+    .get_result::<Total>(conn)?
+    .total;
+    let data = bind(sql_query(format!(
+        "SELECT * FROM ({}) matched ORDER BY {} LIMIT {} OFFSET {}",
+        select, order, limit, offset
+    )))
+    .load::<T>(conn)?;
+    Ok(Page {
+        data,
+        total: total as usize,
+        limit,
+        offset,
+    })
+}
+
 impl Creatable for NewTerpenes {
     type Output = Terpenes;
     fn create(&self, conn: &PgConnection) -> Result<Terpenes, Error> {
-        diesel::insert_into(terpenes).values(self).get_result(conn)
+        self.validate()?;
+        conn.transaction(|| {
+            let profile = diesel::insert_into(terpenes)
+                .values(batch_id.eq(self.batch_id))
+                .returning(id)
+                .get_result(conn)?;
+            self.measure(conn, profile)
+        })
+    }
+}
+
+impl NewTerpenes {
+    /// Overwrite the terpene profile belonging to `self.batch_id`.
+    /// Compounds missing from `self` are cleared rather than left untouched.
+    pub fn replace(&self, conn: &PgConnection) -> Result<Terpenes, Error> {
+        self.validate()?;
+        conn.transaction(|| {
+            let profile = terpenes
+                .filter(batch_id.eq(self.batch_id))
+                .select(id)
+                .first(conn)?;
+            diesel::delete(terpene_measurements.filter(terpenes_id.eq(profile))).execute(conn)?;
+            self.measure(conn, profile)
+        })
+    }
+
+    /// Store one measurement per compound under profile `profile`. Compound names are
+    /// matched like the catalog stores them, trimmed and lowercased. Compounds that aren't
+    /// in the terpene catalog, or that are given twice, are reported as validation errors,
+    /// as is a total beyond what the batch's format plausibly holds.
+    fn measure(&self, conn: &PgConnection, profile: i32) -> Result<Terpenes, Error> {
+        let mut errors = ValidationErrors::default();
+        let format = batches
+            .find(self.batch_id)
+            .select(batch_format)
+            .first::<ProductFormat>(conn)?;
+        errors.terpene_total("terpenes", format, self.compounds.values().sum());
+        let mut compounds = BTreeMap::new();
+        for (compound, amt) in &self.compounds {
+            if compounds
+                .insert(compound.trim().to_lowercase(), *amt)
+                .is_some()
+            {
+                errors.add(compound, "names a terpene that is given more than once");
+            }
+        }
+        let catalog: Vec<TerpeneCompound> = terpene_compounds
+            .filter(compound_name.eq_any(compounds.keys()))
+            .load(conn)?;
+        let mut rows = Vec::with_capacity(compounds.len());
+        for (compound, amt) in &compounds {
+            match catalog.iter().find(|c| &c.name == compound) {
+                Some(c) => rows.push(NewTerpeneMeasurement {
+                    terpenes_id: profile,
+                    compound_id: c.id,
+                    amount: *amt,
+                }),
+                None => errors.add(compound, "is not a known terpene"),
+            }
+        }
+        errors.into_result()?;
+        if !rows.is_empty() {
+            diesel::insert_into(terpene_measurements)
+                .values(&rows)
+                .execute(conn)?;
+        }
+
+        Ok(Terpenes {
+            id: profile,
+            batch_id: self.batch_id,
+            compounds,
+        })
+    }
+}
+
+impl Terpenes {
+    /// Attach the measurements of each `(id, batch_id)` profile
+    fn measured(conn: &PgConnection, profiles: Vec<(i32, i32)>) -> Result<Vec<Terpenes>, Error> {
+        let ids: Vec<i32> = profiles.iter().map(|p| p.0).collect();
+        let measurements: Vec<(i32, String, f32)> = terpene_measurements
+            .inner_join(terpene_compounds)
+            .filter(terpenes_id.eq_any(ids))
+            .select((terpenes_id, compound_name, measured_amount))
+            .load(conn)?;
+
+        Ok(profiles
+            .into_iter()
+            .map(|(tid, batch)| Terpenes {
+                id: tid,
+                batch_id: batch,
+                compounds: measurements
+                    .iter()
+                    .filter(|m| m.0 == tid)
+                    .map(|m| (m.1.clone(), m.2))
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+impl Creatable for NewTerpeneCompound {
+    type Output = TerpeneCompound;
+    fn create(&self, conn: &PgConnection) -> Result<TerpeneCompound, Error> {
+        self.validate()?;
+        diesel::insert_into(terpene_compounds)
+            .values(compound_name.eq(self.name.trim().to_lowercase()))
+            .get_result(conn)
+    }
+}
+
+impl TerpeneCompound {
+    /// Every compound a terpene profile may measure, by name
+    pub fn catalog(conn: &PgConnection) -> Result<Vec<TerpeneCompound>, Error> {
+        terpene_compounds.order(compound_name).load(conn)
+    }
+}
+
+impl Creatable for NewCannabinoids {
+    type Output = Cannabinoids;
+    fn create(&self, conn: &PgConnection) -> Result<Cannabinoids, Error> {
+        self.validate()?;
+        diesel::insert_into(cannabinoids)
+            .values(self)
+            .get_result(conn)
+    }
+}
+
+impl NewCannabinoids {
+    /// Overwrite the cannabinoid panel belonging to `self.batch_id`.
+    /// Columns missing from `self` are cleared rather than left untouched.
+    pub fn replace(&self, conn: &PgConnection) -> Result<Cannabinoids, Error> {
+        self.validate()?;
+        diesel::update(cannabinoids.filter(cannabinoids_batch.eq(self.batch_id)))
+            .set(self)
+            .get_result(conn)
+    }
+}
+
+impl Cannabinoids {
+    /// Combine this panel with its batch's THC and CBD
+    pub fn panel(self, conn: &PgConnection) -> Result<CannabinoidPanel, Error> {
+        let batch = batches.find(self.batch_id).first::<Batch>(conn)?;
+        Ok(CannabinoidPanel::new(&batch, self))
+    }
+}
+
+impl Deletable for Cannabinoids {
+    type Output = Cannabinoids;
+    fn delete(&self, conn: &PgConnection) -> Result<Cannabinoids, Error> {
+        diesel::delete(cannabinoids.find(&self.id)).get_result(conn)
     }
 }
 
 impl Creatable for NewGrower {
     type Output = Grower;
     fn create(&self, conn: &PgConnection) -> Result<Grower, Error> {
+        self.validate()?;
         diesel::insert_into(growers).values(self).get_result(conn)
     }
 }
@@ -138,300 +776,3862 @@ impl Creatable for NewGrower {
 impl Creatable for NewBatch {
     type Output = Batch;
     fn create(&self, conn: &PgConnection) -> Result<Batch, Error> {
+        self.validate()?;
+        self.derivation(conn, None)?.into_result()?;
         diesel::insert_into(batches).values(self).get_result(conn)
     }
 }
 
+impl NewBatch {
+    /// Rules that need the stored batches: a derived product is made from a batch of
+    /// flower of the same strain, and the `existing` batch being replaced, if products
+    /// are made from it, must stay flower
+    fn derivation(
+        &self,
+        conn: &PgConnection,
+        existing: Option<i32>,
+    ) -> Result<ValidationErrors, Error> {
+        let mut errors = ValidationErrors::default();
+        if let Some(pid) = self.parent_batch_id {
+            match batches.find(pid).first::<Batch>(conn).optional()? {
+                None => errors.add("parent_batch_id", "must be set to an existing id"),
+                Some(p) if Some(p.id) == existing => {
+                    errors.add("parent_batch_id", "must not be the batch itself")
+                }
+                Some(p) => {
+                    if p.format != ProductFormat::Flower {
+                        errors.add("parent_batch_id", "must be a batch of flower");
+                    }
+                    if p.strain_id != self.strain_id {
+                        errors.add("strain_id", "must be the parent batch's strain");
+                    }
+                    if self.format == ProductFormat::Flower {
+                        errors.add("format", "must not be Flower for a derived product");
+                    }
+                }
+            }
+        }
+        if let Some(existing) = existing {
+            let derived: i64 = batches
+                .filter(parent_batch_id.eq(existing))
+                .count()
+                .get_result(conn)?;
+            if derived > 0 && self.format != ProductFormat::Flower {
+                errors.add(
+                    "format",
+                    "must stay Flower while products are made from the batch",
+                );
+            }
+        }
+        Ok(errors)
+    }
+}
+
 impl Creatable for NewStrain {
     type Output = Strain;
     fn create(&self, conn: &PgConnection) -> Result<Strain, Error> {
+        self.validate()?;
         diesel::insert_into(strains).values(self).get_result(conn)
     }
 }
 
-impl Deletable for Grower {
-    type Output = Grower;
-    fn delete(&self, conn: &PgConnection) -> Result<Grower, Error> {
-        diesel::delete(growers.find(&self.id)).get_result(conn)
+/// Report names that appear twice in one bulk request, and, unless conflicts are skipped
+/// or updated, names that are already `taken`
+fn check_names(
+    errors: &mut ValidationErrors,
+    names: &[&str],
+    taken: &[String],
+    on_conflict: OnConflict,
+) {
+    for (i, n) in names.iter().enumerate() {
+        if on_conflict != OnConflict::Skip && names[..i].contains(n) {
+            errors.add(
+                &format!("[{}].name", i),
+                "appears more than once in the request",
+            );
+        } else if on_conflict == OnConflict::Error && taken.iter().any(|t| t == n) {
+            errors.add(&format!("[{}].name", i), "is already taken");
+        }
     }
 }
 
-impl Deletable for Batch {
-    type Output = Batch;
-    fn delete(&self, conn: &PgConnection) -> Result<Batch, Error> {
-        diesel::delete(batches.find(&self.id)).get_result(conn)
+/// Report ids of a bulk request that don't refer to an existing record
+fn check_ids(errors: &mut ValidationErrors, field: &str, ids: &[i32], existing: &[i32]) {
+    for (i, v) in ids.iter().enumerate() {
+        if *v > 0 && !existing.contains(v) {
+            errors.add(&format!("[{}].{}", i, field), "does not exist");
+        }
     }
 }
 
-impl Deletable for Strain {
+/// `Skip` leaves existing strains as they are and only returns the new ones;
+/// `Update` overwrites every other field of existing strains and returns them as well
+impl BulkCreatable for NewStrain {
     type Output = Strain;
-    fn delete(&self, conn: &PgConnection) -> Result<Strain, Error> {
-        diesel::delete(strains.find(&self.id)).get_result(conn)
+    fn create_all(
+        conn: &PgConnection,
+        records: &[NewStrain],
+        on_conflict: OnConflict,
+    ) -> Result<Vec<Strain>, Error> {
+        conn.transaction(|| {
+            let mut errors = validate_each(records);
+            let names: Vec<&str> = records.iter().map(|s| s.name.as_str()).collect();
+            let taken = strains
+                .select(name)
+                .filter(name.eq_any(&names))
+                .load::<String>(conn)?;
+            check_names(&mut errors, &names, &taken, on_conflict);
+            errors.into_result()?;
+
+            let insert = diesel::insert_into(strains).values(records);
+            match on_conflict {
+                OnConflict::Error => insert.get_results(conn),
+                OnConflict::Skip => insert.on_conflict(name).do_nothing().get_results(conn),
+                OnConflict::Update => insert
+                    .on_conflict(name)
+                    .do_update()
+                    .set((
+                        species.eq(excluded(species)),
+                        breeder.eq(excluded(breeder)),
+                        phenotype.eq(excluded(phenotype)),
+                        indica_percent.eq(excluded(indica_percent)),
+                        sativa_percent.eq(excluded(sativa_percent)),
+                    ))
+                    .get_results(conn),
+            }
+        })
     }
 }
 
-impl<'b> Retrievable<'b, BatchResponse> for Batch {
-    type Field = BatchField<'b>;
-    fn all(conn: &PgConnection) -> Result<Vec<BatchResponse>, Error> {
-        sql_query(
-            "SELECT s.name as strain, b.harvest_date, b.final_test_date, b.package_date,
-             g.name as grower, b.thc_content, b.cbd_content FROM batches b INNER JOIN
-             strains s ON b.strain_id = s.id INNER JOIN growers g ON b.grower_id = g.id",
-        )
-        .get_results(conn)
+/// `Update` replaces the profile of a grower whose name is taken
+impl BulkCreatable for NewGrower {
+    type Output = Grower;
+    fn create_all(
+        conn: &PgConnection,
+        records: &[NewGrower],
+        on_conflict: OnConflict,
+    ) -> Result<Vec<Grower>, Error> {
+        conn.transaction(|| {
+            let mut errors = validate_each(records);
+            let names: Vec<&str> = records.iter().map(|g| g.name.as_str()).collect();
+            let taken = growers
+                .select(grower_name)
+                .filter(grower_name.eq_any(&names))
+                .load::<String>(conn)?;
+            check_names(&mut errors, &names, &taken, on_conflict);
+            errors.into_result()?;
+
+            let insert = diesel::insert_into(growers).values(records);
+            match on_conflict {
+                OnConflict::Error => insert.get_results(conn),
+                OnConflict::Skip => insert
+                    .on_conflict(grower_name)
+                    .do_nothing()
+                    .get_results(conn),
+                OnConflict::Update => insert
+                    .on_conflict(grower_name)
+                    .do_update()
+                    .set((
+                        license_number.eq(excluded(license_number)),
+                        state.eq(excluded(state)),
+                        city.eq(excluded(city)),
+                        website.eq(excluded(website)),
+                        cultivation_type.eq(excluded(cultivation_type)),
+                        active.eq(excluded(active)),
+                    ))
+                    .get_results(conn),
+            }
+        })
     }
+}
 
-    fn filter(conn: &PgConnection, field: BatchField) -> Result<Vec<BatchResponse>, Error> {
-        let stmt = "SELECT s.name as strain, b.harvest_date, b.final_test_date, b.package_date,
-             g.name as grower, b.thc_content, b.cbd_content FROM batches b INNER JOIN
-             strains s ON b.strain_id = s.id INNER JOIN growers g ON b.grower_id = g.id "
-            .to_owned();
+/// Batches have no unique columns, so `on_conflict` is ignored. Unknown strain and
+/// grower ids are reported by index instead of failing on the foreign key.
+impl BulkCreatable for NewBatch {
+    type Output = Batch;
+    fn create_all(
+        conn: &PgConnection,
+        records: &[NewBatch],
+        _on_conflict: OnConflict,
+    ) -> Result<Vec<Batch>, Error> {
+        conn.transaction(|| {
+            let mut errors = validate_each(records);
+            let strain_ids: Vec<i32> = records.iter().map(|b| b.strain_id).collect();
+            let grower_ids: Vec<i32> = records.iter().map(|b| b.grower_id).collect();
+            let known_strains = strains
+                .select(sid)
+                .filter(sid.eq_any(&strain_ids))
+                .load::<i32>(conn)?;
+            let known_growers = growers
+                .select(gid)
+                .filter(gid.eq_any(&grower_ids))
+                .load::<i32>(conn)?;
+            check_ids(&mut errors, "strain_id", &strain_ids, &known_strains);
+            check_ids(&mut errors, "grower_id", &grower_ids, &known_growers);
+            for (i, b) in records.iter().enumerate() {
+                errors.nest(&format!("[{}]", i), b.derivation(conn, None)?);
+            }
+            errors.into_result()?;
 
-        match field {
-            BatchField::StrainID(_sid) => sql_query(stmt + " WHERE b.strain_id = $1 ")
-                .bind::<Integer, _>(_sid)
-                .get_results(conn),
+            diesel::insert_into(batches)
+                .values(records)
+                .get_results(conn)
+        })
+    }
+}
 
-            BatchField::Strain(s) => sql_query(stmt + " WHERE s.name ILIKE $1 ")
-                .bind::<VarChar, _>(s)
-                .get_results(conn),
+/// A parent that descends from the strain would close a cycle, so it is refused. The
+/// links are locked against other writers while the lineage is checked, so two requests
+/// can't close a cycle between them.
+impl Creatable for StrainParent {
+    type Output = StrainParent;
+    fn create(&self, conn: &PgConnection) -> Result<StrainParent, Error> {
+        self.validate()?;
+        conn.transaction(|| {
+            sql_query("LOCK TABLE strain_parents IN SHARE ROW EXCLUSIVE MODE").execute(conn)?;
+            if Strain::descends_from(conn, self.parent_id, self.strain_id)? {
+                let mut errors = ValidationErrors::default();
+                errors.add("parent_id", "must not be a descendant of the strain");
+                return Err(errors.into());
+            }
+            diesel::insert_into(strain_parents)
+                .values(self)
+                .get_result(conn)
+        })
+    }
+}
 
-            BatchField::HarvestDate(h) => sql_query(stmt + "WHERE harvest_date = $1")
-                .bind::<Date, _>(h)
-                .get_results(conn),
+impl Creatable for NewStrainAlias {
+    type Output = StrainAlias;
+    fn create(&self, conn: &PgConnection) -> Result<StrainAlias, Error> {
+        self.validate()?;
+        diesel::insert_into(strain_aliases)
+            .values(self)
+            .get_result(conn)
+    }
+}
 
-            BatchField::FinalTestDate(h) => sql_query(stmt + "WHERE final_test_date = $1")
-                .bind::<Date, _>(h)
-                .get_results(conn),
+impl Creatable for NewGrowerAlias {
+    type Output = GrowerAlias;
+    fn create(&self, conn: &PgConnection) -> Result<GrowerAlias, Error> {
+        self.validate()?;
+        diesel::insert_into(grower_aliases)
+            .values(self)
+            .get_result(conn)
+    }
+}
 
-            BatchField::PackageDate(p) => sql_query(stmt + "WHERE package_date = $1")
-                .bind::<Date, _>(p)
-                .get_results(conn),
+impl Creatable for NewDispensary {
+    type Output = Dispensary;
+    fn create(&self, conn: &PgConnection) -> Result<Dispensary, Error> {
+        self.validate()?;
+        diesel::insert_into(dispensaries)
+            .values(self)
+            .get_result(conn)
+    }
+}
 
-            BatchField::GrowerID(g) => sql_query(stmt + "WHERE g.id = $1")
-                .bind::<Integer, _>(g)
-                .get_results(conn),
+impl Creatable for NewListing {
+    type Output = Listing;
+    fn create(&self, conn: &PgConnection) -> Result<Listing, Error> {
+        self.validate()?;
+        conn.transaction(|| {
+            let listing = diesel::insert_into(listings)
+                .values(self)
+                .get_result(conn)?;
+            NewPriceObservation::from(&listing).create(conn)?;
+            Ok(listing)
+        })
+    }
+}
 
-            BatchField::Grower(gr) => sql_query(stmt + r"WHERE g.name ILIKE $1 ")
-                .bind::<Varchar, _>(gr)
-                .get_results(conn),
+impl Creatable for NewPriceObservation {
+    type Output = PriceObservation;
+    fn create(&self, conn: &PgConnection) -> Result<PriceObservation, Error> {
+        self.validate()?;
+        diesel::insert_into(price_observations)
+            .values(self)
+            .get_result(conn)
+    }
+}
 
-            _ => Self::all(conn),
-        }
+impl Creatable for NewUser {
+    type Output = User;
+    fn create(&self, conn: &PgConnection) -> Result<User, Error> {
+        diesel::insert_into(users).values(self).get_result(conn)
     }
 }
 
-impl Retrievable<'_> for Grower {
-    type Field = GrowerField;
-    fn all(conn: &PgConnection) -> Result<Vec<Grower>, Error> {
-        growers.load(conn)
+impl Creatable for NewAuthToken {
+    type Output = AuthToken;
+    fn create(&self, conn: &PgConnection) -> Result<AuthToken, Error> {
+        diesel::insert_into(auth_tokens)
+            .values(self)
+            .get_result(conn)
     }
+}
 
-    fn filter(conn: &PgConnection, field: GrowerField) -> Result<Vec<Grower>, Error> {
-        match field {
-            GrowerField::Id(i) => growers.filter(gid.eq(i)).get_results(conn),
-            GrowerField::Name(n) => growers
-                .filter(sql("name ILIKE ").bind::<VarChar, _>(n))
-                .get_results(conn),
+impl AuthToken {
+    /// Look up the owner of the unexpired token `_jti` issued to `_uid`.
+    /// Fails with `Error::NotFound` if the token was logged out or has expired.
+    pub fn user(conn: &PgConnection, _jti: &str, _uid: i32) -> Result<User, Error> {
+        auth_tokens
+            .inner_join(users)
+            .filter(jti.eq(_jti))
+            .filter(user_id.eq(_uid))
+            .filter(expires_at.gt(diesel::dsl::now))
+            .select(super::schema::users::all_columns)
+            .first(conn)
+    }
+
+    /// Revoke the token `_jti`
+    pub fn revoke(conn: &PgConnection, _jti: &str) -> Result<AuthToken, Error> {
+        diesel::delete(auth_tokens.filter(jti.eq(_jti))).get_result(conn)
+    }
+}
+
+/// Take what `session` used out of its user's stock of its batch, spread over their
+/// purchases oldest first (undated ones last), and record each draw in `session_draws` so
+/// that it can be given back exactly. Weighed items give up the session's `amount`; items
+/// counted in units give up one unit per session. Whatever stock can't cover is not taken.
+fn draw_inventory(conn: &PgConnection, session: &Session) -> Result<(), Error> {
+    let stock: Vec<Inventory> = inventory
+        .filter(inventory_user.eq(session.user_id))
+        .filter(inventory_batch.eq(session.batch_id))
+        .filter(stock_quantity.gt(0.0))
+        .order((purchased_on.asc(), inventory_id.asc()))
+        .for_update()
+        .load(conn)?;
+    let (mut grams, mut units) = (session.amount.unwrap_or(0.0), 1.0);
+    let mut draws = vec![];
+    for item in stock {
+        let (wanted, per_unit) = match item.unit {
+            InventoryUnit::Grams => (&mut grams, 1.0),
+            InventoryUnit::Eighths => (&mut grams, GRAMS_PER_EIGHTH),
+            InventoryUnit::Units => (&mut units, 1.0),
+        };
+        if *wanted <= 0.0 {
+            continue;
         }
+        let taken = (*wanted / per_unit).min(item.quantity);
+        // An item that covers the rest leaves nothing wanted, without rounding residue
+        *wanted = match taken < item.quantity {
+            true => 0.0,
+            false => *wanted - taken * per_unit,
+        };
+        diesel::update(inventory.find(item.id))
+            .set(stock_quantity.eq(item.quantity - taken))
+            .execute(conn)?;
+        draws.push(NewSessionDraw {
+            session_id: session.id,
+            inventory_id: item.id,
+            quantity: taken,
+        });
     }
+    diesel::insert_into(session_draws)
+        .values(&draws)
+        .execute(conn)?;
+    Ok(())
 }
 
-impl Retrievable<'_> for Strain {
-    type Field = StrainField;
-    fn all(conn: &PgConnection) -> Result<Vec<Strain>, Error> {
-        strains.load(conn)
+/// Add back to each inventory item what session `$1` drew from it
+const RESTOCK_INVENTORY_QUERY: &str = "UPDATE inventory i SET quantity = i.quantity + d.quantity
+     FROM session_draws d WHERE d.session_id = $1 AND d.inventory_id = i.id";
+
+/// Give back exactly what session `_id` drew, and forget the draws
+fn restock_inventory(conn: &PgConnection, _id: i32) -> Result<(), Error> {
+    sql_query(RESTOCK_INVENTORY_QUERY)
+        .bind::<Integer, _>(_id)
+        .execute(conn)?;
+    diesel::delete(session_draws.filter(drawing_session.eq(_id))).execute(conn)?;
+    Ok(())
+}
+
+/// Report `field` as invalid unless batch `_id` exists
+fn existing_batch(conn: &PgConnection, field: &str, _id: i32) -> Result<(), Error> {
+    let found: i64 = batches.find(_id).count().get_result(conn)?;
+    let mut errors = ValidationErrors::default();
+    if found == 0 {
+        errors.add(field, "must be set to an existing id");
     }
+    Ok(errors.into_result()?)
+}
 
-    fn filter(conn: &PgConnection, field: StrainField) -> Result<Vec<Strain>, Error> {
-        match field {
-            StrainField::Id(i) => strains.filter(sid.eq(i)).get_results(conn),
-            StrainField::Name(n) => strains
-                .filter(sql("name ILIKE  ").bind::<VarChar, _>(n))
-                .get_results(conn),
-            StrainField::Species(s) => strains.filter(species.eq(s)).get_results(conn),
-        }
+/// Logging a session also decrements the user's inventory of the batch
+impl Creatable for NewSession {
+    type Output = Session;
+    fn create(&self, conn: &PgConnection) -> Result<Session, Error> {
+        self.validate()?;
+        existing_batch(conn, "batch_id", self.batch_id)?;
+        conn.transaction(|| {
+            let session: Session = diesel::insert_into(sessions)
+                .values(self)
+                .get_result(conn)?;
+            draw_inventory(conn, &session)?;
+            Ok(session)
+        })
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::models::{NewStrain, Species, Strain};
+impl Creatable for NewInventory {
+    type Output = Inventory;
+    fn create(&self, conn: &PgConnection) -> Result<Inventory, Error> {
+        self.validate()?;
+        diesel::insert_into(inventory).values(self).get_result(conn)
+    }
+}
 
-    #[test]
-    fn connection_established() {
-        let conn = establish_connection();
-        assert!(conn.is_ok());
+impl Updatable for InventoryChanges {
+    type Output = Inventory;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Inventory, Error> {
+        self.validate()?;
+        diesel::update(inventory.find(_id))
+            .set(self)
+            .get_result(conn)
     }
+}
 
-    #[test]
-    fn new_strain_created() {
+impl Deletable for Inventory {
+    type Output = Inventory;
+    fn delete(&self, conn: &PgConnection) -> Result<Inventory, Error> {
+        diesel::delete(inventory.find(&self.id)).get_result(conn)
+    }
+}
+
+/// Per-strain totals of one user's inventory
+const INVENTORY_SUMMARY_QUERY: &str = "SELECT s.id as strain_id, s.name as strain,
+     COUNT(i.id) as items,
+     COALESCE(SUM(CASE i.unit WHEN 'grams' THEN i.quantity
+                              WHEN 'eighths' THEN i.quantity * $2 END), 0)::float8 as grams,
+     COALESCE(SUM(i.quantity) FILTER (WHERE i.unit = 'units'), 0)::float8 as units,
+     COALESCE(SUM(i.purchase_price_cents), 0)::int8 as spent_cents
+     FROM inventory i INNER JOIN batches b ON i.batch_id = b.id
+     INNER JOIN strains s ON b.strain_id = s.id
+     WHERE i.user_id = $1 GROUP BY s.id, s.name ORDER BY s.name";
+
+impl Inventory {
+    /// Retrieve inventory item `_id` only if it belongs to user `_uid`
+    pub fn find_owned(conn: &PgConnection, _id: i32, _uid: i32) -> Result<Inventory, Error> {
+        inventory
+            .find(_id)
+            .filter(inventory_user.eq(_uid))
+            .first(conn)
+    }
+}
+
+impl InventorySummary {
+    /// Totals of user `_uid`'s inventory grouped by strain
+    pub fn for_user(conn: &PgConnection, _uid: i32) -> Result<Vec<InventorySummary>, Error> {
+        sql_query(INVENTORY_SUMMARY_QUERY)
+            .bind::<Integer, _>(_uid)
+            .bind::<Float4, _>(GRAMS_PER_EIGHTH)
+            .get_results(conn)
+    }
+}
+
+/// The user's inventory follows the edit: when the amount or the batch changes, what the
+/// session drew is given back and the edited session draws again
+impl Updatable for SessionChanges {
+    type Output = Session;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Session, Error> {
+        self.validate()?;
+        if let Some(b) = self.batch_id {
+            existing_batch(conn, "batch_id", b)?;
+        }
+        conn.transaction(|| {
+            let old: Session = sessions.find(_id).first(conn)?;
+            let new: Session = diesel::update(sessions.find(_id))
+                .set(self)
+                .get_result(conn)?;
+            if old.batch_id != new.batch_id || old.amount != new.amount {
+                restock_inventory(conn, new.id)?;
+                draw_inventory(conn, &new)?;
+            }
+            Ok(new)
+        })
+    }
+}
+
+/// Deleting a session gives what it drew back to the user's inventory
+impl Deletable for Session {
+    type Output = Session;
+    fn delete(&self, conn: &PgConnection) -> Result<Session, Error> {
+        conn.transaction(|| {
+            restock_inventory(conn, self.id)?;
+            diesel::delete(sessions.find(&self.id)).get_result(conn)
+        })
+    }
+}
+
+impl Creatable for NewReview {
+    type Output = Review;
+    fn create(&self, conn: &PgConnection) -> Result<Review, Error> {
+        self.validate()?;
+        diesel::insert_into(reviews).values(self).get_result(conn)
+    }
+}
+
+impl Updatable for ReviewChanges {
+    type Output = Review;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Review, Error> {
+        self.validate()?;
+        diesel::update(reviews.find(_id)).set(self).get_result(conn)
+    }
+}
+
+impl Deletable for Review {
+    type Output = Review;
+    fn delete(&self, conn: &PgConnection) -> Result<Review, Error> {
+        diesel::delete(reviews.find(&self.id)).get_result(conn)
+    }
+}
+
+impl Review {
+    /// Retrieve review `_id` only if it was written by user `_uid`
+    pub fn find_owned(conn: &PgConnection, _id: i32, _uid: i32) -> Result<Review, Error> {
+        reviews.find(_id).filter(review_user.eq(_uid)).first(conn)
+    }
+}
+
+impl Session {
+    /// Retrieve session `_id` only if it was logged by user `_uid`
+    pub fn find_owned(conn: &PgConnection, _id: i32, _uid: i32) -> Result<Session, Error> {
+        sessions.find(_id).filter(session_user.eq(_uid)).first(conn)
+    }
+}
+
+impl Updatable for NewStrain {
+    type Output = Strain;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Strain, Error> {
+        self.validate()?;
+        diesel::update(strains.find(_id)).set(self).get_result(conn)
+    }
+}
+
+impl Updatable for StrainChanges {
+    type Output = Strain;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Strain, Error> {
+        self.validate()?;
+        diesel::update(strains.find(_id)).set(self).get_result(conn)
+    }
+}
+
+impl Updatable for NewGrower {
+    type Output = Grower;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Grower, Error> {
+        self.validate()?;
+        diesel::update(growers.find(_id)).set(self).get_result(conn)
+    }
+}
+
+/// Like `BatchChanges`, the changes are applied first and the resulting grower is checked
+/// as a whole, so a new license number is held to the stored state and the other way
+/// around. A broken grower is rolled back.
+impl Updatable for GrowerChanges {
+    type Output = Grower;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Grower, Error> {
+        self.validate()?;
+        conn.transaction(|| {
+            let grower = diesel::update(growers.find(_id))
+                .set(self)
+                .get_result::<Grower>(conn)?;
+            NewGrower::from(&grower).validate()?;
+            Ok(grower)
+        })
+    }
+}
+
+impl Updatable for NewDispensary {
+    type Output = Dispensary;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Dispensary, Error> {
+        self.validate()?;
+        diesel::update(dispensaries.find(_id))
+            .set(self)
+            .get_result(conn)
+    }
+}
+
+impl Updatable for DispensaryChanges {
+    type Output = Dispensary;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Dispensary, Error> {
+        self.validate()?;
+        diesel::update(dispensaries.find(_id))
+            .set(self)
+            .get_result(conn)
+    }
+}
+
+/// A changed listing was seen at its new price just now, unless told otherwise
+impl Updatable for ListingChanges {
+    type Output = Listing;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Listing, Error> {
+        self.validate()?;
+        let mut changes = self.clone();
+        changes
+            .observed_at
+            .get_or_insert_with(|| Utc::now().naive_utc());
+        conn.transaction(|| {
+            let listing: Listing = diesel::update(listings.find(_id))
+                .set(&changes)
+                .get_result(conn)?;
+            // Only a listing on the menu tells what the batch sells for
+            if listing.in_stock {
+                NewPriceObservation::from(&listing).create(conn)?;
+            }
+            Ok(listing)
+        })
+    }
+}
+
+impl Updatable for NewBatch {
+    type Output = Batch;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Batch, Error> {
+        self.validate()?;
+        self.derivation(conn, Some(_id))?.into_result()?;
+        diesel::update(batches.find(_id)).set(self).get_result(conn)
+    }
+}
+
+/// The changes are applied first and the resulting batch is checked as a whole, so
+/// potency is measured against the format it ends up with. A broken batch is rolled back.
+impl Updatable for BatchChanges {
+    type Output = Batch;
+    fn update(&self, conn: &PgConnection, _id: i32) -> Result<Batch, Error> {
+        self.validate()?;
+        conn.transaction(|| {
+            let batch = diesel::update(batches.find(_id))
+                .set(self)
+                .get_result::<Batch>(conn)?;
+            let updated = NewBatch::from(&batch);
+            updated.validate()?;
+            updated.derivation(conn, Some(_id))?.into_result()?;
+            Ok(batch)
+        })
+    }
+}
+
+impl Strain {
+    /// Number of batches that reference this strain and would be removed with it
+    /// by the `ON DELETE CASCADE` on `batches.strain_id`
+    pub fn batch_count(&self, conn: &PgConnection) -> Result<i64, Error> {
+        batches
+            .filter(strain_id.eq(self.id))
+            .count()
+            .get_result(conn)
+    }
+
+    /// Aggregate rating of every review of every batch of this strain
+    pub fn rating(&self, conn: &PgConnection) -> Result<RatingSummary, Error> {
+        sql_query(STRAIN_RATING_QUERY)
+            .bind::<Integer, _>(self.id)
+            .get_result::<RatingRow>(conn)
+            .map(RatingSummary::from)
+    }
+
+    /// Chemotype of this strain's batches, or `None` if it has none with THC or CBD
+    pub fn chemotype(&self, conn: &PgConnection) -> Result<Option<Chemotype>, Error> {
+        sql_query(format!(
+            "SELECT p.thc, p.cbd FROM strains s LEFT JOIN ({}) p ON p.strain_id = s.id
+             WHERE s.id = $2",
+            STRAIN_POTENCY_SUBQUERY
+        ))
+        .bind::<Float4, _>(DECARB_FACTOR)
+        .bind::<Integer, _>(self.id)
+        .get_result::<PotencyRow>(conn)
+        .map(|p| p.chemotype())
+    }
+
+    /// Potency of this strain's flower batches overall, per grower and per month of
+    /// harvest, along with their mean terpene profile. Batches without a harvest date are
+    /// left out of the monthly series only. Derived products are measured in other units
+    /// and to other norms, so they are left out.
+    pub fn stats(&self, conn: &PgConnection) -> Result<StrainStats, Error> {
+        let rows: Vec<StatsRow> = sql_query(STRAIN_STATS_QUERY)
+            .bind::<Float4, _>(DECARB_FACTOR)
+            .bind::<Integer, _>(self.id)
+            .load(conn)?;
+        let profiled_batches = terpenes
+            .inner_join(batches)
+            .filter(strain_id.eq(self.id))
+            .filter(batch_format.eq(ProductFormat::Flower))
+            .count()
+            .get_result(conn)?;
+        let means: Vec<CompoundMean> = sql_query(STRAIN_TERPENE_MEANS_QUERY)
+            .bind::<Integer, _>(self.id)
+            .bind::<BigInt, _>(profiled_batches)
+            .load(conn)?;
+
+        let mut stats = StrainStats {
+            strain_id: self.id,
+            batch_count: 0,
+            thc: PotencyStats::default(),
+            cbd: PotencyStats::default(),
+            profiled_batches,
+            terpenes: means.into_iter().map(|m| (m.compound, m.amount)).collect(),
+            growers: vec![],
+            monthly: vec![],
+        };
+        for row in rows {
+            match (
+                row.grouped_by.as_str(),
+                row.grower_id,
+                &row.grower,
+                row.month,
+            ) {
+                ("grower", Some(g), Some(n), _) => stats.growers.push(GrowerPotency {
+                    grower_id: g,
+                    grower: n.clone(),
+                    batch_count: row.batch_count,
+                    thc: row.thc(),
+                    cbd: row.cbd(),
+                }),
+                ("month", _, _, Some(m)) => stats.monthly.push(MonthlyPotency {
+                    month: m,
+                    batch_count: row.batch_count,
+                    thc: row.thc(),
+                    cbd: row.cbd(),
+                }),
+                ("strain", ..) => {
+                    stats.batch_count = row.batch_count;
+                    stats.thc = row.thc();
+                    stats.cbd = row.cbd();
+                }
+                _ => {}
+            }
+        }
+        Ok(stats)
+    }
+
+    /// In-stock listings of this strain's batches, cheapest per gram first, optionally
+    /// of one `format` only
+    pub fn where_to_buy(
+        &self,
+        conn: &PgConnection,
+        format: Option<ProductFormat>,
+    ) -> Result<Vec<StrainListing>, Error> {
+        sql_query(WHERE_TO_BUY_QUERY)
+            .bind::<Integer, _>(self.id)
+            .bind::<Nullable<ProductFormatMapping>, _>(format)
+            .load(conn)
+    }
+
+    /// Strains this strain was bred from
+    pub fn parents(&self, conn: &PgConnection) -> Result<Vec<Strain>, Error> {
+        let ids = strain_parents
+            .select(parent_id)
+            .filter(child_id.eq(self.id));
+        strains.filter(sid.eq_any(ids)).order(name).load(conn)
+    }
+
+    /// Strains bred from this strain
+    pub fn children(&self, conn: &PgConnection) -> Result<Vec<Strain>, Error> {
+        let ids = strain_parents
+            .select(child_id)
+            .filter(parent_id.eq(self.id));
+        strains.filter(sid.eq_any(ids)).order(name).load(conn)
+    }
+
+    /// Other names this strain is sold under
+    pub fn aliases(&self, conn: &PgConnection) -> Result<Vec<StrainAlias>, Error> {
+        strain_aliases
+            .filter(aliased_strain.eq(self.id))
+            .order(strain_alias)
+            .load(conn)
+    }
+
+    /// Whether strain `ancestor` appears anywhere in strain `_id`'s lineage
+    pub fn descends_from(conn: &PgConnection, _id: i32, ancestor: i32) -> Result<bool, Error> {
+        sql_query(ANCESTOR_QUERY)
+            .bind::<Integer, _>(_id)
+            .bind::<Integer, _>(ancestor)
+            .get_results::<StrainParent>(conn)
+            .map(|links| !links.is_empty())
+    }
+
+    /// This strain's family tree, `depth` generations up. Each generation is read by one
+    /// step of a recursive query; the tree is then assembled from the links.
+    ///
+    /// Example:
+    /// let cake = strains.find(1).first::<Strain>(&conn).unwrap();
+    /// let tree = cake.lineage(&conn, 2).unwrap();
+    /// assert_eq!(tree.parents[0].strain.name, "Animal Mints");
+    pub fn lineage(self, conn: &PgConnection, depth: i32) -> Result<StrainLineage, Error> {
+        let links = sql_query(LINEAGE_QUERY)
+            .bind::<Integer, _>(self.id)
+            .bind::<Integer, _>(depth)
+            .get_results::<StrainParent>(conn)?;
+        let ids: Vec<i32> = links.iter().map(|l| l.parent_id).collect();
+        let ancestors: Vec<Strain> = strains.filter(sid.eq_any(ids)).order(name).load(conn)?;
+
+        fn branch(strain: Strain, links: &[StrainParent], ancestors: &[Strain]) -> StrainLineage {
+            let parents = ancestors
+                .iter()
+                .filter(|a| {
+                    links.contains(&StrainParent {
+                        strain_id: strain.id,
+                        parent_id: a.id,
+                    })
+                })
+                .map(|a| branch(a.clone(), links, ancestors))
+                .collect();
+            StrainLineage { strain, parents }
+        }
+        Ok(branch(self, &links, &ancestors))
+    }
+}
+
+impl Grower {
+    /// Number of batches that reference this grower and would be removed with it
+    /// by the `ON DELETE CASCADE` on `batches.grower_id`
+    pub fn batch_count(&self, conn: &PgConnection) -> Result<i64, Error> {
+        batches
+            .filter(grower_id.eq(self.id))
+            .count()
+            .get_result(conn)
+    }
+
+    /// Up to `limit` growers with at least `min_batches` batches, best first by `metric`.
+    /// Growers that can't be scored on the metric, such as those with no strain grown
+    /// twice when ranking by consistency, come last.
+    pub fn leaderboard(
+        conn: &PgConnection,
+        metric: ScoreMetric,
+        min_batches: i64,
+        limit: i64,
+    ) -> Result<Vec<GrowerScore>, Error> {
+        Grower::scores(conn, metric, None, min_batches, limit)
+    }
+
+    /// This grower's score, ranked by consistency, and the consistency of each strain
+    pub fn scorecard(&self, conn: &PgConnection) -> Result<GrowerScorecard, Error> {
+        let score = Grower::scores(conn, ScoreMetric::Consistency, Some(self.id), 0, 1)?
+            .pop()
+            .ok_or(Error::NotFound)?;
+        let strain_rows = sql_query(GROWER_STRAINS_QUERY)
+            .bind::<Float4, _>(DECARB_FACTOR)
+            .bind::<Integer, _>(self.id)
+            .load(conn)?;
+        Ok(GrowerScorecard {
+            score,
+            strains: strain_rows,
+        })
+    }
+
+    /// Growers with fewer than `min_batches` batches are left out before ranking, so they
+    /// take up no ranks; `grower` is picked out of the ranking afterwards, keeping its rank
+    fn scores(
+        conn: &PgConnection,
+        metric: ScoreMetric,
+        grower: Option<i32>,
+        min_batches: i64,
+        limit: i64,
+    ) -> Result<Vec<GrowerScore>, Error> {
+        sql_query(format!(
+            "SELECT * FROM (
+                 SELECT s.*, RANK() OVER (ORDER BY {} NULLS LAST) AS rank FROM ({}) s
+                 WHERE s.batch_count >= $3) ranked
+             WHERE ($2::int4 IS NULL OR grower_id = $2)
+             ORDER BY rank, grower
+             LIMIT $4",
+            metric.order(),
+            GROWER_SCORE_SUBQUERY
+        ))
+        .bind::<Float4, _>(DECARB_FACTOR)
+        .bind::<Nullable<Integer>, _>(grower)
+        .bind::<BigInt, _>(min_batches)
+        .bind::<BigInt, _>(limit)
+        .load(conn)
+    }
+
+    /// Other names this grower trades under
+    pub fn aliases(&self, conn: &PgConnection) -> Result<Vec<GrowerAlias>, Error> {
+        grower_aliases
+            .filter(aliased_grower.eq(self.id))
+            .order(grower_alias)
+            .load(conn)
+    }
+}
+
+impl SearchResults {
+    /// Strains and growers whose name or one of whose aliases resembles `q`, best match
+    /// first and at most `limit` of each. A prefix of a name matches it, as do names
+    /// misspelled by a letter or two.
+    ///
+    /// Example:
+    /// let found = SearchResults::find(&conn, "gelato", 10).unwrap();
+    /// assert_eq!(found.strains[0].name, "Gelato");
+    pub fn find(conn: &PgConnection, q: &str, limit: i64) -> Result<SearchResults, Error> {
+        let search = |table: &str, aliases: &str, owner: &str| {
+            sql_query(search_query(table, aliases, owner))
+                .bind::<VarChar, _>(q)
+                .bind::<BigInt, _>(limit)
+                .get_results(conn)
+        };
+        Ok(SearchResults {
+            strains: search("strains", "strain_aliases", "strain_id")?,
+            growers: search("growers", "grower_aliases", "grower_id")?,
+        })
+    }
+}
+
+impl Deletable for StrainAlias {
+    type Output = StrainAlias;
+    fn delete(&self, conn: &PgConnection) -> Result<StrainAlias, Error> {
+        diesel::delete(strain_aliases.find(self.id)).get_result(conn)
+    }
+}
+
+impl Deletable for GrowerAlias {
+    type Output = GrowerAlias;
+    fn delete(&self, conn: &PgConnection) -> Result<GrowerAlias, Error> {
+        diesel::delete(grower_aliases.find(self.id)).get_result(conn)
+    }
+}
+
+/// Its listings are removed with it
+impl Deletable for Dispensary {
+    type Output = Dispensary;
+    fn delete(&self, conn: &PgConnection) -> Result<Dispensary, Error> {
+        diesel::delete(dispensaries.find(self.id)).get_result(conn)
+    }
+}
+
+impl Deletable for Listing {
+    type Output = Listing;
+    fn delete(&self, conn: &PgConnection) -> Result<Listing, Error> {
+        diesel::delete(listings.find(self.id)).get_result(conn)
+    }
+}
+
+impl Listing {
+    /// Up to `limit` in-stock listings matching `filter`, ranked by what a gram of total
+    /// THC costs. Batches without THC can't be ranked and are left out.
+    pub fn by_value(
+        conn: &PgConnection,
+        filter: ValueFilter,
+        limit: i64,
+    ) -> Result<Vec<ListingValue>, Error> {
+        sql_query(LISTING_VALUE_QUERY)
+            .bind::<Nullable<SpeciesMapping>, _>(filter.species)
+            .bind::<Nullable<Integer>, _>(filter.strain_id)
+            .bind::<Nullable<ProductFormatMapping>, _>(filter.format)
+            .bind::<BigInt, _>(limit)
+            .bind::<Float4, _>(DECARB_FACTOR)
+            .load(conn)
+    }
+}
+
+impl Deletable for Grower {
+    type Output = Grower;
+    fn delete(&self, conn: &PgConnection) -> Result<Grower, Error> {
+        diesel::delete(growers.find(&self.id)).get_result(conn)
+    }
+}
+
+impl Deletable for Batch {
+    type Output = Batch;
+    fn delete(&self, conn: &PgConnection) -> Result<Batch, Error> {
+        diesel::delete(batches.find(&self.id)).get_result(conn)
+    }
+}
+
+impl Deletable for StrainParent {
+    type Output = StrainParent;
+    fn delete(&self, conn: &PgConnection) -> Result<StrainParent, Error> {
+        diesel::delete(strain_parents.find((self.strain_id, self.parent_id))).get_result(conn)
+    }
+}
+
+impl Deletable for Strain {
+    type Output = Strain;
+    fn delete(&self, conn: &PgConnection) -> Result<Strain, Error> {
+        diesel::delete(strains.find(&self.id)).get_result(conn)
+    }
+}
+
+/// Measurements are removed with the profile
+impl Deletable for Terpenes {
+    type Output = Terpenes;
+    fn delete(&self, conn: &PgConnection) -> Result<Terpenes, Error> {
+        match diesel::delete(terpenes.find(&self.id)).execute(conn)? {
+            0 => Err(Error::NotFound),
+            _ => Ok(self.clone()),
+        }
+    }
+}
+
+/// Base query behind every `BatchResponse`. Terpenes, cannabinoids and review aggregates are
+/// `LEFT JOIN`ed so that batches without a profile, panel or reviews are still returned.
+const BATCH_RESPONSE_QUERY: &str = "SELECT b.id, b.strain_id, s.name as strain, b.harvest_date,
+     b.final_test_date, b.package_date, b.grower_id, g.name as grower, b.thc_content,
+     b.cbd_content, b.format, b.parent_batch_id, t.id as terpenes_id, tm.terpene_names, tm.terpene_amounts,
+     c.id as cannabinoids_id, c.thca, c.cbda, c.cbg, c.cbga, c.cbn, c.cbc,
+     c.thcv, COALESCE(r.review_count, 0) as review_count, r.rating_avg,
+     COALESCE(r.rating_1, 0) as rating_1, COALESCE(r.rating_2, 0) as rating_2,
+     COALESCE(r.rating_3, 0) as rating_3, COALESCE(r.rating_4, 0) as rating_4,
+     COALESCE(r.rating_5, 0) as rating_5
+     FROM batches b INNER JOIN strains s ON b.strain_id = s.id
+     INNER JOIN growers g ON b.grower_id = g.id LEFT JOIN terpenes t ON t.batch_id = b.id
+     LEFT JOIN (SELECT m.terpenes_id, array_agg(tc.name ORDER BY tc.name) as terpene_names,
+         array_agg(m.amount ORDER BY tc.name) as terpene_amounts
+         FROM terpene_measurements m INNER JOIN terpene_compounds tc ON m.compound_id = tc.id
+         GROUP BY m.terpenes_id) tm ON tm.terpenes_id = t.id
+     LEFT JOIN cannabinoids c ON c.batch_id = b.id
+     LEFT JOIN (SELECT batch_id, COUNT(*) as review_count, AVG(rating)::float8 as rating_avg,
+         COUNT(*) FILTER (WHERE rating = 1) as rating_1,
+         COUNT(*) FILTER (WHERE rating = 2) as rating_2,
+         COUNT(*) FILTER (WHERE rating = 3) as rating_3,
+         COUNT(*) FILTER (WHERE rating = 4) as rating_4,
+         COUNT(*) FILTER (WHERE rating = 5) as rating_5
+         FROM reviews GROUP BY batch_id) r ON r.batch_id = b.id ";
+
+/// Mean total THC and CBD across flower batches, per strain. `$1` is `DECARB_FACTOR`.
+const STRAIN_POTENCY_SUBQUERY: &str = "SELECT b.strain_id,
+         AVG(b.thc_content + $1 * COALESCE(c.thca, 0))::float4 as thc,
+         AVG(b.cbd_content + $1 * COALESCE(c.cbda, 0))::float4 as cbd
+     FROM batches b LEFT JOIN cannabinoids c ON c.batch_id = b.id
+     WHERE b.format = 'flower' GROUP BY b.strain_id";
+
+/// `WHERE` clause for `StrainFilter` over strains `s` joined with their potency `p`.
+/// `$2` is `CHEMOTYPE_RATIO`; the `CASE` mirrors `Chemotype::classify`.
+const STRAIN_FILTER_CLAUSE: &str = "WHERE ($3::varchar IS NULL OR s.name ILIKE $3)
+     AND ($4::species IS NULL OR s.species = $4)
+     AND ($5::int4 IS NULL OR s.indica_percent >= $5)
+     AND ($6::int4 IS NULL OR s.indica_percent <= $6)
+     AND ($7::int4 IS NULL OR s.sativa_percent >= $7)
+     AND ($8::int4 IS NULL OR s.sativa_percent <= $8)
+     AND ($9::varchar IS NULL OR $9 = CASE
+         WHEN p.thc IS NULL OR (p.thc <= 0 AND p.cbd <= 0) THEN NULL
+         WHEN p.thc >= $2 * p.cbd THEN 'I'
+         WHEN p.cbd >= $2 * p.thc THEN 'III'
+         ELSE 'II' END)";
+
+/// Records of `table` whose name or whose alias in `aliases` resembles `$1`, each with
+/// its closest match, best first and at most `$2` of them. `%` compares whole names and
+/// `<%` finds `$1` within a longer one, so prefixes and typos both match; both can use
+/// the trigram indexes.
+fn search_query(table: &str, aliases: &str, owner: &str) -> String {
+    format!(
+        "SELECT id, name, matched, score FROM (
+             SELECT DISTINCT ON (t.id) t.id, t.name, m.matched,
+                 GREATEST(similarity(m.matched, $1), word_similarity($1, m.matched))::float4
+                     AS score
+             FROM {table} t INNER JOIN (
+                 SELECT id AS owner, name AS matched FROM {table}
+                 UNION ALL
+                 SELECT {owner}, alias FROM {aliases}) m ON m.owner = t.id
+             WHERE m.matched % $1 OR $1 <% m.matched
+             ORDER BY t.id, score DESC, m.matched = t.name DESC) hits
+         ORDER BY score DESC, name
+         LIMIT $2",
+        table = table,
+        aliases = aliases,
+        owner = owner
+    )
+}
+
+/// Total potency of strain `$2`'s flower batches (`$1` is `DECARB_FACTOR`) summarized over all
+/// of them, per grower and per month of harvest in a single pass. The set a row belongs
+/// to is named by `grouped_by`; with no batches only the `strain` row is returned.
+const STRAIN_STATS_QUERY: &str = "WITH p AS (
+         SELECT b.grower_id, g.name AS grower,
+             date_trunc('month', b.harvest_date)::date AS month,
+             (b.thc_content + $1 * COALESCE(c.thca, 0))::float4 AS thc,
+             (b.cbd_content + $1 * COALESCE(c.cbda, 0))::float4 AS cbd
+         FROM batches b INNER JOIN growers g ON g.id = b.grower_id
+             LEFT JOIN cannabinoids c ON c.batch_id = b.id
+         WHERE b.strain_id = $2 AND b.format = 'flower')
+     SELECT CASE WHEN GROUPING(grower_id) = 0 THEN 'grower'
+             WHEN GROUPING(month) = 0 THEN 'month'
+             ELSE 'strain' END AS grouped_by,
+         grower_id, grower, month, COUNT(*) AS batch_count,
+         MIN(thc) AS thc_min, MAX(thc) AS thc_max, AVG(thc)::float4 AS thc_mean,
+         (percentile_cont(0.5) WITHIN GROUP (ORDER BY thc))::float4 AS thc_median,
+         MIN(cbd) AS cbd_min, MAX(cbd) AS cbd_max, AVG(cbd)::float4 AS cbd_mean,
+         (percentile_cont(0.5) WITHIN GROUP (ORDER BY cbd))::float4 AS cbd_median
+     FROM p
+     GROUP BY GROUPING SETS ((), (grower_id, grower), (month))
+     ORDER BY grouped_by, grower, month";
+
+/// Mean amount of each compound across the `$2` terpene profiles of strain `$1`'s flower
+/// batches
+const STRAIN_TERPENE_MEANS_QUERY: &str = "SELECT tc.name AS compound,
+         (SUM(m.amount) / $2)::float4 AS amount
+     FROM terpene_measurements m INNER JOIN terpenes t ON t.id = m.terpenes_id
+         INNER JOIN batches b ON b.id = t.batch_id
+         INNER JOIN terpene_compounds tc ON tc.id = m.compound_id
+     WHERE b.strain_id = $1 AND b.format = 'flower'
+     GROUP BY tc.name
+     ORDER BY tc.name";
+
+/// Every grower's `GrowerScore` columns but `rank`. `$1` is `DECARB_FACTOR`. THC variance
+/// is measured over flower batches only.
+const GROWER_SCORE_SUBQUERY: &str = "WITH b AS (
+         SELECT b.id, b.grower_id, b.strain_id, b.harvest_date, b.final_test_date,
+             b.package_date, b.format, (b.thc_content + $1 * COALESCE(c.thca, 0))::float4 AS thc,
+             EXISTS (SELECT 1 FROM terpenes t WHERE t.batch_id = b.id) AS profiled
+         FROM batches b LEFT JOIN cannabinoids c ON c.batch_id = b.id),
+     per_strain AS (
+         SELECT grower_id, VAR_SAMP(thc) AS thc_variance FROM b
+         WHERE format = 'flower' GROUP BY grower_id, strain_id)
+     SELECT g.id AS grower_id, g.name AS grower,
+         COUNT(DISTINCT b.strain_id) AS strain_count, COUNT(b.id) AS batch_count,
+         (SELECT AVG(ps.thc_variance) FROM per_strain ps WHERE ps.grower_id = g.id)::float4
+             AS mean_thc_variance,
+         AVG(b.package_date - b.harvest_date)::float4 AS package_lag_days,
+         AVG(b.profiled::int)::float4 AS profiled_share,
+         (AVG((b.harvest_date IS NOT NULL AND b.final_test_date IS NOT NULL
+             AND b.package_date IS NOT NULL)::int) FILTER (WHERE b.id IS NOT NULL))::float4
+             AS complete_dates_share
+     FROM growers g LEFT JOIN b ON b.grower_id = g.id
+     GROUP BY g.id, g.name";
+
+/// Mean and sample variance of total THC per strain across grower `$2`'s flower batches.
+/// `$1` is `DECARB_FACTOR`.
+const GROWER_STRAINS_QUERY: &str = "SELECT s.id AS strain_id, s.name AS strain,
+         COUNT(*) AS batch_count,
+         AVG(b.thc_content + $1 * COALESCE(c.thca, 0))::float4 AS thc_mean,
+         VAR_SAMP(b.thc_content + $1 * COALESCE(c.thca, 0))::float4 AS thc_variance
+     FROM batches b INNER JOIN strains s ON s.id = b.strain_id
+         LEFT JOIN cannabinoids c ON c.batch_id = b.id
+     WHERE b.grower_id = $2 AND b.format = 'flower'
+     GROUP BY s.id, s.name
+     ORDER BY s.name";
+
+/// Listings matching a `ListingFilter`
+const LISTING_FILTER_QUERY: &str =
+    "SELECT l.* FROM listings l INNER JOIN batches b ON b.id = l.batch_id
+     WHERE ($1::int4 IS NULL OR l.dispensary_id = $1)
+     AND ($2::int4 IS NULL OR l.batch_id = $2)
+     AND ($3::int4 IS NULL OR b.strain_id = $3)
+     AND ($4::product_format IS NULL OR l.format = $4)
+     AND ($5::bool IS NULL OR l.in_stock = $5)";
+
+/// Price observations of batch `$1` made from day `$2` through day `$3`, with the cost of
+/// a gram and of a gram of total THC (`$4` is `DECARB_FACTOR`). Edibles' THC is given per
+/// unit rather than by weight, so they have no cost per gram of THC.
+const PRICE_HISTORY_QUERY: &str = "SELECT o.id, o.listing_id, d.name AS dispensary,
+         o.weight_grams, o.price_cents, o.observed_at,
+         (o.price_cents / o.weight_grams)::float4 AS price_per_gram_cents,
+         CASE WHEN b.format <> 'edible' THEN
+             (o.price_cents / (o.weight_grams
+                 * NULLIF(b.thc_content + $4 * COALESCE(c.thca, 0), 0) / 100))::float4
+         END AS price_per_gram_thc_cents
+     FROM price_observations o INNER JOIN batches b ON b.id = o.batch_id
+         LEFT JOIN cannabinoids c ON c.batch_id = b.id
+         LEFT JOIN listings l ON l.id = o.listing_id
+         LEFT JOIN dispensaries d ON d.id = l.dispensary_id
+     WHERE o.batch_id = $1
+     AND ($2::date IS NULL OR o.observed_at >= $2)
+     AND ($3::date IS NULL OR o.observed_at < $3 + 1)
+     ORDER BY o.observed_at, o.id";
+
+/// In-stock listings of species `$1`, strain `$2` and format `$3` (each if given), ranked
+/// by the cost of a gram of total THC (`$5` is `DECARB_FACTOR`). Edible batches are left
+/// out like in `PRICE_HISTORY_QUERY`.
+const LISTING_VALUE_QUERY: &str =
+    "SELECT RANK() OVER (ORDER BY v.price_per_gram_thc_cents) AS rank,
+         v.*
+     FROM (SELECT l.id AS listing_id, d.id AS dispensary_id, d.name AS dispensary,
+             l.batch_id, s.id AS strain_id, s.name AS strain, s.species, g.name AS grower,
+             l.format, b.thc_content, t.total_thc, l.weight_grams, l.price_cents,
+             (l.price_cents / l.weight_grams)::float4 AS price_per_gram_cents,
+             (l.price_cents / (l.weight_grams * t.total_thc / 100))::float4
+                 AS price_per_gram_thc_cents,
+             l.observed_at
+         FROM listings l INNER JOIN dispensaries d ON d.id = l.dispensary_id
+             INNER JOIN batches b ON b.id = l.batch_id
+             INNER JOIN strains s ON s.id = b.strain_id
+             INNER JOIN growers g ON g.id = b.grower_id
+             LEFT JOIN cannabinoids c ON c.batch_id = b.id
+             CROSS JOIN LATERAL
+                 (SELECT (b.thc_content + $5 * COALESCE(c.thca, 0))::float4 AS total_thc) t
+         WHERE l.in_stock AND t.total_thc > 0 AND b.format <> 'edible'
+         AND ($1::species IS NULL OR s.species = $1)
+         AND ($2::int4 IS NULL OR s.id = $2)
+         AND ($3::product_format IS NULL OR l.format = $3)) v
+     ORDER BY rank, v.listing_id
+     LIMIT $4";
+
+/// In-stock listings of strain `$1`, of format `$2` if given, cheapest per gram first
+const WHERE_TO_BUY_QUERY: &str = "SELECT l.id AS listing_id, d.id AS dispensary_id,
+         d.name AS dispensary, d.address, l.batch_id, g.name AS grower, l.format,
+         l.weight_grams, l.price_cents,
+         (l.price_cents / l.weight_grams)::float4 AS price_per_gram_cents, l.observed_at
+     FROM listings l INNER JOIN dispensaries d ON d.id = l.dispensary_id
+         INNER JOIN batches b ON b.id = l.batch_id
+         INNER JOIN growers g ON g.id = b.grower_id
+     WHERE b.strain_id = $1 AND l.in_stock
+     AND ($2::product_format IS NULL OR l.format = $2)
+     ORDER BY price_per_gram_cents, l.id";
+
+/// Growers matching a `GrowerFilter`
+const GROWER_FILTER_QUERY: &str = "SELECT * FROM growers
+     WHERE ($1::varchar IS NULL OR name ILIKE $1)
+     AND ($2::varchar IS NULL OR state = UPPER($2))
+     AND ($3::cultivation_type IS NULL OR cultivation_type = $3)
+     AND ($4::bool IS NULL OR active = $4)";
+
+/// Dispensaries matching a `DispensaryFilter`
+const DISPENSARY_FILTER_QUERY: &str = "SELECT * FROM dispensaries
+     WHERE ($1::varchar IS NULL OR name ILIKE $1)";
+
+/// Sessions by id `$1`, user `$2` and batch `$3`, each skipped when NULL
+const SESSION_FILTER_QUERY: &str = "SELECT * FROM sessions
+     WHERE ($1::int4 IS NULL OR id = $1)
+     AND ($2::int4 IS NULL OR user_id = $2)
+     AND ($3::int4 IS NULL OR batch_id = $3)";
+
+/// Reviews by id `$1`, user `$2`, batch `$3` and strain `$4`, each skipped when NULL
+const REVIEW_FILTER_QUERY: &str =
+    "SELECT r.* FROM reviews r INNER JOIN batches b ON b.id = r.batch_id
+     WHERE ($1::int4 IS NULL OR r.id = $1)
+     AND ($2::int4 IS NULL OR r.user_id = $2)
+     AND ($3::int4 IS NULL OR r.batch_id = $3)
+     AND ($4::int4 IS NULL OR b.strain_id = $4)";
+
+/// Inventory items by id `$1` and user `$2`, each skipped when NULL, and only those low on
+/// stock when `$3`. `$4` to `$6` are the default thresholds of grams, eighths and units.
+const INVENTORY_FILTER_QUERY: &str = "SELECT * FROM inventory
+     WHERE ($1::int4 IS NULL OR id = $1)
+     AND ($2::int4 IS NULL OR user_id = $2)
+     AND (NOT $3 OR quantity <= COALESCE(low_stock_threshold, CASE unit
+         WHEN 'grams' THEN $4 WHEN 'eighths' THEN $5 ELSE $6 END))";
+
+/// Parent links of strain `$1` and of its ancestors, at most `$2` generations up
+const LINEAGE_QUERY: &str = "WITH RECURSIVE lineage(strain_id, parent_id, depth) AS (
+         SELECT strain_id, parent_id, 1 FROM strain_parents WHERE strain_id = $1
+         UNION
+         SELECT sp.strain_id, sp.parent_id, l.depth + 1
+         FROM strain_parents sp INNER JOIN lineage l ON sp.strain_id = l.parent_id
+         WHERE l.depth < $2)
+     SELECT DISTINCT strain_id, parent_id FROM lineage";
+
+/// The parent link through which strain `$2` is an ancestor of strain `$1`, if it is one.
+/// `UNION` drops links already seen, so the walk ends even if the graph had a cycle.
+const ANCESTOR_QUERY: &str = "WITH RECURSIVE ancestors(strain_id, parent_id) AS (
+         SELECT strain_id, parent_id FROM strain_parents WHERE strain_id = $1
+         UNION
+         SELECT sp.strain_id, sp.parent_id
+         FROM strain_parents sp INNER JOIN ancestors a ON sp.strain_id = a.parent_id)
+     SELECT strain_id, parent_id FROM ancestors WHERE parent_id = $2 LIMIT 1";
+
+/// Review aggregates across every batch of strain `$1`
+const STRAIN_RATING_QUERY: &str = "SELECT COUNT(r.id) as review_count,
+     AVG(r.rating)::float8 as rating_avg,
+     COUNT(r.id) FILTER (WHERE r.rating = 1) as rating_1,
+     COUNT(r.id) FILTER (WHERE r.rating = 2) as rating_2,
+     COUNT(r.id) FILTER (WHERE r.rating = 3) as rating_3,
+     COUNT(r.id) FILTER (WHERE r.rating = 4) as rating_4,
+     COUNT(r.id) FILTER (WHERE r.rating = 5) as rating_5
+     FROM reviews r INNER JOIN batches b ON r.batch_id = b.id WHERE b.strain_id = $1";
+
+/// `WHERE` clause for `BatchFilter`. Each criterion is skipped when its parameter is NULL,
+/// so a single statement with a fixed number of binds covers every combination.
+const BATCH_FILTER_CLAUSE: &str = "WHERE ($1::int4 IS NULL OR b.strain_id = $1)
+     AND ($2::varchar IS NULL OR s.name ILIKE $2)
+     AND ($3::int4 IS NULL OR b.grower_id = $3)
+     AND ($4::varchar IS NULL OR g.name ILIKE $4)
+     AND ($5::species IS NULL OR s.species = $5)
+     AND ($6::date IS NULL OR b.harvest_date >= $6)
+     AND ($7::date IS NULL OR b.harvest_date <= $7)
+     AND ($8::date IS NULL OR b.final_test_date >= $8)
+     AND ($9::date IS NULL OR b.final_test_date <= $9)
+     AND ($10::date IS NULL OR b.package_date >= $10)
+     AND ($11::date IS NULL OR b.package_date <= $11)
+     AND ($12::float4 IS NULL OR b.thc_content >= $12)
+     AND ($13::float4 IS NULL OR b.thc_content <= $13)
+     AND ($14::float4 IS NULL OR b.cbd_content >= $14)
+     AND ($15::float4 IS NULL OR b.cbd_content <= $15)
+     AND ($16::product_format IS NULL OR b.format = $16)
+     AND ($17::int4 IS NULL OR b.parent_batch_id = $17) ";
+
+impl Batch {
+    /// Every price this batch was seen at within `filter`, oldest first
+    pub fn prices(
+        &self,
+        conn: &PgConnection,
+        filter: PriceHistoryFilter,
+    ) -> Result<Vec<PricePoint>, Error> {
+        sql_query(PRICE_HISTORY_QUERY)
+            .bind::<Integer, _>(self.id)
+            .bind::<Nullable<Date>, _>(filter.from)
+            .bind::<Nullable<Date>, _>(filter.to)
+            .bind::<Float4, _>(DECARB_FACTOR)
+            .load(conn)
+    }
+
+    /// Up to `limit` rows of the batch view with ids above `after`, in id order, so the
+    /// whole view can be walked a chunk at a time
+    pub fn responses_after(
+        conn: &PgConnection,
+        after: i32,
+        limit: i64,
+    ) -> Result<Vec<BatchResponse>, Error> {
+        sql_query(BATCH_RESPONSE_QUERY.to_owned() + "WHERE b.id > $1 ORDER BY b.id LIMIT $2")
+            .bind::<Integer, _>(after)
+            .bind::<BigInt, _>(limit)
+            .get_results::<BatchRow>(conn)
+            .map(|rows| rows.into_iter().map(BatchResponse::from).collect())
+    }
+}
+
+impl Retrievable<'_, BatchResponse> for Batch {
+    type Field = BatchFilter;
+    fn all(conn: &PgConnection) -> Result<Vec<BatchResponse>, Error> {
+        Self::filter(conn, BatchFilter::default())
+    }
+
+    fn filter(conn: &PgConnection, field: BatchFilter) -> Result<Vec<BatchResponse>, Error> {
+        sql_query(BATCH_RESPONSE_QUERY.to_owned() + BATCH_FILTER_CLAUSE + "ORDER BY b.id")
+            .bind::<Nullable<Integer>, _>(field.strain_id)
+            .bind::<Nullable<VarChar>, _>(field.strain)
+            .bind::<Nullable<Integer>, _>(field.grower_id)
+            .bind::<Nullable<VarChar>, _>(field.grower)
+            .bind::<Nullable<SpeciesMapping>, _>(field.species)
+            .bind::<Nullable<Date>, _>(field.harvest_date_min)
+            .bind::<Nullable<Date>, _>(field.harvest_date_max)
+            .bind::<Nullable<Date>, _>(field.final_test_date_min)
+            .bind::<Nullable<Date>, _>(field.final_test_date_max)
+            .bind::<Nullable<Date>, _>(field.package_date_min)
+            .bind::<Nullable<Date>, _>(field.package_date_max)
+            .bind::<Nullable<Float4>, _>(field.thc_min)
+            .bind::<Nullable<Float4>, _>(field.thc_max)
+            .bind::<Nullable<Float4>, _>(field.cbd_min)
+            .bind::<Nullable<Float4>, _>(field.cbd_max)
+            .bind::<Nullable<ProductFormatMapping>, _>(field.format)
+            .bind::<Nullable<Integer>, _>(field.parent_batch_id)
+            .get_results::<BatchRow>(conn)
+            .map(|rows| rows.into_iter().map(BatchResponse::from).collect())
+    }
+}
+
+impl Retrievable<'_> for Grower {
+    type Field = GrowerField;
+    fn all(conn: &PgConnection) -> Result<Vec<Grower>, Error> {
+        growers.load(conn)
+    }
+
+    fn filter(conn: &PgConnection, field: GrowerField) -> Result<Vec<Grower>, Error> {
+        match field {
+            GrowerField::Id(i) => growers.filter(gid.eq(i)).get_results(conn),
+            GrowerField::Name(n) => growers
+                .filter(sql("name ILIKE ").bind::<VarChar, _>(n))
+                .get_results(conn),
+        }
+    }
+}
+
+impl Retrievable<'_> for Strain {
+    type Field = StrainField;
+    fn all(conn: &PgConnection) -> Result<Vec<Strain>, Error> {
+        strains.load(conn)
+    }
+
+    fn filter(conn: &PgConnection, field: StrainField) -> Result<Vec<Strain>, Error> {
+        match field {
+            StrainField::Id(i) => strains.filter(sid.eq(i)).get_results(conn),
+            StrainField::Name(n) => strains
+                .filter(sql("name ILIKE  ").bind::<VarChar, _>(n))
+                .get_results(conn),
+            StrainField::Species(s) => strains.filter(species.eq(s)).get_results(conn),
+        }
+    }
+}
+
+impl Retrievable<'_> for Terpenes {
+    type Field = TerpenesField;
+    fn all(conn: &PgConnection) -> Result<Vec<Terpenes>, Error> {
+        let profiles = terpenes.order(id).load(conn)?;
+        Terpenes::measured(conn, profiles)
+    }
+
+    fn filter(conn: &PgConnection, field: TerpenesField) -> Result<Vec<Terpenes>, Error> {
+        let query = terpenes.order(id);
+        let profiles = match field {
+            TerpenesField::Id(i) => query.filter(id.eq(i)).load(conn)?,
+            TerpenesField::BatchID(b) => query.filter(batch_id.eq(b)).load(conn)?,
+        };
+        Terpenes::measured(conn, profiles)
+    }
+}
+
+impl Retrievable<'_> for Cannabinoids {
+    type Field = CannabinoidsField;
+    fn all(conn: &PgConnection) -> Result<Vec<Cannabinoids>, Error> {
+        cannabinoids.load(conn)
+    }
+
+    fn filter(conn: &PgConnection, field: CannabinoidsField) -> Result<Vec<Cannabinoids>, Error> {
+        match field {
+            CannabinoidsField::Id(i) => {
+                cannabinoids.filter(cannabinoids_id.eq(i)).get_results(conn)
+            }
+            CannabinoidsField::BatchID(b) => cannabinoids
+                .filter(cannabinoids_batch.eq(b))
+                .get_results(conn),
+        }
+    }
+}
+
+impl Retrievable<'_> for Inventory {
+    type Field = InventoryField;
+    fn all(conn: &PgConnection) -> Result<Vec<Inventory>, Error> {
+        inventory.order(inventory_id).load(conn)
+    }
+
+    fn filter(conn: &PgConnection, field: InventoryField) -> Result<Vec<Inventory>, Error> {
+        let query = inventory.order(inventory_id);
+        match field {
+            InventoryField::Id(i) => query.filter(inventory_id.eq(i)).get_results(conn),
+            InventoryField::UserID(u) => query.filter(inventory_user.eq(u)).get_results(conn),
+            InventoryField::LowStock(u) => query
+                .filter(inventory_user.eq(u))
+                .get_results::<Inventory>(conn)
+                .map(|items| items.into_iter().filter(Inventory::is_low_stock).collect()),
+        }
+    }
+}
+
+/// Reviews are always returned newest first
+impl Retrievable<'_> for Review {
+    type Field = ReviewField;
+    fn all(conn: &PgConnection) -> Result<Vec<Review>, Error> {
+        reviews
+            .order(super::schema::reviews::created_at.desc())
+            .load(conn)
+    }
+
+    fn filter(conn: &PgConnection, field: ReviewField) -> Result<Vec<Review>, Error> {
+        let query = reviews.order(super::schema::reviews::created_at.desc());
+        match field {
+            ReviewField::Id(i) => query.filter(review_id.eq(i)).get_results(conn),
+            ReviewField::UserID(u) => query.filter(review_user.eq(u)).get_results(conn),
+            ReviewField::BatchID(b) => query.filter(review_batch.eq(b)).get_results(conn),
+            ReviewField::StrainID(s) => query
+                .inner_join(batches)
+                .filter(strain_id.eq(s))
+                .select(super::schema::reviews::all_columns)
+                .get_results(conn),
+        }
+    }
+}
+
+/// Sessions are always returned newest first
+impl Retrievable<'_> for Session {
+    type Field = SessionField;
+    fn all(conn: &PgConnection) -> Result<Vec<Session>, Error> {
+        sessions.order(consumed_at.desc()).load(conn)
+    }
+
+    fn filter(conn: &PgConnection, field: SessionField) -> Result<Vec<Session>, Error> {
+        let query = sessions.order(consumed_at.desc());
+        match field {
+            SessionField::Id(i) => query.filter(session_id.eq(i)).get_results(conn),
+            SessionField::UserID(u) => query.filter(session_user.eq(u)).get_results(conn),
+            SessionField::UserBatch(u, b) => query
+                .filter(session_user.eq(u))
+                .filter(session_batch.eq(b))
+                .get_results(conn),
+        }
+    }
+}
+
+impl Retrievable<'_> for User {
+    type Field = UserField;
+    fn all(conn: &PgConnection) -> Result<Vec<User>, Error> {
+        users.load(conn)
+    }
+
+    fn filter(conn: &PgConnection, field: UserField) -> Result<Vec<User>, Error> {
+        match field {
+            UserField::Id(i) => users.filter(uid.eq(i)).get_results(conn),
+            UserField::Username(n) => users.filter(username.eq(n)).get_results(conn),
+        }
+    }
+}
+
+impl Pageable<BatchResponse> for Batch {
+    type Filter = BatchFilter;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::StrainId, "strain_id"),
+        (SortField::Strain, "LOWER(strain)"),
+        (SortField::GrowerId, "grower_id"),
+        (SortField::Grower, "LOWER(grower)"),
+        (SortField::HarvestDate, "harvest_date"),
+        (SortField::FinalTestDate, "final_test_date"),
+        (SortField::PackageDate, "package_date"),
+        (SortField::ThcContent, "thc_content"),
+        (SortField::CbdContent, "cbd_content"),
+        (SortField::Format, "format::text"),
+        (SortField::ReviewCount, "review_count"),
+        (SortField::Rating, "rating_avg"),
+    ];
+    const DEFAULT_ORDER: &'static str = "id";
+
+    fn page(
+        conn: &PgConnection,
+        filter: BatchFilter,
+        query: &PageQuery,
+    ) -> Result<Page<BatchResponse>, Error> {
+        let select = BATCH_RESPONSE_QUERY.to_owned() + BATCH_FILTER_CLAUSE;
+        let page = load_page::<BatchRow, _, _>(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            &select,
+            |q| {
+                q.bind::<Nullable<Integer>, _>(filter.strain_id)
+                    .bind::<Nullable<VarChar>, _>(&filter.strain)
+                    .bind::<Nullable<Integer>, _>(filter.grower_id)
+                    .bind::<Nullable<VarChar>, _>(&filter.grower)
+                    .bind::<Nullable<SpeciesMapping>, _>(&filter.species)
+                    .bind::<Nullable<Date>, _>(filter.harvest_date_min)
+                    .bind::<Nullable<Date>, _>(filter.harvest_date_max)
+                    .bind::<Nullable<Date>, _>(filter.final_test_date_min)
+                    .bind::<Nullable<Date>, _>(filter.final_test_date_max)
+                    .bind::<Nullable<Date>, _>(filter.package_date_min)
+                    .bind::<Nullable<Date>, _>(filter.package_date_max)
+                    .bind::<Nullable<Float4>, _>(filter.thc_min)
+                    .bind::<Nullable<Float4>, _>(filter.thc_max)
+                    .bind::<Nullable<Float4>, _>(filter.cbd_min)
+                    .bind::<Nullable<Float4>, _>(filter.cbd_max)
+                    .bind::<Nullable<ProductFormatMapping>, _>(filter.format)
+                    .bind::<Nullable<Integer>, _>(filter.parent_batch_id)
+            },
+        )?;
+        Ok(page.map(BatchResponse::from))
+    }
+}
+
+impl Pageable for Strain {
+    type Filter = StrainFilter;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::Name, "LOWER(name)"),
+        (SortField::Species, "species::text"),
+        (SortField::Breeder, "LOWER(breeder)"),
+        (SortField::Phenotype, "LOWER(phenotype)"),
+        (SortField::IndicaPercent, "indica_percent"),
+        (SortField::SativaPercent, "sativa_percent"),
+    ];
+    const DEFAULT_ORDER: &'static str = "id";
+
+    fn page(
+        conn: &PgConnection,
+        filter: StrainFilter,
+        query: &PageQuery,
+    ) -> Result<Page<Strain>, Error> {
+        let select = format!(
+            "SELECT s.* FROM strains s LEFT JOIN ({}) p ON p.strain_id = s.id {}",
+            STRAIN_POTENCY_SUBQUERY, STRAIN_FILTER_CLAUSE
+        );
+        load_page(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            &select,
+            |q| {
+                q.bind::<Float4, _>(DECARB_FACTOR)
+                    .bind::<Float4, _>(CHEMOTYPE_RATIO)
+                    .bind::<Nullable<VarChar>, _>(&filter.name)
+                    .bind::<Nullable<SpeciesMapping>, _>(&filter.species)
+                    .bind::<Nullable<Integer>, _>(filter.indica_min)
+                    .bind::<Nullable<Integer>, _>(filter.indica_max)
+                    .bind::<Nullable<Integer>, _>(filter.sativa_min)
+                    .bind::<Nullable<Integer>, _>(filter.sativa_max)
+                    .bind::<Nullable<VarChar>, _>(filter.chemotype.map(|c| c.as_str()))
+            },
+        )
+    }
+}
+
+impl Pageable for Grower {
+    type Filter = GrowerFilter;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::Name, "LOWER(name)"),
+        (SortField::LicenseNumber, "license_number"),
+        (SortField::State, "state"),
+        (SortField::City, "LOWER(city)"),
+        (SortField::CultivationType, "cultivation_type::text"),
+        (SortField::Active, "active"),
+    ];
+    const DEFAULT_ORDER: &'static str = "id";
+
+    fn page(
+        conn: &PgConnection,
+        filter: GrowerFilter,
+        query: &PageQuery,
+    ) -> Result<Page<Grower>, Error> {
+        load_page(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            GROWER_FILTER_QUERY,
+            |q| {
+                q.bind::<Nullable<VarChar>, _>(&filter.name)
+                    .bind::<Nullable<VarChar>, _>(&filter.state)
+                    .bind::<Nullable<CultivationTypeMapping>, _>(filter.cultivation_type)
+                    .bind::<Nullable<Bool>, _>(filter.active)
+            },
+        )
+    }
+}
+
+impl Pageable for Dispensary {
+    type Filter = DispensaryFilter;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::Name, "LOWER(name)"),
+        (SortField::Address, "LOWER(address)"),
+        (SortField::LicenseNumber, "license_number"),
+    ];
+    const DEFAULT_ORDER: &'static str = "id";
+
+    fn page(
+        conn: &PgConnection,
+        filter: DispensaryFilter,
+        query: &PageQuery,
+    ) -> Result<Page<Dispensary>, Error> {
+        load_page(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            DISPENSARY_FILTER_QUERY,
+            |q| q.bind::<Nullable<VarChar>, _>(&filter.name),
+        )
+    }
+}
+
+impl Pageable for Listing {
+    type Filter = ListingFilter;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::DispensaryId, "dispensary_id"),
+        (SortField::BatchId, "batch_id"),
+        (SortField::Format, "format::text"),
+        (SortField::WeightGrams, "weight_grams"),
+        (SortField::PriceCents, "price_cents"),
+        (SortField::InStock, "in_stock"),
+        (SortField::ObservedAt, "observed_at"),
+    ];
+    const DEFAULT_ORDER: &'static str = "id";
+
+    fn page(
+        conn: &PgConnection,
+        filter: ListingFilter,
+        query: &PageQuery,
+    ) -> Result<Page<Listing>, Error> {
+        load_page(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            LISTING_FILTER_QUERY,
+            |q| {
+                q.bind::<Nullable<Integer>, _>(filter.dispensary_id)
+                    .bind::<Nullable<Integer>, _>(filter.batch_id)
+                    .bind::<Nullable<Integer>, _>(filter.strain_id)
+                    .bind::<Nullable<ProductFormatMapping>, _>(filter.format)
+                    .bind::<Nullable<Bool>, _>(filter.in_stock)
+            },
+        )
+    }
+}
+
+impl Pageable for Inventory {
+    type Filter = InventoryField;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::BatchId, "batch_id"),
+        (SortField::Quantity, "quantity"),
+        (SortField::Unit, "unit::text"),
+        (SortField::PurchasePriceCents, "purchase_price_cents"),
+        (SortField::PurchaseDate, "purchase_date"),
+        (SortField::Location, "LOWER(location)"),
+    ];
+    const DEFAULT_ORDER: &'static str = "id";
+
+    fn page(
+        conn: &PgConnection,
+        field: InventoryField,
+        query: &PageQuery,
+    ) -> Result<Page<Inventory>, Error> {
+        let (item, user, low_stock) = match field {
+            InventoryField::Id(i) => (Some(i), None, false),
+            InventoryField::UserID(u) => (None, Some(u), false),
+            InventoryField::LowStock(u) => (None, Some(u), true),
+        };
+        load_page(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            INVENTORY_FILTER_QUERY,
+            |q| {
+                q.bind::<Nullable<Integer>, _>(item)
+                    .bind::<Nullable<Integer>, _>(user)
+                    .bind::<Bool, _>(low_stock)
+                    .bind::<Float4, _>(InventoryUnit::Grams.default_low_stock())
+                    .bind::<Float4, _>(InventoryUnit::Eighths.default_low_stock())
+                    .bind::<Float4, _>(InventoryUnit::Units.default_low_stock())
+            },
+        )
+    }
+}
+
+/// Reviews are listed newest first unless sorted otherwise
+impl Pageable for Review {
+    type Filter = ReviewField;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::BatchId, "batch_id"),
+        (SortField::Rating, "rating"),
+        (SortField::CreatedAt, "created_at"),
+    ];
+    const DEFAULT_ORDER: &'static str = "created_at DESC, id DESC";
+
+    fn page(
+        conn: &PgConnection,
+        field: ReviewField,
+        query: &PageQuery,
+    ) -> Result<Page<Review>, Error> {
+        let (review, user, batch, strain) = match field {
+            ReviewField::Id(i) => (Some(i), None, None, None),
+            ReviewField::UserID(u) => (None, Some(u), None, None),
+            ReviewField::BatchID(b) => (None, None, Some(b), None),
+            ReviewField::StrainID(s) => (None, None, None, Some(s)),
+        };
+        load_page(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            REVIEW_FILTER_QUERY,
+            |q| {
+                q.bind::<Nullable<Integer>, _>(review)
+                    .bind::<Nullable<Integer>, _>(user)
+                    .bind::<Nullable<Integer>, _>(batch)
+                    .bind::<Nullable<Integer>, _>(strain)
+            },
+        )
+    }
+}
+
+/// Sessions are listed newest first unless sorted otherwise
+impl Pageable for Session {
+    type Filter = SessionField;
+    const SORTABLE: &'static [(SortField, &'static str)] = &[
+        (SortField::Id, "id"),
+        (SortField::BatchId, "batch_id"),
+        (SortField::ConsumedAt, "consumed_at"),
+        (SortField::Method, "method::text"),
+        (SortField::Amount, "amount"),
+    ];
+    const DEFAULT_ORDER: &'static str = "consumed_at DESC, id DESC";
+
+    fn page(
+        conn: &PgConnection,
+        field: SessionField,
+        query: &PageQuery,
+    ) -> Result<Page<Session>, Error> {
+        let (session, user, batch) = match field {
+            SessionField::Id(i) => (Some(i), None, None),
+            SessionField::UserID(u) => (None, Some(u), None),
+            SessionField::UserBatch(u, b) => (None, Some(u), Some(b)),
+        };
+        load_page(
+            conn,
+            query,
+            Self::SORTABLE,
+            Self::DEFAULT_ORDER,
+            SESSION_FILTER_QUERY,
+            |q| {
+                q.bind::<Nullable<Integer>, _>(session)
+                    .bind::<Nullable<Integer>, _>(user)
+                    .bind::<Nullable<Integer>, _>(batch)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NewStrain, Species, Strain};
+
+    #[test]
+    fn connection_established() {
+        let conn = establish_connection();
+        assert!(conn.is_ok());
+    }
+
+    #[test]
+    fn new_strain_created() {
+        let conn = establish_connection().unwrap();
+        let new = NewStrain {
+            name: "Test OG".to_owned(),
+            species: Species::Indica,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        };
+        let strain = new.create(&conn);
+
+        assert!(strain.is_ok());
+        assert_eq!(strain.as_ref().unwrap().species, Species::Indica);
+        diesel::delete(strains.find(strain.as_ref().unwrap().id))
+            .get_result::<Strain>(&conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn strain_deleted() {
+        let conn = establish_connection().unwrap();
+        let new = NewStrain {
+            name: "Reggie Kush".to_owned(),
+            species: Species::Indica,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        };
+        let strain = new.create(&conn).unwrap();
+        assert!(strain.delete(&conn).is_ok());
+        let fails = strains.find(strain.id).get_result::<Strain>(&conn);
+        assert!(fails.is_err());
+    }
+
+    #[test]
+    fn all_strains_retrieved() {
+        let conn = establish_connection().unwrap();
+        let all = Strain::all(&conn);
+        assert_ne!(all.as_ref().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn strain_filtered_by_id() {
+        use super::StrainField;
+        use crate::models::{Species, Species::*};
+        let conn = establish_connection().unwrap();
+        let filtered_by_id = Strain::filter(&conn, StrainField::Id(3)).unwrap();
+        let indicas = Strain::filter(&conn, StrainField::Species(Indica)).unwrap();
+        assert_eq!(filtered_by_id[0].id, 3);
+        assert_eq!(indicas[2].species, Species::Indica);
+    }
+
+    #[test]
+    fn strain_filtered_by_species() {
+        use super::StrainField;
+        use crate::models::{Species, Species::*};
+        let conn = establish_connection().unwrap();
+        let indicas = Strain::filter(&conn, StrainField::Species(Indica)).unwrap();
+        assert_eq!(indicas[2].species, Species::Indica);
+    }
+
+    #[test]
+    fn strain_filtered_by_name() {
+        use super::StrainField;
+        let conn = establish_connection().unwrap();
+        let res = Strain::filter(&conn, StrainField::Name("gaylord OG".to_string())).unwrap();
+        assert_eq!(res[0].name, "Gaylord OG");
+    }
+
+    #[test]
+    fn new_batch_created() {
+        use crate::models::NewBatch;
+        let conn = establish_connection().unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(3)
+            .thc_content(22.9)
+            .cbd_content(0.2)
+            .build()
+            .create(&conn);
+
+        assert_eq!(batch.unwrap().strain_id, 3);
+    }
+
+    #[test]
+    fn batch_deleted() {
+        use super::Deletable;
+        let conn = establish_connection().unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(1)
+            .grower_id(3)
+            .thc_content(32.9)
+            .cbd_content(1.2)
+            .build()
+            .create(&conn)
+            .unwrap();
+
+        assert!(batch.delete(&conn).is_ok());
+    }
+
+    #[test]
+    fn new_grower_created() {
+        use super::Creatable;
+        let conn = establish_connection().unwrap();
+        let new = NewGrower {
+            name: "Tegridy Farms".to_string(),
+            ..Default::default()
+        };
+        let grower = new.create(&conn);
+    }
+
+    #[test]
+    fn grower_retrieved_by_name() {
+        use super::Retrievable;
+        let conn = establish_connection().unwrap();
+        let tegridy =
+            Grower::filter(&conn, GrowerField::Name("Tegridy Farms".to_string())).unwrap();
+        assert_eq!(tegridy[0].name, "Tegridy Farms");
+    }
+
+    #[test]
+    fn all_growers_retrieved() {
+        use super::Retrievable;
+        let conn = establish_connection().unwrap();
+        let _growers = Grower::all(&conn);
+        assert!(_growers.is_ok());
+    }
+
+    #[test]
+    fn all_batches_retrieved() {
+        use super::Retrievable;
+        let conn = establish_connection().unwrap();
+        let all = Batch::all(&conn).unwrap();
+        assert_ne!(all.len(), 0);
+    }
+
+    #[test]
+    fn batch_filtered_by_strain_name() {
+        use super::Retrievable;
+        let conn = establish_connection().unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain: Some("Blackwater OG".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(res[0].strain, "Blackwater OG");
+    }
+
+    #[test]
+    fn batch_filtered_by_strain_id() {
+        use super::Retrievable;
+        let conn = establish_connection().unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain_id: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(res[0].strain, "Blackwater OG".to_owned());
+    }
+
+    #[test]
+    fn batch_filtered_by_grower_id() {
+        let conn = establish_connection().unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                grower_id: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(res[0].grower, "Summa");
+    }
+    #[test]
+    fn batch_filtered_by_grower_name() {
+        let conn = establish_connection().unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                grower: Some("Summa".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(res[0].grower, "Summa");
+    }
+
+    #[test]
+    fn batch_filtered_by_date() {
+        let conn = establish_connection().unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                harvest_date_min: Some(NaiveDate::from_ymd(2022, 1, 10)),
+                harvest_date_max: Some(NaiveDate::from_ymd(2022, 1, 10)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(res[0].harvest_date, Some(NaiveDate::from_ymd(2022, 1, 10)));
+    }
+
+    #[test]
+    fn terpenes_created_and_retrieved_by_batch_id() {
+        let conn = establish_connection().unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(3)
+            .thc_content(25.1)
+            .cbd_content(0.1)
+            .build()
+            .create(&conn)
+            .unwrap();
+        let terps = NewTerpenes::builder()
+            .batch_id(batch.id)
+            .limonene(Some(0.42))
+            .myrcene(Some(0.31))
+            .build()
+            .create(&conn)
+            .unwrap();
+
+        let res = Terpenes::filter(&conn, TerpenesField::BatchID(batch.id)).unwrap();
+        assert_eq!(res[0].id, terps.id);
+        assert_eq!(res[0].compounds.get("limonene"), Some(&0.42));
+        assert_eq!(res[0].compounds.len(), 2);
+        batch.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn terpene_names_matched_without_case() {
+        let conn = establish_connection().unwrap();
+        let new = NewTerpenes::builder()
+            .batch_id(1)
+            .compound(" Limonene", Some(0.42))
+            .compound("MYRCENE ", Some(0.31))
+            .build();
+        conn.test_transaction::<_, Error, _>(|| {
+            diesel::delete(terpenes.filter(batch_id.eq(1))).execute(&conn)?;
+            let created = new.create(&conn)?;
+            assert_eq!(created.compounds.get("limonene"), Some(&0.42));
+            assert_eq!(created.compounds.get("myrcene"), Some(&0.31));
+            assert_eq!(created.compounds.len(), 2);
+
+            let stored = Terpenes::filter(&conn, TerpenesField::BatchID(1))?;
+            assert_eq!(stored[0].compounds, created.compounds);
+            Ok(())
+        });
+
+        let twice = NewTerpenes::builder()
+            .batch_id(1)
+            .compound("limonene", Some(0.4))
+            .compound("Limonene", Some(0.5))
+            .build();
+        conn.test_transaction::<_, Error, _>(|| {
+            diesel::delete(terpenes.filter(batch_id.eq(1))).execute(&conn)?;
+            assert!(matches!(
+                twice.create(&conn),
+                Err(Error::QueryBuilderError(_))
+            ));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn terpene_total_bounded_by_format() {
+        let conn = establish_connection().unwrap();
+        let sauce = |batch| {
+            NewTerpenes::builder()
+                .batch_id(batch)
+                .compound("myrcene", Some(18.0))
+                .compound("limonene", Some(12.0))
+                .build()
+        };
+        conn.test_transaction::<_, Error, _>(|| {
+            let flower = batches.find(1).first::<Batch>(&conn)?;
+            let extract = NewProduct {
+                format: ProductFormat::Concentrate,
+                final_test_date: None,
+                package_date: None,
+                thc_content: 70.0,
+                cbd_content: 0.0,
+            }
+            .of(&flower)
+            .create(&conn)?;
+            assert_eq!(sauce(extract.id).create(&conn)?.compounds.len(), 2);
+
+            diesel::delete(terpenes.filter(batch_id.eq(flower.id))).execute(&conn)?;
+            match sauce(flower.id).create(&conn) {
+                Err(Error::QueryBuilderError(e)) => {
+                    let errors = e.downcast::<ValidationErrors>().unwrap();
+                    assert_eq!(errors.0[0].field, "terpenes");
+                }
+                other => panic!("expected validation error, got {:?}", other),
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn unknown_terpene_rejected() {
+        let conn = establish_connection().unwrap();
+        let new = NewTerpenes::builder()
+            .batch_id(1)
+            .compound("unobtainium", Some(0.1))
+            .build();
+        conn.test_transaction::<_, Error, _>(|| {
+            match new.create(&conn) {
+                Err(Error::QueryBuilderError(e)) => {
+                    let errors = e.downcast::<ValidationErrors>().unwrap();
+                    assert_eq!(errors.0[0].field, "unobtainium");
+                }
+                other => panic!("expected validation error, got {:?}", other),
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn duplicate_terpenes_rejected() {
+        use diesel::result::DatabaseErrorKind;
+        let conn = establish_connection().unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(3)
+            .thc_content(25.1)
+            .cbd_content(0.1)
+            .build()
+            .create(&conn)
+            .unwrap();
+        let new = NewTerpenes::builder().batch_id(batch.id).build();
+        new.create(&conn).unwrap();
+
+        match new.create(&conn) {
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => (),
+            other => panic!("expected unique violation, got {:?}", other),
+        }
+        batch.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn terpenes_replaced_and_deleted() {
         let conn = establish_connection().unwrap();
-        let new = NewStrain {
-            name: "Test OG".to_owned(),
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(3)
+            .thc_content(25.1)
+            .cbd_content(0.1)
+            .build()
+            .create(&conn)
+            .unwrap();
+        NewTerpenes::builder()
+            .batch_id(batch.id)
+            .pinene(Some(0.2))
+            .build()
+            .create(&conn)
+            .unwrap();
+
+        let replaced = NewTerpenes::builder()
+            .batch_id(batch.id)
+            .humulene(Some(0.15))
+            .build()
+            .replace(&conn)
+            .unwrap();
+        let stored = Terpenes::filter(&conn, TerpenesField::BatchID(batch.id)).unwrap();
+        assert_eq!(stored[0], replaced);
+        assert_eq!(stored[0].compounds.get("pinene"), None);
+        assert_eq!(stored[0].compounds.get("humulene"), Some(&0.15));
+
+        assert!(replaced.delete(&conn).is_ok());
+        let res = Terpenes::filter(&conn, TerpenesField::BatchID(batch.id)).unwrap();
+        assert!(res.is_empty());
+        batch.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn batch_response_includes_ids_and_terpenes() {
+        let conn = establish_connection().unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(3)
+            .thc_content(26.3)
+            .cbd_content(0.2)
+            .build()
+            .create(&conn)
+            .unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain_id: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let without = res.iter().find(|r| r.id == batch.id).unwrap();
+        assert_eq!(without.strain_id, 3);
+        assert_eq!(without.grower_id, 3);
+        assert!(without.terpenes.is_none());
+
+        NewTerpenes::builder()
+            .batch_id(batch.id)
+            .limonene(Some(0.08))
+            .compound("terpinolene", Some(0.3))
+            .build()
+            .create(&conn)
+            .unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain_id: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let with = res.iter().find(|r| r.id == batch.id).unwrap();
+        let terps = with.terpenes.as_ref().unwrap();
+        assert_eq!(terps.compounds.get("limonene"), Some(&0.08));
+        assert_eq!(terps.compounds.get("terpinolene"), Some(&0.3));
+        batch.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn batch_filtered_by_combined_criteria() {
+        let conn = establish_connection().unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain_id: Some(3),
+                grower_id: Some(3),
+                species: Some(Species::Indica),
+                harvest_date_min: Some(NaiveDate::from_ymd(2022, 1, 1)),
+                thc_min: Some(20.0),
+                cbd_max: Some(0.5),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_ne!(res.len(), 0);
+        assert!(res.iter().all(|b| b.strain_id == 3
+            && b.grower_id == 3
+            && b.harvest_date >= Some(NaiveDate::from_ymd(2022, 1, 1))
+            && b.thc_content >= 20.0
+            && b.cbd_content <= 0.5));
+    }
+
+    #[test]
+    fn batch_filtered_by_potency_range() {
+        let conn = establish_connection().unwrap();
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                thc_min: Some(100.0),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(res.is_empty());
+    }
+
+    #[test]
+    fn strains_paged_and_sorted() {
+        let conn = establish_connection().unwrap();
+        let query = PageQuery {
+            limit: Some(2),
+            offset: Some(1),
+            sort: Some("name:desc".to_owned()),
+        };
+        let page = Strain::page(&conn, StrainFilter::default(), &query).unwrap();
+        let mut names: Vec<String> = Strain::all(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name.to_lowercase())
+            .collect();
+        names.sort();
+        names.reverse();
+
+        assert_eq!(page.total, names.len());
+        assert_eq!(page.data.len(), 2);
+        assert_eq!(page.data[0].name.to_lowercase(), names[1]);
+        assert!(page.has_prev());
+    }
+
+    #[test]
+    fn batches_paged_with_filter() {
+        let conn = establish_connection().unwrap();
+        let query = PageQuery {
+            limit: Some(1),
+            sort: Some("thc_content:asc".to_owned()),
+            ..Default::default()
+        };
+        let filter = BatchFilter {
+            strain_id: Some(3),
+            ..Default::default()
+        };
+        let page = Batch::page(&conn, filter, &query).unwrap();
+        assert_eq!(page.data.len(), 1);
+        assert!(page.total >= 2);
+        assert!(page.has_next());
+    }
+
+    #[test]
+    fn invalid_sort_rejected() {
+        let conn = establish_connection().unwrap();
+        let query = PageQuery {
+            sort: Some("nonexistent:asc".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Grower::page(&conn, GrowerFilter::default(), &query),
+            Err(Error::QueryBuilderError(_))
+        ));
+
+        // Known field, but not one growers have
+        let query = PageQuery {
+            sort: Some("rating:desc".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Grower::page(&conn, GrowerFilter::default(), &query),
+            Err(Error::QueryBuilderError(_))
+        ));
+    }
+
+    #[test]
+    fn pages_sorted_across_windows() {
+        let conn = establish_connection().unwrap();
+        let query = |offset| PageQuery {
+            limit: Some(2),
+            offset: Some(offset),
+            sort: Some("thc_content:desc".to_owned()),
+        };
+        let first = Batch::page(&conn, BatchFilter::default(), &query(0)).unwrap();
+        let second = Batch::page(&conn, BatchFilter::default(), &query(2)).unwrap();
+        let thc: Vec<f32> = first
+            .data
+            .iter()
+            .chain(second.data.iter())
+            .map(|b| b.thc_content)
+            .collect();
+
+        assert_eq!(thc.len(), 4);
+        assert!(thc.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn strain_updated() {
+        let conn = establish_connection().unwrap();
+        let strain = NewStrain {
+            name: "Updatable Kush".to_owned(),
+            species: Species::Indica,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .create(&conn)
+        .unwrap();
+
+        let patched = StrainChanges {
+            species: Some(Species::Hybrid),
+            ..Default::default()
+        }
+        .update(&conn, strain.id)
+        .unwrap();
+        assert_eq!(patched.name, "Updatable Kush");
+        assert_eq!(patched.species, Species::Hybrid);
+
+        let replaced = NewStrain {
+            name: "Replaced Kush".to_owned(),
+            species: Species::Sativa,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .update(&conn, strain.id)
+        .unwrap();
+        assert_eq!(replaced.name, "Replaced Kush");
+        assert_eq!(replaced.species, Species::Sativa);
+        replaced.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn names_stored_trimmed() {
+        let conn = establish_connection().unwrap();
+        let strain: NewStrain =
+            serde_json::from_str(r#"{"name": "  Padded Kush ", "species": "Indica"}"#).unwrap();
+        let strain = strain.create(&conn).unwrap();
+        assert_eq!(strain.name, "Padded Kush");
+
+        let grower: NewGrower = serde_json::from_str(r#"{"name": "\tPadded Farms "}"#).unwrap();
+        let grower = grower.create(&conn).unwrap();
+        assert_eq!(grower.name, "Padded Farms");
+
+        let changes: GrowerChanges =
+            serde_json::from_str(r#"{"name": " Repadded Farms  "}"#).unwrap();
+        let grower = changes.update(&conn, grower.id).unwrap();
+        assert_eq!(grower.name, "Repadded Farms");
+
+        grower.delete(&conn).unwrap();
+        strain.delete(&conn).unwrap();
+
+        // Login looks a user up by the same trimmed name they registered under
+        let creds: crate::models::Credentials =
+            serde_json::from_str(r#"{"username": " alice ", "password": "password123"}"#).unwrap();
+        assert_eq!(creds.username, "alice");
+    }
+
+    #[test]
+    fn batch_updated() {
+        let conn = establish_connection().unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(3)
+            .harvest_date(Some(NaiveDate::from_ymd(2022, 3, 1)))
+            .thc_content(22.0)
+            .cbd_content(0.1)
+            .build()
+            .create(&conn)
+            .unwrap();
+
+        let patched = BatchChanges {
+            thc_content: Some(23.5),
+            ..Default::default()
+        }
+        .update(&conn, batch.id)
+        .unwrap();
+        assert_eq!(patched.thc_content, 23.5);
+        assert_eq!(patched.harvest_date, Some(NaiveDate::from_ymd(2022, 3, 1)));
+
+        let replaced = NewBatch::builder()
+            .strain_id(1)
+            .grower_id(2)
+            .thc_content(18.0)
+            .cbd_content(0.4)
+            .build()
+            .update(&conn, batch.id)
+            .unwrap();
+        assert_eq!(replaced.strain_id, 1);
+        assert_eq!(replaced.harvest_date, None);
+        replaced.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn missing_record_not_updated() {
+        let conn = establish_connection().unwrap();
+        let res = GrowerChanges {
+            name: Some("Nobody".to_owned()),
+            ..Default::default()
+        }
+        .update(&conn, -1);
+        assert!(matches!(res, Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn strain_batches_counted() {
+        let conn = establish_connection().unwrap();
+        let strain = Strain::filter(&conn, StrainField::Id(3)).unwrap().remove(0);
+        let expected = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain_id: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .len();
+        assert_eq!(strain.batch_count(&conn).unwrap(), expected as i64);
+    }
+
+    #[test]
+    fn token_resolves_to_user_until_revoked() {
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "token tester".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let token = NewAuthToken {
+            user_id: user.id,
+            jti: "token-tester-jti".to_owned(),
+            expires_at: chrono::Utc::now().naive_utc() + chrono::Duration::hours(1),
+        }
+        .create(&conn)
+        .unwrap();
+
+        assert_eq!(
+            AuthToken::user(&conn, &token.jti, user.id).unwrap().id,
+            user.id
+        );
+        assert!(AuthToken::user(&conn, &token.jti, user.id + 1).is_err());
+        AuthToken::revoke(&conn, &token.jti).unwrap();
+        assert!(matches!(
+            AuthToken::user(&conn, &token.jti, user.id),
+            Err(Error::NotFound)
+        ));
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+    }
+
+    #[test]
+    fn user_retrieved_by_username() {
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "lookup tester".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let res = User::filter(&conn, UserField::Username("lookup tester".to_owned())).unwrap();
+        assert_eq!(res[0].id, user.id);
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+    }
+
+    #[test]
+    fn session_logged_updated_and_deleted() {
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "session tester".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let session = NewSession {
+            user_id: user.id,
+            batch_id: 1,
+            consumed_at: None,
+            method: ConsumptionMethod::Vape,
+            amount: Some(0.25),
+            notes: Some("Couch lock".to_owned()),
+        }
+        .create(&conn)
+        .unwrap();
+        assert_eq!(session.method, ConsumptionMethod::Vape);
+
+        let mine = Session::filter(&conn, SessionField::UserBatch(user.id, 1)).unwrap();
+        assert_eq!(mine.len(), 1);
+        assert!(Session::filter(&conn, SessionField::UserBatch(user.id, 2))
+            .unwrap()
+            .is_empty());
+
+        let changed = SessionChanges {
+            method: Some(ConsumptionMethod::Flower),
+            ..Default::default()
+        }
+        .update(&conn, session.id)
+        .unwrap();
+        assert_eq!(changed.method, ConsumptionMethod::Flower);
+        assert_eq!(changed.amount, Some(0.25));
+
+        assert!(changed.delete(&conn).is_ok());
+        assert!(Session::filter(&conn, SessionField::UserID(user.id))
+            .unwrap()
+            .is_empty());
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+    }
+
+    #[test]
+    fn session_of_missing_batch_rejected() {
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "missing batch tester".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let session = NewSession {
+            user_id: user.id,
+            batch_id: i32::MAX,
+            consumed_at: None,
+            method: ConsumptionMethod::Flower,
+            amount: Some(0.5),
+            notes: None,
+        };
+        assert!(matches!(
+            session.create(&conn),
+            Err(Error::QueryBuilderError(_))
+        ));
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+    }
+
+    #[test]
+    fn reviews_roll_up_to_batch_and_strain() {
+        let conn = establish_connection().unwrap();
+        let strain = NewStrain {
+            name: "Review Haze".to_owned(),
+            species: Species::Sativa,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(strain.id)
+            .grower_id(1)
+            .thc_content(20.0)
+            .cbd_content(0.1)
+            .build()
+            .create(&conn)
+            .unwrap();
+        let mut reviewers = vec![];
+        for (i, rating) in [5, 4, 4].iter().enumerate() {
+            let user = NewUser {
+                username: format!("reviewer {}", i),
+                password_hash: "not a real hash".to_owned(),
+            }
+            .create(&conn)
+            .unwrap();
+            NewReview {
+                user_id: user.id,
+                batch_id: batch.id,
+                rating: *rating,
+                body: None,
+                effects: vec!["relaxed".to_owned()],
+                flavors: vec!["citrus".to_owned(), "pine".to_owned()],
+            }
+            .create(&conn)
+            .unwrap();
+            reviewers.push(user);
+        }
+
+        let summary = strain.rating(&conn).unwrap();
+        assert_eq!(summary.count, 3);
+        assert_eq!(summary.distribution, [0, 0, 0, 2, 1]);
+        assert!((summary.average.unwrap() - 13.0 / 3.0).abs() < 1e-9);
+
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain_id: Some(strain.id),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(res[0].rating, summary);
+        assert_eq!(
+            Review::filter(&conn, ReviewField::StrainID(strain.id))
+                .unwrap()
+                .len(),
+            3
+        );
+
+        for user in reviewers {
+            diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+        }
+        strain.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn second_review_of_batch_rejected() {
+        use diesel::result::DatabaseErrorKind;
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "repeat reviewer".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let review = NewReview {
+            user_id: user.id,
+            batch_id: 1,
+            rating: 3,
+            body: Some("Fine".to_owned()),
+            effects: vec![],
+            flavors: vec![],
+        };
+        review.create(&conn).unwrap();
+        assert!(matches!(
+            review.create(&conn),
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+        ));
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+    }
+
+    #[test]
+    fn unreviewed_strain_has_empty_rating() {
+        let conn = establish_connection().unwrap();
+        let strain = NewStrain {
+            name: "Unrated OG".to_owned(),
             species: Species::Indica,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .create(&conn)
+        .unwrap();
+        assert_eq!(strain.rating(&conn).unwrap(), RatingSummary::default());
+        strain.delete(&conn).unwrap();
+    }
+
+    #[test]
+    fn session_decrements_inventory() {
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "inventory tester".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let grams = NewInventory {
+            user_id: user.id,
+            batch_id: 1,
+            quantity: 7.0,
+            unit: InventoryUnit::Grams,
+            purchase_price_cents: Some(6000),
+            purchase_date: Some(NaiveDate::from_ymd(2022, 6, 1)),
+            location: Some("Jar on the shelf".to_owned()),
+            low_stock_threshold: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let edibles = NewInventory {
+            user_id: user.id,
+            batch_id: 2,
+            quantity: 3.0,
+            unit: InventoryUnit::Units,
+            purchase_price_cents: None,
+            purchase_date: None,
+            location: None,
+            low_stock_threshold: None,
+        }
+        .create(&conn)
+        .unwrap();
+
+        for (batch, method, amount) in [
+            (1, ConsumptionMethod::Flower, Some(0.5)),
+            (2, ConsumptionMethod::Edible, None),
+        ] {
+            NewSession {
+                user_id: user.id,
+                batch_id: batch,
+                consumed_at: None,
+                method,
+                amount,
+                notes: None,
+            }
+            .create(&conn)
+            .unwrap();
+        }
+
+        let grams = Inventory::find_owned(&conn, grams.id, user.id).unwrap();
+        let edibles = Inventory::find_owned(&conn, edibles.id, user.id).unwrap();
+        assert_eq!(grams.quantity, 6.5);
+        assert_eq!(edibles.quantity, 2.0);
+
+        let low = Inventory::filter(&conn, InventoryField::LowStock(user.id)).unwrap();
+        assert_eq!(low.len(), 1);
+        assert_eq!(low[0].id, edibles.id);
+
+        let summary = InventorySummary::for_user(&conn, user.id).unwrap();
+        assert_eq!(summary.iter().map(|s| s.items).sum::<i64>(), 2);
+        assert_eq!(summary.iter().map(|s| s.grams).sum::<f64>(), 6.5);
+        assert_eq!(summary.iter().map(|s| s.units).sum::<f64>(), 2.0);
+        assert_eq!(summary.iter().map(|s| s.spent_cents).sum::<i64>(), 6000);
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+    }
+
+    #[test]
+    fn inventory_follows_session_edits() {
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "session edit tester".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let stock = |batch, quantity, unit| {
+            NewInventory {
+                user_id: user.id,
+                batch_id: batch,
+                quantity,
+                unit,
+                purchase_price_cents: None,
+                purchase_date: None,
+                location: None,
+                low_stock_threshold: None,
+            }
+            .create(&conn)
+            .unwrap()
         };
-        let strain = new.create(&conn);
+        let grams = stock(1, 7.0, InventoryUnit::Grams);
+        let edibles = stock(2, 3.0, InventoryUnit::Units);
+        let quantity = |item: &Inventory| {
+            Inventory::find_owned(&conn, item.id, user.id)
+                .unwrap()
+                .quantity
+        };
+
+        let session = NewSession {
+            user_id: user.id,
+            batch_id: 1,
+            consumed_at: None,
+            method: ConsumptionMethod::Flower,
+            amount: Some(1.0),
+            notes: None,
+        }
+        .create(&conn)
+        .unwrap();
+        assert_eq!(quantity(&grams), 6.0);
+
+        let session = SessionChanges {
+            amount: Some(2.5),
+            ..Default::default()
+        }
+        .update(&conn, session.id)
+        .unwrap();
+        assert_eq!(quantity(&grams), 4.5);
+
+        let session = SessionChanges {
+            batch_id: Some(2),
+            ..Default::default()
+        }
+        .update(&conn, session.id)
+        .unwrap();
+        assert_eq!(quantity(&grams), 7.0);
+        assert_eq!(quantity(&edibles), 2.0);
+
+        session.delete(&conn).unwrap();
+        assert_eq!(quantity(&edibles), 3.0);
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
+    }
+
+    #[test]
+    fn session_draws_given_back_exactly() {
+        let conn = establish_connection().unwrap();
+        let user = NewUser {
+            username: "session draw tester".to_owned(),
+            password_hash: "not a real hash".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let stock = |grams, bought| {
+            NewInventory {
+                user_id: user.id,
+                batch_id: 1,
+                quantity: grams,
+                unit: InventoryUnit::Grams,
+                purchase_price_cents: None,
+                purchase_date: Some(NaiveDate::from_ymd(2022, 6, bought)),
+                location: None,
+                low_stock_threshold: None,
+            }
+            .create(&conn)
+            .unwrap()
+        };
+        let quantity = |item: &Inventory| {
+            Inventory::find_owned(&conn, item.id, user.id)
+                .unwrap()
+                .quantity
+        };
+        let session = |grams| {
+            NewSession {
+                user_id: user.id,
+                batch_id: 1,
+                consumed_at: None,
+                method: ConsumptionMethod::Flower,
+                amount: Some(grams),
+                notes: None,
+            }
+            .create(&conn)
+            .unwrap()
+        };
+
+        // Logged before any stock was bought, so there is nothing to give back
+        let early = session(1.0);
+        let (first, second) = (stock(1.0, 1), stock(3.0, 2));
 
-        assert!(strain.is_ok());
-        assert_eq!(strain.as_ref().unwrap().species, Species::Indica);
-        diesel::delete(strains.find(strain.as_ref().unwrap().id))
-            .get_result::<Strain>(&conn)
-            .unwrap();
+        // More than the first purchase holds; the rest comes out of the second
+        let big = session(2.0);
+        assert_eq!((quantity(&first), quantity(&second)), (0.0, 2.0));
+        big.delete(&conn).unwrap();
+        assert_eq!((quantity(&first), quantity(&second)), (1.0, 3.0));
+        early.delete(&conn).unwrap();
+        assert_eq!((quantity(&first), quantity(&second)), (1.0, 3.0));
+
+        // Taking more than is left empties the stock, and gives back only what was taken
+        let greedy = session(6.0);
+        assert_eq!((quantity(&first), quantity(&second)), (0.0, 0.0));
+        let greedy = SessionChanges {
+            amount: Some(1.5),
+            ..Default::default()
+        }
+        .update(&conn, greedy.id)
+        .unwrap();
+        assert_eq!((quantity(&first), quantity(&second)), (0.0, 2.5));
+        greedy.delete(&conn).unwrap();
+        assert_eq!((quantity(&first), quantity(&second)), (1.0, 3.0));
+        diesel::delete(users.find(user.id)).execute(&conn).unwrap();
     }
 
     #[test]
-    fn strain_deleted() {
+    fn cannabinoid_panel_totals() {
         let conn = establish_connection().unwrap();
-        let new = NewStrain {
-            name: "Reggie Kush".to_owned(),
-            species: Species::Indica,
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(3)
+            .thc_content(0.8)
+            .cbd_content(0.1)
+            .build()
+            .create(&conn)
+            .unwrap();
+        let new = NewCannabinoids {
+            batch_id: batch.id,
+            thca: Some(24.0),
+            cbda: Some(1.0),
+            cbg: Some(0.9),
+            ..Default::default()
         };
-        let strain = new.create(&conn).unwrap();
-        assert!(strain.delete(&conn).is_ok());
-        let fails = strains.find(strain.id).get_result::<Strain>(&conn);
-        assert!(fails.is_err());
+        let panel = new.create(&conn).unwrap().panel(&conn).unwrap();
+        assert!((panel.total_thc - (0.8 + 24.0 * 0.877)).abs() < 1e-4);
+        assert!((panel.total_cbd - (0.1 + 0.877)).abs() < 1e-4);
+
+        let res = Batch::filter(
+            &conn,
+            BatchFilter {
+                strain_id: Some(3),
+                grower_id: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .into_iter()
+        .find(|b| b.id == batch.id)
+        .unwrap();
+        assert_eq!(res.cannabinoids.unwrap().cbg, Some(0.9));
+        assert_eq!(res.total_thc, panel.total_thc);
+
+        let cleared = NewCannabinoids {
+            batch_id: batch.id,
+            thca: Some(20.0),
+            ..Default::default()
+        }
+        .replace(&conn)
+        .unwrap();
+        assert_eq!(cleared.cbg, None);
+        batch.delete(&conn).unwrap();
     }
 
     #[test]
-    fn all_strains_retrieved() {
+    fn coa_import_all_or_nothing() {
+        use crate::import::{import_coa, parse_rows, CoaFormat};
+
         let conn = establish_connection().unwrap();
-        let all = Strain::all(&conn);
-        assert_ne!(all.as_ref().unwrap().len(), 0);
+        let strain = NewStrain {
+            name: "COA Test Kush".to_owned(),
+            species: Species::Indica,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let grower = NewGrower {
+            name: "COA Test Farms".to_owned(),
+            ..Default::default()
+        }
+        .create(&conn)
+        .unwrap();
+        let csv = b"Strain,Grower,Harvest Date,THC,THCA,CBD,Limonene,Lab Notes\n\
+                    coa test kush,COA TEST FARMS,2022-05-02,0.8,24.1%,ND,0.42,ok\n";
+        let rows = parse_rows(CoaFormat::Csv, csv).unwrap();
+
+        let dry = import_coa(&conn, &rows, true).unwrap();
+        assert!(dry.errors.is_empty());
+        assert_eq!(dry.ignored_columns, vec!["lab_notes"]);
+        assert!(batches
+            .find(dry.created[0].batch.id)
+            .first::<Batch>(&conn)
+            .is_err());
+
+        let mut bad = rows.clone();
+        bad.push(rows[0].clone());
+        bad[1].insert("strain".to_owned(), "No Such Strain".to_owned());
+        let failed = import_coa(&conn, &bad, false).unwrap();
+        assert_eq!(failed.errors[0].row, 2);
+        assert_eq!(failed.errors[0].errors[0].field, "strain");
+        assert!(batches
+            .find(failed.created[0].batch.id)
+            .first::<Batch>(&conn)
+            .is_err());
+
+        let report = import_coa(&conn, &rows, false).unwrap();
+        let imported = &report.created[0];
+        assert_eq!(imported.batch.strain_id, strain.id);
+        assert_eq!(imported.cannabinoids.as_ref().unwrap().thca, Some(24.1));
+        assert_eq!(
+            imported.terpenes.as_ref().unwrap().compounds["limonene"],
+            0.42
+        );
+
+        imported.batch.delete(&conn).unwrap();
+        strain.delete(&conn).unwrap();
+        grower.delete(&conn).unwrap();
     }
 
     #[test]
-    fn strain_filtered_by_id() {
-        use super::StrainField;
-        use crate::models::{Species, Species::*};
+    fn bulk_insert_all_or_nothing() {
         let conn = establish_connection().unwrap();
-        let filtered_by_id = Strain::filter(&conn, StrainField::Id(3)).unwrap();
-        let indicas = Strain::filter(&conn, StrainField::Species(Indica)).unwrap();
-        assert_eq!(filtered_by_id[0].id, 3);
-        assert_eq!(indicas[2].species, Species::Indica);
+        let new = vec![
+            NewGrower {
+                name: "Bulk Farms".to_owned(),
+                ..Default::default()
+            },
+            NewGrower {
+                name: "Cresco".to_owned(),
+                ..Default::default()
+            },
+            NewGrower {
+                name: "".to_owned(),
+                ..Default::default()
+            },
+        ];
+        let errors = match NewGrower::create_all(&conn, &new, OnConflict::Error) {
+            Err(Error::QueryBuilderError(e)) => e.downcast::<ValidationErrors>().unwrap(),
+            Err(e) => panic!("expected validation errors, got {}", e),
+            Ok(_) => panic!("growers with taken names were created"),
+        };
+        let fields: Vec<&str> = errors.0.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["[2].name", "[1].name"]);
+        assert!(
+            Grower::filter(&conn, GrowerField::Name("Bulk Farms".to_owned()))
+                .unwrap()
+                .is_empty()
+        );
+
+        let skipped = NewGrower::create_all(&conn, &new[..2], OnConflict::Skip).unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "Bulk Farms");
+        let updated = NewGrower::create_all(&conn, &new[..2], OnConflict::Update).unwrap();
+        assert_eq!(updated.len(), 2);
+
+        let new_batches = vec![
+            NewBatch::builder()
+                .strain_id(3)
+                .grower_id(skipped[0].id)
+                .build(),
+            NewBatch::builder().strain_id(3).grower_id(999_999).build(),
+        ];
+        assert!(NewBatch::create_all(&conn, &new_batches, OnConflict::Error).is_err());
+        let created = NewBatch::create_all(&conn, &new_batches[..1], OnConflict::Error).unwrap();
+        assert_eq!(created[0].grower_id, skipped[0].id);
+        skipped[0].delete(&conn).unwrap();
     }
 
     #[test]
-    fn strain_filtered_by_species() {
-        use super::StrainField;
-        use crate::models::{Species, Species::*};
+    fn archive_export_walks_chunks() {
+        use crate::archive::{export_chunk, restore, ArchiveTable};
+
         let conn = establish_connection().unwrap();
-        let indicas = Strain::filter(&conn, StrainField::Species(Indica)).unwrap();
-        assert_eq!(indicas[2].species, Species::Indica);
+        let mut exported = vec![];
+        let mut after = 0;
+        while let (records, Some(last)) = export_chunk(&conn, ArchiveTable::Strains, after).unwrap()
+        {
+            exported.extend(records);
+            after = last;
+        }
+        assert_eq!(exported.len(), Strain::all(&conn).unwrap().len());
+
+        let (view, _) = export_chunk(&conn, ArchiveTable::BatchResponses, 0).unwrap();
+        assert_eq!(view.len(), Batch::all(&conn).unwrap().len().min(500));
+
+        let errors = match restore(&conn, exported) {
+            Err(Error::QueryBuilderError(e)) => e.downcast::<ValidationErrors>().unwrap(),
+            Err(e) => panic!("expected validation errors, got {}", e),
+            Ok(_) => panic!("restored into a catalog that has strains"),
+        };
+        assert_eq!(errors.0[0].field, "strains");
     }
 
     #[test]
-    fn strain_filtered_by_name() {
-        use super::StrainField;
+    fn export_reads_one_snapshot() {
+        use crate::archive::{ArchiveTable, ExportSnapshot};
+        use diesel::connection::TransactionManager;
+
         let conn = establish_connection().unwrap();
-        let res = Strain::filter(&conn, StrainField::Name("gaylord OG".to_string())).unwrap();
-        assert_eq!(res[0].name, "Gaylord OG");
+        let reader = establish_connection().unwrap();
+        let snapshot = ExportSnapshot::begin(&reader).unwrap();
+        snapshot.chunk(ArchiveTable::Strains, i32::MAX).unwrap();
+
+        let late = NewStrain {
+            name: "Snapshot Sherbet".to_owned(),
+            species: Species::Hybrid,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let (records, _) = snapshot.chunk(ArchiveTable::Strains, late.id - 1).unwrap();
+        assert!(records.is_empty());
+        snapshot.finish().unwrap();
+
+        let snapshot = ExportSnapshot::begin(&reader).unwrap();
+        let (records, _) = snapshot.chunk(ArchiveTable::Strains, late.id - 1).unwrap();
+        assert_eq!(records.len(), 1);
+        // An export dropped part way leaves no transaction open on its connection
+        drop(snapshot);
+        let depth =
+            TransactionManager::<PgConnection>::get_transaction_depth(reader.transaction_manager());
+        assert_eq!(depth, 0);
+
+        late.delete(&conn).unwrap();
     }
 
     #[test]
-    fn new_batch_created() {
-        use crate::models::NewBatch;
+    fn archive_round_trips_through_restore() {
+        use crate::archive::{export_chunk, restore, ArchiveRecord, ArchiveTable};
+
         let conn = establish_connection().unwrap();
-        let batch = NewBatch::builder()
-            .strain_id(3)
-            .grower_id(3)
-            .thc_content(22.9)
-            .cbd_content(0.2)
-            .build()
-            .create(&conn);
+        let export = || -> Result<(Vec<ArchiveRecord>, Vec<String>), Error> {
+            let mut records = vec![];
+            for table in ArchiveTable::ALL.iter() {
+                let mut after = 0;
+                while let (chunk, Some(last)) = export_chunk(&conn, *table, after)? {
+                    records.extend(chunk);
+                    after = last;
+                }
+            }
+            // Restored panels are given new ids; everything else keeps its own
+            let mut lines: Vec<String> = records
+                .iter()
+                .map(|r| {
+                    let mut line = serde_json::to_value(r).unwrap();
+                    if r.table() == ArchiveTable::Cannabinoids {
+                        line["record"].as_object_mut().unwrap().remove("id");
+                    }
+                    line.to_string()
+                })
+                .collect();
+            lines.sort();
+            Ok((records, lines))
+        };
 
-        assert_eq!(batch.unwrap().strain_id, 3);
+        // Restoring needs an empty catalog, so the catalog is emptied inside a
+        // transaction that is rolled back, with its tables locked from the other tests
+        conn.test_transaction::<_, Error, _>(|| {
+            sql_query(
+                "LOCK TABLE strains, strain_parents, strain_aliases, growers, grower_aliases, \
+                 batches, terpenes, terpene_measurements, cannabinoids, sessions, reviews, \
+                 inventory, listings, price_observations IN EXCLUSIVE MODE",
+            )
+            .execute(&conn)?;
+            let flower = |thc| {
+                NewBatch::builder()
+                    .strain_id(3)
+                    .grower_id(1)
+                    .thc_content(thc)
+                    .cbd_content(0.2)
+                    .build()
+                    .create(&conn)
+            };
+            let early = flower(21.0)?;
+            let hash = NewProduct {
+                format: ProductFormat::Concentrate,
+                final_test_date: None,
+                package_date: None,
+                thc_content: 55.0,
+                cbd_content: 0.0,
+            }
+            .of(&early)
+            .create(&conn)?;
+            // Made from a batch entered after it, so the product is exported first
+            let later = flower(23.0)?;
+            diesel::update(batches.find(hash.id))
+                .set(parent_batch_id.eq(later.id))
+                .execute(&conn)?;
+
+            let (records, before) = export()?;
+            diesel::delete(strains).execute(&conn)?;
+            diesel::delete(growers).execute(&conn)?;
+            let report = restore(&conn, records)?;
+            assert_eq!(report["batches"], Batch::all(&conn)?.len());
+
+            let (_, after) = export()?;
+            assert_eq!(before, after);
+            let restored = batches.find(hash.id).first::<Batch>(&conn)?;
+            assert_eq!(restored.parent_batch_id, Some(later.id));
+            Ok(())
+        });
     }
 
     #[test]
-    fn batch_deleted() {
-        use super::Deletable;
+    fn lineage_follows_parents_and_refuses_cycles() {
         let conn = establish_connection().unwrap();
-        let batch = NewBatch::builder()
-            .strain_id(1)
-            .grower_id(3)
-            .thc_content(32.9)
-            .cbd_content(1.2)
-            .build()
-            .create(&conn)
-            .unwrap();
+        let family: Vec<Strain> = ["Lineage Cake", "Lineage Kush", "Lineage Mints"]
+            .iter()
+            .map(|n| {
+                NewStrain {
+                    name: n.to_string(),
+                    species: Species::Hybrid,
+                    breeder: Some("Seed Junky".to_owned()),
+                    phenotype: None,
+                    indica_percent: None,
+                    sativa_percent: None,
+                }
+                .create(&conn)
+                .unwrap()
+            })
+            .collect();
+        let (cake, kush, mints) = (&family[0], &family[1], &family[2]);
+        let link = |child: &Strain, parent: &Strain| StrainParent {
+            strain_id: child.id,
+            parent_id: parent.id,
+        };
+        link(cake, kush).create(&conn).unwrap();
+        link(kush, mints).create(&conn).unwrap();
 
-        assert!(batch.delete(&conn).is_ok());
+        let tree = cake.clone().lineage(&conn, 3).unwrap();
+        assert_eq!(tree.parents[0].strain.name, "Lineage Kush");
+        assert_eq!(tree.parents[0].parents[0].strain.name, "Lineage Mints");
+        let shallow = cake.clone().lineage(&conn, 1).unwrap();
+        assert!(shallow.parents[0].parents.is_empty());
+        assert_eq!(mints.children(&conn).unwrap()[0].id, kush.id);
+        assert!(Strain::descends_from(&conn, cake.id, mints.id).unwrap());
+
+        assert!(link(mints, cake).create(&conn).is_err());
+        assert!(link(cake, cake).create(&conn).is_err());
+
+        for s in family.iter() {
+            s.delete(&conn).unwrap();
+        }
     }
 
     #[test]
-    fn new_grower_created() {
-        use super::Creatable;
+    fn chemotype_follows_batches() {
         let conn = establish_connection().unwrap();
-        let new = NewGrower {
-            name: "Tegridy Farms".to_string(),
+        let strain = NewStrain {
+            name: "Chemotype Haze".to_owned(),
+            species: Species::Hybrid,
+            breeder: None,
+            phenotype: None,
+            indica_percent: Some(70),
+            sativa_percent: Some(30),
+        }
+        .create(&conn)
+        .unwrap();
+        let grower = NewGrower {
+            name: "Chemotype Farms".to_owned(),
+            ..Default::default()
+        }
+        .create(&conn)
+        .unwrap();
+        assert_eq!(strain.chemotype(&conn).unwrap(), None);
+
+        let batch = |thc: f32, cbd: f32| {
+            NewBatch {
+                strain_id: strain.id,
+                harvest_date: None,
+                final_test_date: None,
+                package_date: None,
+                grower_id: grower.id,
+                thc_content: thc,
+                cbd_content: cbd,
+                format: ProductFormat::Flower,
+                parent_batch_id: None,
+            }
+            .create(&conn)
+            .unwrap()
         };
-        let grower = new.create(&conn);
+        let potent = batch(22.0, 0.5);
+        assert_eq!(strain.chemotype(&conn).unwrap(), Some(Chemotype::TypeI));
+        let filter = StrainFilter {
+            indica_min: Some(60),
+            chemotype: Some(Chemotype::TypeI),
+            ..Default::default()
+        };
+        let found = Strain::page(&conn, filter, &PageQuery::default())
+            .unwrap()
+            .data;
+        assert!(found.iter().any(|s| s.id == strain.id));
+
+        let medical = batch(0.5, 25.0);
+        assert_eq!(strain.chemotype(&conn).unwrap(), Some(Chemotype::TypeII));
+        let filter = StrainFilter {
+            sativa_min: Some(40),
+            ..Default::default()
+        };
+        let found = Strain::page(&conn, filter, &PageQuery::default())
+            .unwrap()
+            .data;
+        assert!(found.iter().all(|s| s.id != strain.id));
+
+        potent.delete(&conn).unwrap();
+        assert_eq!(strain.chemotype(&conn).unwrap(), Some(Chemotype::TypeIII));
+
+        medical.delete(&conn).unwrap();
+        strain.delete(&conn).unwrap();
+        grower.delete(&conn).unwrap();
     }
 
     #[test]
-    fn grower_retrieved_by_name() {
-        use super::Retrievable;
+    fn search_matches_aliases_prefixes_and_typos() {
         let conn = establish_connection().unwrap();
-        let tegridy =
-            Grower::filter(&conn, GrowerField::Name("Tegridy Farms".to_string())).unwrap();
-        assert_eq!(tegridy[0].name, "Tegridy Farms");
+        let strain = NewStrain {
+            name: "Searchable Scout Cookies".to_owned(),
+            species: Species::Hybrid,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let alias = NewStrainAlias {
+            strain_id: strain.id,
+            alias: "SSCQX".to_owned(),
+        }
+        .create(&conn)
+        .unwrap();
+        let taken = NewStrainAlias {
+            strain_id: strain.id,
+            alias: "sscqx".to_owned(),
+        };
+        assert!(taken.create(&conn).is_err());
+        let grower = NewGrower {
+            name: "Searchable Farms".to_owned(),
+            ..Default::default()
+        }
+        .create(&conn)
+        .unwrap();
+
+        let hit = |q: &str| {
+            SearchResults::find(&conn, q, 10)
+                .unwrap()
+                .strains
+                .into_iter()
+                .find(|h| h.id == strain.id)
+        };
+        assert_eq!(hit("sscqx").unwrap().matched, "SSCQX");
+        assert_eq!(hit("Searchable Scout Cookies").unwrap().score, 1.0);
+        assert!(hit("searchab").is_some());
+        assert!(hit("Serchable Scout Cookies").is_some());
+        assert!(hit("zzzzzz").is_none());
+        let found = SearchResults::find(&conn, "searchable farm", 10).unwrap();
+        assert_eq!(found.growers[0].id, grower.id);
+
+        assert_eq!(strain.aliases(&conn).unwrap()[0].id, alias.id);
+        alias.delete(&conn).unwrap();
+        assert!(hit("sscqx").is_none());
+
+        strain.delete(&conn).unwrap();
+        grower.delete(&conn).unwrap();
     }
 
     #[test]
-    fn all_growers_retrieved() {
-        use super::Retrievable;
+    fn strain_stats_group_by_grower_and_month() {
         let conn = establish_connection().unwrap();
-        let _growers = Grower::all(&conn);
-        assert!(_growers.is_ok());
+        let strain = NewStrain {
+            name: "Stats Kush".to_owned(),
+            species: Species::Indica,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let empty = strain.stats(&conn).unwrap();
+        assert_eq!(empty.batch_count, 0);
+        assert_eq!(empty.thc.mean, None);
+
+        let harvest = |d: u32| NaiveDate::from_ymd_opt(2022, 3, d);
+        let batch = |grower: i32, thc: f32, date: Option<NaiveDate>| {
+            NewBatch::builder()
+                .strain_id(strain.id)
+                .grower_id(grower)
+                .thc_content(thc)
+                .cbd_content(0.5)
+                .harvest_date(date)
+                .build()
+                .create(&conn)
+                .unwrap()
+        };
+        let first = batch(1, 20.0, harvest(2));
+        batch(1, 24.0, harvest(20));
+        batch(2, 28.0, None);
+        NewTerpenes::builder()
+            .batch_id(first.id)
+            .limonene(Some(0.6))
+            .build()
+            .create(&conn)
+            .unwrap();
+
+        let stats = strain.stats(&conn).unwrap();
+        assert_eq!(stats.batch_count, 3);
+        assert_eq!(stats.thc.min, Some(20.0));
+        assert_eq!(stats.thc.median, Some(24.0));
+        assert_eq!(stats.cbd.max, Some(0.5));
+        assert_eq!(stats.profiled_batches, 1);
+        assert_eq!(stats.terpenes["limonene"], 0.6);
+        assert_eq!(stats.growers.len(), 2);
+        let cresco = stats.growers.iter().find(|g| g.grower_id == 1).unwrap();
+        assert_eq!(cresco.batch_count, 2);
+        assert_eq!(cresco.thc.mean, Some(22.0));
+        assert_eq!(stats.monthly.len(), 1);
+        assert_eq!(stats.monthly[0].month, harvest(1).unwrap());
+        assert_eq!(stats.monthly[0].batch_count, 2);
+
+        strain.delete(&conn).unwrap();
     }
 
     #[test]
-    fn all_batches_retrieved() {
-        use super::Retrievable;
+    fn growers_filtered_by_profile() {
         let conn = establish_connection().unwrap();
-        let all = Batch::all(&conn).unwrap();
-        assert_ne!(all.len(), 0);
+        let grower = NewGrower {
+            name: "Profile Farms".to_owned(),
+            license_number: Some("CCL18-0003443".to_owned()),
+            state: Some("CA".to_owned()),
+            city: Some("Humboldt".to_owned()),
+            website: Some("https://profile.farm".to_owned()),
+            cultivation_type: Some(CultivationType::LivingSoil),
+            active: true,
+        }
+        .create(&conn)
+        .unwrap();
+        let unlicensed = NewGrower {
+            name: "Unlicensed Farms".to_owned(),
+            license_number: Some("403R-00123".to_owned()),
+            ..Default::default()
+        };
+        assert!(unlicensed.create(&conn).is_err());
+
+        let filter = GrowerFilter {
+            state: Some("ca".to_owned()),
+            cultivation_type: Some(CultivationType::LivingSoil),
+            ..Default::default()
+        };
+        let found = Grower::page(&conn, filter, &PageQuery::default())
+            .unwrap()
+            .data;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, grower.id);
+
+        let retired = GrowerChanges {
+            active: Some(false),
+            ..Default::default()
+        }
+        .update(&conn, grower.id)
+        .unwrap();
+        assert_eq!(retired.city.as_deref(), Some("Humboldt"));
+        let filter = GrowerFilter {
+            state: Some("CA".to_owned()),
+            active: Some(true),
+            ..Default::default()
+        };
+        assert!(Grower::page(&conn, filter, &PageQuery::default())
+            .unwrap()
+            .data
+            .is_empty());
+
+        grower.delete(&conn).unwrap();
     }
 
     #[test]
-    fn batch_filtered_by_strain_name() {
-        use super::Retrievable;
+    fn grower_patch_checked_against_stored_license() {
         let conn = establish_connection().unwrap();
-        let res = Batch::filter(&conn, BatchField::Strain("Blackwater OG")).unwrap();
-        assert_eq!(res[0].strain, "Blackwater OG");
+        let grower = NewGrower {
+            name: "Patched License Farms".to_owned(),
+            license_number: Some("CCL18-0003443".to_owned()),
+            state: Some("CA".to_owned()),
+            ..Default::default()
+        }
+        .create(&conn)
+        .unwrap();
+
+        // The stored state is enough for a new license number
+        let relicensed = GrowerChanges {
+            license_number: Some("CCL19-0001234".to_owned()),
+            ..Default::default()
+        }
+        .update(&conn, grower.id)
+        .unwrap();
+        assert_eq!(relicensed.license_number.as_deref(), Some("CCL19-0001234"));
+
+        let misfiled = GrowerChanges {
+            license_number: Some("403R-00123".to_owned()),
+            ..Default::default()
+        }
+        .update(&conn, grower.id);
+        assert!(matches!(misfiled, Err(Error::QueryBuilderError(_))));
+
+        // Moving states re-checks the stored license number, and rolls back on failure
+        let moved = GrowerChanges {
+            state: Some("CO".to_owned()),
+            ..Default::default()
+        }
+        .update(&conn, grower.id);
+        assert!(matches!(moved, Err(Error::QueryBuilderError(_))));
+        let stored: Grower = growers.find(grower.id).first(&conn).unwrap();
+        assert_eq!(stored.state.as_deref(), Some("CA"));
+        assert_eq!(stored.license_number.as_deref(), Some("CCL19-0001234"));
+
+        stored.delete(&conn).unwrap();
     }
 
     #[test]
-    fn batch_filtered_by_strain_id() {
-        use super::Retrievable;
+    fn scorecard_measures_consistency() {
         let conn = establish_connection().unwrap();
-        let res = Batch::filter(&conn, BatchField::StrainID(3)).unwrap();
-        assert_eq!(res[0].strain, "Blackwater OG".to_owned());
+        let grower = NewGrower {
+            name: "Scorecard Farms".to_owned(),
+            ..Default::default()
+        }
+        .create(&conn)
+        .unwrap();
+        let day = |d: u32| NaiveDate::from_ymd_opt(2022, 4, d);
+        let batch = |strain: i32, thc: f32, dates: bool| {
+            NewBatch::builder()
+                .strain_id(strain)
+                .grower_id(grower.id)
+                .thc_content(thc)
+                .cbd_content(0.1)
+                .harvest_date(day(1))
+                .final_test_date(if dates { day(5) } else { None })
+                .package_date(day(11))
+                .build()
+                .create(&conn)
+                .unwrap()
+        };
+        let first = batch(1, 20.0, true);
+        batch(1, 22.0, false);
+        batch(3, 25.0, true);
+        NewTerpenes::builder()
+            .batch_id(first.id)
+            .limonene(Some(0.5))
+            .build()
+            .create(&conn)
+            .unwrap();
+
+        let card = grower.scorecard(&conn).unwrap();
+        assert_eq!(card.score.strain_count, 2);
+        assert_eq!(card.score.batch_count, 3);
+        assert_eq!(card.score.mean_thc_variance, Some(2.0));
+        assert_eq!(card.score.package_lag_days, Some(10.0));
+        assert!((card.score.profiled_share.unwrap() - 1.0 / 3.0).abs() < 1e-6);
+        assert!((card.score.complete_dates_share.unwrap() - 2.0 / 3.0).abs() < 1e-6);
+        let wedding_cake = card.strains.iter().find(|s| s.strain_id == 1).unwrap();
+        assert_eq!(wedding_cake.thc_variance, Some(2.0));
+        assert!(card.strains.iter().any(|s| s.thc_variance.is_none()));
+
+        let board = Grower::leaderboard(&conn, ScoreMetric::Consistency, 3, 100).unwrap();
+        assert!(board.iter().any(|g| g.grower_id == grower.id));
+        assert!(board.iter().all(|g| g.batch_count >= 3));
+        // A steadier grower left out by `min_batches` takes up no rank
+        let steady = NewGrower {
+            name: "Steady Farms".to_owned(),
+            ..Default::default()
+        }
+        .create(&conn)
+        .unwrap();
+        for _ in 0..2 {
+            NewBatch::builder()
+                .strain_id(1)
+                .grower_id(steady.id)
+                .thc_content(21.0)
+                .cbd_content(0.1)
+                .build()
+                .create(&conn)
+                .unwrap();
+        }
+        let board = Grower::leaderboard(&conn, ScoreMetric::Consistency, 3, 100).unwrap();
+        assert!(board.iter().all(|g| g.grower_id != steady.id));
+        for g in &board {
+            let better = board
+                .iter()
+                .filter(|o| match (o.mean_thc_variance, g.mean_thc_variance) {
+                    (Some(a), Some(b)) => a < b,
+                    (Some(_), None) => true,
+                    _ => false,
+                })
+                .count();
+            assert_eq!(g.rank, better as i64 + 1);
+        }
+        steady.delete(&conn).unwrap();
+        let board = Grower::leaderboard(&conn, ScoreMetric::Batches, 1, 100).unwrap();
+        assert!(board
+            .windows(2)
+            .all(|w| w[0].batch_count >= w[1].batch_count));
+
+        grower.delete(&conn).unwrap();
     }
 
     #[test]
-    fn batch_filtered_by_grower_id() {
+    fn listings_sorted_by_price_per_gram() {
         let conn = establish_connection().unwrap();
-        let res = Batch::filter(&conn, BatchField::GrowerID(3)).unwrap();
-        assert_eq!(res[0].grower, "Summa");
+        let shop = NewDispensary {
+            name: "Where To Buy Dispensary".to_owned(),
+            address: None,
+            license_number: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(1)
+            .thc_content(21.0)
+            .cbd_content(0.1)
+            .build()
+            .create(&conn)
+            .unwrap();
+        let listing = |format: ProductFormat, weight_grams: f32, price_cents: i32| {
+            NewListing {
+                dispensary_id: shop.id,
+                batch_id: batch.id,
+                format,
+                weight_grams,
+                price_cents,
+                in_stock: None,
+                observed_at: None,
+            }
+            .create(&conn)
+            .unwrap()
+        };
+        let eighth = listing(ProductFormat::Flower, 3.5, 4500);
+        let ounce = listing(ProductFormat::Flower, 28.0, 20000);
+        let pre_roll = listing(ProductFormat::PreRoll, 1.0, 1000);
+        let sold_out = listing(ProductFormat::Flower, 7.0, 100);
+        ListingChanges {
+            in_stock: Some(false),
+            ..Default::default()
+        }
+        .update(&conn, sold_out.id)
+        .unwrap();
+
+        let strain = strains.find(3).first::<Strain>(&conn).unwrap();
+        let ids = |found: Vec<StrainListing>| -> Vec<i32> {
+            found
+                .into_iter()
+                .filter(|l| l.dispensary_id == shop.id)
+                .map(|l| l.listing_id)
+                .collect()
+        };
+        let all = ids(strain.where_to_buy(&conn, None).unwrap());
+        assert_eq!(all, vec![ounce.id, pre_roll.id, eighth.id]);
+        let flower = ids(strain
+            .where_to_buy(&conn, Some(ProductFormat::Flower))
+            .unwrap());
+        assert_eq!(flower, vec![ounce.id, eighth.id]);
+
+        let filter = ListingFilter {
+            dispensary_id: Some(shop.id),
+            in_stock: Some(false),
+            ..Default::default()
+        };
+        let out = Listing::page(&conn, filter, &PageQuery::default())
+            .unwrap()
+            .data;
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].id, sold_out.id);
+
+        shop.delete(&conn).unwrap();
+        assert!(listings.find(eighth.id).first::<Listing>(&conn).is_err());
+        batch.delete(&conn).unwrap();
     }
+
     #[test]
-    fn batch_filtered_by_grower_name() {
+    fn prices_tracked_and_ranked_by_value() {
         let conn = establish_connection().unwrap();
-        let res = Batch::filter(&conn, BatchField::Grower("Summa")).unwrap();
-        assert_eq!(res[0].grower, "Summa");
+        let shop = NewDispensary {
+            name: "Value Dispensary".to_owned(),
+            address: None,
+            license_number: None,
+        }
+        .create(&conn)
+        .unwrap();
+        let batch = |thc: f32| {
+            NewBatch::builder()
+                .strain_id(3)
+                .grower_id(1)
+                .thc_content(thc)
+                .cbd_content(0.1)
+                .build()
+                .create(&conn)
+                .unwrap()
+        };
+        let strong = batch(25.0);
+        let weak = batch(12.5);
+        // A lab that reports THCA rather than decarboxylated THC
+        let lab = batch(0.8);
+        NewCannabinoids {
+            batch_id: lab.id,
+            thca: Some(24.0),
+            ..Default::default()
+        }
+        .create(&conn)
+        .unwrap();
+        let day = |d: u32| Some(NaiveDate::from_ymd(2022, 7, d).and_hms(12, 0, 0));
+        let listing = |batch: &Batch, price_cents: i32| {
+            NewListing {
+                dispensary_id: shop.id,
+                batch_id: batch.id,
+                format: ProductFormat::Flower,
+                weight_grams: 4.0,
+                price_cents,
+                in_stock: None,
+                observed_at: day(1),
+            }
+            .create(&conn)
+            .unwrap()
+        };
+        let strong_listing = listing(&strong, 5000);
+        let weak_listing = listing(&weak, 3000);
+        let lab_listing = listing(&lab, 3000);
+        ListingChanges {
+            price_cents: Some(4000),
+            observed_at: day(8),
+            ..Default::default()
+        }
+        .update(&conn, strong_listing.id)
+        .unwrap();
+        NewPriceObservation {
+            batch_id: strong.id,
+            listing_id: None,
+            weight_grams: 3.5,
+            price_cents: 3500,
+            observed_at: day(15),
+        }
+        .create(&conn)
+        .unwrap();
+
+        let history = strong.prices(&conn, PriceHistoryFilter::default()).unwrap();
+        let prices: Vec<i32> = history.iter().map(|p| p.price_cents).collect();
+        assert_eq!(prices, vec![5000, 4000, 3500]);
+        assert_eq!(history[0].dispensary.as_deref(), Some("Value Dispensary"));
+        assert_eq!(history[1].price_per_gram_cents, 1000.0);
+        assert_eq!(history[1].price_per_gram_thc_cents, Some(4000.0));
+        assert!(history[2].listing_id.is_none());
+        let filter = PriceHistoryFilter {
+            from: NaiveDate::from_ymd_opt(2022, 7, 8),
+            to: NaiveDate::from_ymd_opt(2022, 7, 8),
+        };
+        assert_eq!(strong.prices(&conn, filter).unwrap().len(), 1);
+        let total_thc = 0.8 + DECARB_FACTOR * 24.0;
+        let lab_price = lab.prices(&conn, PriceHistoryFilter::default()).unwrap()[0]
+            .price_per_gram_thc_cents
+            .unwrap();
+        assert!((lab_price - 3000.0 / (4.0 * total_thc / 100.0)).abs() < 0.5);
+
+        let filter = ValueFilter {
+            strain_id: Some(3),
+            ..Default::default()
+        };
+        let ranked: Vec<ListingValue> = Listing::by_value(&conn, filter, 100)
+            .unwrap()
+            .into_iter()
+            .filter(|v| v.dispensary_id == shop.id)
+            .collect();
+        let ids: Vec<i32> = ranked.iter().map(|v| v.listing_id).collect();
+        assert_eq!(
+            ids,
+            vec![lab_listing.id, strong_listing.id, weak_listing.id]
+        );
+        assert_eq!(ranked[0].thc_content, 0.8);
+        assert!((ranked[0].total_thc - total_thc).abs() < 1e-4);
+        let filter = ValueFilter {
+            species: Some(Species::Indica),
+            strain_id: Some(3),
+            ..Default::default()
+        };
+        let strain = strains.find(3).first::<Strain>(&conn).unwrap();
+        let found = Listing::by_value(&conn, filter, 100).unwrap();
+        assert_eq!(found.is_empty(), strain.species != Species::Indica);
+
+        shop.delete(&conn).unwrap();
+        assert_eq!(
+            strong
+                .prices(&conn, PriceHistoryFilter::default())
+                .unwrap()
+                .len(),
+            3
+        );
+        strong.delete(&conn).unwrap();
+        weak.delete(&conn).unwrap();
+        lab.delete(&conn).unwrap();
     }
 
     #[test]
-    fn batch_filtered_by_date() {
+    fn products_derived_from_flower() {
         let conn = establish_connection().unwrap();
-        let res = Batch::filter(
-            &conn,
-            BatchField::HarvestDate(NaiveDate::from_ymd(2022, 1, 10)),
-        )
-        .unwrap();
-        assert_eq!(res[0].harvest_date, Some(NaiveDate::from_ymd(2022, 1, 10)));
+        let flower = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(1)
+            .thc_content(22.0)
+            .cbd_content(0.2)
+            .build()
+            .create(&conn)
+            .unwrap();
+        let product = |format: ProductFormat, thc: f32| NewProduct {
+            format,
+            final_test_date: None,
+            package_date: None,
+            thc_content: thc,
+            cbd_content: 0.0,
+        };
+        let rosin = product(ProductFormat::Concentrate, 72.0)
+            .of(&flower)
+            .create(&conn)
+            .unwrap();
+        let gummy = product(ProductFormat::Edible, 10.0)
+            .of(&flower)
+            .create(&conn)
+            .unwrap();
+        assert_eq!(gummy.strain_id, flower.strain_id);
+        assert_eq!(gummy.parent_batch_id, Some(flower.id));
+
+        // Products are only made from flower, and never are flower themselves
+        assert!(product(ProductFormat::Vape, 80.0)
+            .of(&gummy)
+            .create(&conn)
+            .is_err());
+        assert!(product(ProductFormat::Flower, 20.0)
+            .of(&flower)
+            .create(&conn)
+            .is_err());
+        let changes = BatchChanges {
+            format: Some(ProductFormat::PreRoll),
+            ..Default::default()
+        };
+        assert!(changes.update(&conn, flower.id).is_err());
+        // The stored format decides the unit of a lone potency change
+        let changes = BatchChanges {
+            thc_content: Some(150.0),
+            ..Default::default()
+        };
+        assert!(changes.update(&conn, rosin.id).is_err());
+        assert_eq!(changes.update(&conn, gummy.id).unwrap().thc_content, 150.0);
+
+        let filter = BatchFilter {
+            parent_batch_id: Some(flower.id),
+            ..Default::default()
+        };
+        let products = Batch::filter(&conn, filter).unwrap();
+        let units: Vec<PotencyUnit> = products.iter().map(|p| p.potency_unit).collect();
+        assert_eq!(units, vec![PotencyUnit::Percent, PotencyUnit::MgPerUnit]);
+
+        // Products don't count towards the strain's flower potency
+        let stats = strains
+            .find(3)
+            .first::<Strain>(&conn)
+            .unwrap()
+            .stats(&conn)
+            .unwrap();
+        assert!(stats.thc.max.unwrap() < 72.0);
+
+        flower.delete(&conn).unwrap();
+        let orphan = batches.find(gummy.id).first::<Batch>(&conn).unwrap();
+        assert_eq!(orphan.parent_batch_id, None);
+        orphan.delete(&conn).unwrap();
+        rosin.delete(&conn).unwrap();
     }
 }
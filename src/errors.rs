@@ -0,0 +1,171 @@
+use super::validation::ValidationErrors;
+
+use actix_web::error::BlockingError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use diesel::r2d2::PoolError;
+use diesel::result::{DatabaseErrorKind, Error};
+use serde_json::json;
+
+use std::fmt;
+
+/// Every way a request can fail. Handlers return `Result<HttpResponse, ApiError>` and actix
+/// renders the error with the same envelope as a successful response:
+///
+/// Ex:
+///     `{"message": "Username is taken", "status code": 409}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    NotFound(String),
+    Conflict(String),
+    Unprocessable(String),
+    /// Request body broke one or more `Validate` rules
+    Invalid(ValidationErrors),
+    Internal(String),
+    Unavailable(String),
+}
+
+impl ApiError {
+    fn message(&self) -> &str {
+        match self {
+            ApiError::BadRequest(m)
+            | ApiError::Unauthorized(m)
+            | ApiError::NotFound(m)
+            | ApiError::Conflict(m)
+            | ApiError::Unprocessable(m)
+            | ApiError::Internal(m)
+            | ApiError::Unavailable(m) => m,
+            ApiError::Invalid(_) => "Validation failed",
+        }
+    }
+
+    /// Replace the message of a 404 with one that names what was missing
+    pub fn on_not_found(self, message: &str) -> ApiError {
+        match self {
+            ApiError::NotFound(_) => ApiError::NotFound(message.to_owned()),
+            e => e,
+        }
+    }
+
+    /// Replace the database's message for a 409 with one meant for the client
+    pub fn on_conflict(self, message: &str) -> ApiError {
+        match self {
+            ApiError::Conflict(_) => ApiError::Conflict(message.to_owned()),
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unprocessable(_) | ApiError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        match self {
+            ApiError::Invalid(errors) => HttpResponse::build(status).json(json!({
+                "message": self.message(),
+                "errors": errors,
+                "status code": status.as_u16()
+            })),
+            _ => HttpResponse::build(status)
+                .json(json!({ "message": self.message(), "status code": status.as_u16() })),
+        }
+    }
+}
+
+/// A missing record is a 404, a duplicate is a 409, a dangling foreign key or a record
+/// that fails validation is a 422 and a query the client built badly (an unknown `sort`
+/// or an empty PATCH) is a 400.
+impl From<Error> for ApiError {
+    fn from(e: Error) -> ApiError {
+        match e {
+            Error::NotFound => ApiError::NotFound("NotFound".to_owned()),
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                ApiError::Conflict(info.message().to_owned())
+            }
+            Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                ApiError::Unprocessable(info.message().to_owned())
+            }
+            Error::QueryBuilderError(msg) => match msg.downcast::<ValidationErrors>() {
+                Ok(errors) => ApiError::Invalid(*errors),
+                Err(msg) => ApiError::BadRequest(msg.to_string()),
+            },
+            _ => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+/// No connection could be checked out of the pool in time
+impl From<PoolError> for ApiError {
+    fn from(e: PoolError) -> ApiError {
+        ApiError::Unavailable(format!("Database unavailable: {}", e))
+    }
+}
+
+impl<E> From<BlockingError<E>> for ApiError
+where
+    E: Into<ApiError> + fmt::Debug,
+{
+    fn from(e: BlockingError<E>) -> ApiError {
+        match e {
+            BlockingError::Error(e) => e.into(),
+            BlockingError::Canceled => ApiError::Unavailable("Request was canceled".to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diesel_errors_mapped() {
+        assert_eq!(
+            ApiError::from(Error::NotFound).status_code(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            ApiError::from(BlockingError::Error(Error::RollbackTransaction)).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            ApiError::from(BlockingError::<Error>::Canceled).status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn validation_errors_unpacked() {
+        let mut errors = ValidationErrors::default();
+        errors.percent("thc_content", 340.0);
+        let e = ApiError::from(Error::from(errors.clone()));
+        assert_eq!(e, ApiError::Invalid(errors));
+        assert_eq!(e.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn messages_replaced_by_kind() {
+        let e = ApiError::from(Error::NotFound)
+            .on_conflict("Already exists")
+            .on_not_found("No Terpenes Found");
+        assert_eq!(e, ApiError::NotFound("No Terpenes Found".to_owned()));
+    }
+}
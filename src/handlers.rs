@@ -1,10 +1,33 @@
+use super::archive::{self, ArchiveFormat, ArchiveTable, ExportSnapshot};
+use super::auth::{self, AuthUser, TokenSecret};
 use super::db::*;
-use super::models::{Batch, Grower, NewBatch, NewGrower, NewStrain, Species, Strain};
+use super::errors::ApiError;
+use super::import::{self, CoaFormat};
+use super::models::{
+    AuthToken, Batch, BatchChanges, BatchResponse, CannabinoidPanel, Cannabinoids,
+    CompactBatchResponse, Credentials, Dispensary, DispensaryChanges, Grower, GrowerAlias,
+    GrowerChanges, Inventory, InventoryChanges, InventorySummary, Listing, ListingChanges,
+    NewBatch, NewCannabinoids, NewDispensary, NewGrower, NewGrowerAlias, NewInventory, NewListing,
+    NewPriceObservation, NewProduct, NewReview, NewSession, NewStrain, NewStrainAlias,
+    NewTerpeneCompound, NewTerpenes, NewUser, ProductFormat, Review, ReviewChanges, SearchResults,
+    Session, SessionChanges, Strain, StrainAlias, StrainChanges, StrainParent, StrainResponse,
+    TerpeneCompound, Terpenes, User,
+};
+use super::schema::batches::dsl::batches;
+use super::schema::dispensaries::dsl::dispensaries;
+use super::schema::grower_aliases::dsl::{grower_aliases, grower_id as aliased_grower};
 use super::schema::growers::dsl::{growers, id as gid};
+use super::schema::listings::dsl::listings;
+use super::schema::strain_aliases::dsl::{strain_aliases, strain_id as aliased_strain};
 use super::schema::strains::dsl::{id as sid, strains};
 use super::DbPool;
-use actix_web::{get, post, web, HttpResponse, Responder};
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use actix_web::error::BlockingError;
+use actix_web::http::header::CONTENT_DISPOSITION;
+use actix_web::web::Bytes;
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse};
+use diesel::result::Error;
+use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+use futures::stream;
 
 use diesel::sql_query;
 use diesel::sql_types::Integer;
@@ -14,30 +37,224 @@ use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+/// `?format=csv|json` names the format of an uploaded COA export; `?dry_run=true` checks
+/// every row without saving anything
 #[derive(Debug, Deserialize, Clone)]
-struct StrainQuery {
-    name: Option<String>,
-    species: Option<Species>,
+struct ImportQuery {
+    format: Option<CoaFormat>,
+    dry_run: Option<bool>,
 }
 
+/// `?format=csv|jsonl` and, optionally, the one `table` to export or import
 #[derive(Debug, Deserialize, Clone)]
-struct GrowerQuery {
-    name: Option<String>,
+struct ArchiveQuery {
+    format: Option<ArchiveFormat>,
+    table: Option<ArchiveTable>,
 }
 
+/// `?depth=N` limits a lineage to N generations of ancestors
+#[derive(Debug, Deserialize, Clone)]
+struct LineageQuery {
+    depth: Option<i32>,
+}
+
+/// Generations of ancestors in a lineage when `depth` isn't given
+const DEFAULT_LINEAGE_DEPTH: i32 = 3;
+
+/// Deepest lineage a single request may ask for
+const MAX_LINEAGE_DEPTH: i32 = 10;
+
+/// Body of a request adding a parent to a strain
+#[derive(Debug, Deserialize, Clone)]
+struct ParentBody {
+    parent_id: i32,
+}
+
+/// Body of a request adding an alias to a strain or grower
+#[derive(Debug, Deserialize, Clone)]
+struct AliasBody {
+    alias: String,
+}
+
+/// `?q=` is the text to look for; `?limit=N` caps the matches of each kind
+#[derive(Debug, Deserialize, Clone)]
+struct SearchQuery {
+    q: String,
+    limit: Option<i64>,
+}
+
+/// Matches of each kind a search returns when `limit` isn't given
+const DEFAULT_SEARCH_LIMIT: i64 = 10;
+
+/// Most matches of each kind a single search may ask for
+const MAX_SEARCH_LIMIT: i64 = 100;
+
+/// `?by=` names the metric to rank growers by; `?min_batches=N` leaves out growers with
+/// fewer batches and `?limit=N` caps the number ranked
+#[derive(Debug, Deserialize, Clone)]
+struct LeaderboardQuery {
+    by: Option<ScoreMetric>,
+    min_batches: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// Growers on the leaderboard when `limit` isn't given
+const DEFAULT_LEADERBOARD_LIMIT: i64 = 10;
+
+/// Most growers a single leaderboard request may ask for
+const MAX_LEADERBOARD_LIMIT: i64 = 100;
+
+/// `?on_conflict=skip|update` lets a bulk insert step around names that are already taken
+#[derive(Debug, Deserialize, Clone)]
+struct BulkQuery {
+    on_conflict: Option<OnConflict>,
+}
+
+/// Most records one bulk request may create, which keeps a batch insert well under
+/// Postgres' limit on bind parameters
+const MAX_BULK_RECORDS: usize = 1000;
+
+/// `?cascade=true` confirms that deleting a strain or grower may also delete its batches
+#[derive(Debug, Deserialize, Clone)]
+struct CascadeQuery {
+    cascade: Option<bool>,
+}
+
+/// Query parameters shared by every route that returns `BatchResponse`s.
+/// `?compact=true` drops the ids and nested terpene profile.
+#[derive(Debug, Deserialize, Clone)]
+struct BatchResponseQuery {
+    compact: Option<bool>,
+}
+
+impl BatchResponseQuery {
+    fn render(&self, res: BatchResponse) -> serde_json::Value {
+        match self.compact {
+            Some(true) => json!(CompactBatchResponse::from(res)),
+            _ => json!(res),
+        }
+    }
+}
+
+/// Build the URL of another page of the current request, keeping every other parameter
+fn page_link(req: &HttpRequest, limit: usize, offset: usize) -> String {
+    let paging = format!("limit={}&offset={}", limit, offset);
+    let mut params: Vec<&str> = req
+        .query_string()
+        .split('&')
+        .filter(|p| !p.is_empty() && !p.starts_with("limit=") && !p.starts_with("offset="))
+        .collect();
+    params.push(&paging);
+    format!("{}?{}", req.path(), params.join("&"))
+}
+
+/// Wrap a single record in the success envelope.
+///
+/// Ex:
+///     `{"data": {"id": 6, "name": "Tegridy Farms"}, "status code": 200}`
+fn ok<T: Serialize>(data: T) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "data": data, "status code": 200 }))
+}
+
+/// Like `ok`, for a record the request just created
+fn created<T: Serialize>(data: T) -> HttpResponse {
+    HttpResponse::Created().json(json!({ "data": data, "status code": 201 }))
+}
+
+/// The records of a bulk request, as long as there is at least one and not too many
+fn bulk_records<T>(data: web::Json<Vec<T>>) -> Result<Vec<T>, ApiError> {
+    match data.len() {
+        0 => Err(ApiError::BadRequest("No records to create".to_owned())),
+        n if n > MAX_BULK_RECORDS => Err(ApiError::BadRequest(format!(
+            "At most {} records can be created at once",
+            MAX_BULK_RECORDS
+        ))),
+        _ => Ok(data.into_inner()),
+    }
+}
+
+/// Wrap one page of records in the list envelope, with the total number of matching
+/// records and links to the next and previous pages (`null` at either end).
+///
+/// Ex:
+///     `{"data": [...], "total": 240, "limit": 100, "offset": 100,
+///       "links": {"next": "/strains?limit=100&offset=200", "prev": "/strains?limit=100&offset=0"},
+///       "status code": 200}`
+fn page_response<T: Serialize>(req: &HttpRequest, page: Page<T>) -> HttpResponse {
+    let next = if page.has_next() {
+        Some(page_link(req, page.limit, page.offset + page.limit))
+    } else {
+        None
+    };
+    let prev = if page.has_prev() {
+        Some(page_link(
+            req,
+            page.limit,
+            page.offset.saturating_sub(page.limit),
+        ))
+    } else {
+        None
+    };
+    HttpResponse::Ok().json(json!({
+        "data": page.data,
+        "total": page.total,
+        "limit": page.limit,
+        "offset": page.offset,
+        "links": { "next": next, "prev": prev },
+        "status code": 200
+    }))
+}
+
+/// Respond to a strain/grower DELETE. `Err(n)` means the record still has `n` batches
+/// and the caller didn't pass `?cascade=true`, so nothing was removed.
+fn cascade_response<T: Serialize>(
+    res: Result<(T, i64), i64>,
+    kind: &str,
+) -> Result<HttpResponse, ApiError> {
+    match res {
+        Ok((deleted, n)) => Ok(HttpResponse::Ok().json(json!({
+            "data": deleted,
+            "batches deleted": n,
+            "status code": 200
+        }))),
+        Err(n) => Err(ApiError::Conflict(format!(
+            "Deleting this {} would also delete {} batches. Pass ?cascade=true to confirm.",
+            kind, n
+        ))),
+    }
+}
+
+/// A missing batch or profile is reported as "No Terpenes Found" and a second profile
+/// for the same batch as a conflict
+fn terpenes_error(e: BlockingError<Error>) -> ApiError {
+    ApiError::from(e)
+        .on_not_found("No Terpenes Found")
+        .on_conflict("Batch already has a terpene profile")
+}
+
+/// Batches of grower {id}. Responds with 404 if the grower doesn't exist and with an
+/// empty page if it has no batches.
 #[get("/growers/{id}/batches")]
-async fn get_batches_by_grower_id(pool: web::Data<DbPool>, path: web::Path<i32>) -> impl Responder {
-    let conn = pool.get().expect("Could not get connection.");
-    web::block(move || Batch::filter(&conn, BatchField::GrowerID(path.0)))
-        .await
-        .map(|res| match res.len() {
-            0 => HttpResponse::NotFound().json(json!({"404": "No Batches Found"})),
-            _ => HttpResponse::Ok().json(json!({ "data": res, "status code": 200 })),
-        })
-        .map_err(|e| {
-            HttpResponse::InternalServerError()
-                .json(json!({"message": e.to_string(), "status code": 500 }))
-        })
+async fn get_batches_by_grower_id(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    filter: web::Query<BatchFilter>,
+    page: web::Query<PageQuery>,
+    query: web::Query<BatchResponseQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let filter = BatchFilter {
+        grower_id: Some(path.0),
+        ..filter.into_inner()
+    };
+    web::block(move || {
+        growers.find(path.0).first::<Grower>(&conn)?;
+        Batch::page(&conn, filter, &page)
+    })
+    .await
+    .map(|res| page_response(&req, res.map(|b| query.render(b))))
+    .map_err(ApiError::from)
 }
 
 /// Make a POST request to create a new `Grower` object.
@@ -50,149 +267,1875 @@ async fn get_batches_by_grower_id(pool: web::Data<DbPool>, path: web::Path<i32>)
 ///      $ localhost:8008/growers`
 ///
 ///     Response:
-///     `{"201": {"id":30, "name":"High Guys"}}`
+///     `{"data": {"id":30, "name":"High Guys"}, "status code": 201}`
 #[post("/growers")]
-async fn post_new_grower(pool: web::Data<DbPool>, data: web::Json<NewGrower>) -> impl Responder {
+async fn post_new_grower(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewGrower>,
+) -> Result<HttpResponse, ApiError> {
     let grower = data.into_inner();
-    let conn = pool.get().expect("Could not get connection.");
+    let conn = pool.get()?;
     web::block(move || grower.create(&conn))
         .await
-        .map(|g| HttpResponse::Ok().json(json!({ "data": g, "status code": 201 })))
-        .map_err(|e| {
-            HttpResponse::InternalServerError()
-                .json(json!({"message": e.to_string(), "status code": 500 }))
-        })
+        .map(created)
+        .map_err(ApiError::from)
 }
 
-/// Retrieve a list of all growers or a subset of them that match a given query.
-/// Please select one field to query by passing in either `id` or `name` but not both.
+/// Create every grower in the body or none of them
+#[post("/growers/bulk")]
+async fn post_new_growers(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    query: web::Query<BulkQuery>,
+    data: web::Json<Vec<NewGrower>>,
+) -> Result<HttpResponse, ApiError> {
+    let new = bulk_records(data)?;
+    let on_conflict = query.on_conflict.unwrap_or_default();
+    let conn = pool.get()?;
+    web::block(move || NewGrower::create_all(&conn, &new, on_conflict))
+        .await
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+/// Retrieve a list of all growers or the subset matching every given criterion: `name`,
+/// `state`, `cultivation_type` (`Indoor`, `Outdoor`, `Greenhouse` or `LivingSoil`) and
+/// `active`.
 ///
 /// Ex:
 ///     Request:
-///     `$ curl localhost:8008/growers?name=Tegridy%20Farms`
+///     `$ curl localhost:8008/growers?state=CA&cultivation_type=LivingSoil`
 ///
 ///     Response:
-///     `$ {"200":[{"id":6,"name":"Tegridy Farms"}, {"id":42,"name":"Stuco"}]}`
+///     `$ {"data":[{"id":6,"name":"Tegridy Farms","state":"CA",...}], "total": 1, ..., "status code": 200}`
 #[get("/growers")]
-async fn query_growers(pool: web::Data<DbPool>, query: web::Query<GrowerQuery>) -> impl Responder {
-    let conn = pool.get().expect("Could not get connection.");
-    web::block(move || match query.0.name {
-        Some(n) => Grower::filter(&conn, GrowerField::Name(n)),
-        None => Grower::all(&conn),
+async fn query_growers(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    filter: web::Query<GrowerFilter>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Grower::page(&conn, filter.into_inner(), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Get grower by {id}
+#[get("/growers/{id}")]
+async fn get_grower_by_id(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || growers.find(path.0).first::<Grower>(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// How consistently grower {id} produces: strains and batches grown, the variance of THC
+/// within each strain, the mean days from harvest to package, and the share of its
+/// batches with a terpene profile or with every date filled in
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/growers/3/scorecard`
+#[get("/growers/{id}/scorecard")]
+async fn get_grower_scorecard(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        growers
+            .find(path.0)
+            .first::<Grower>(&conn)?
+            .scorecard(&conn)
     })
     .await
-    .map(|res| match res.len() {
-        0 => HttpResponse::NotFound()
-            .json(json!({"message": "No Growers Found", "status code": 404 })),
-        _ => HttpResponse::Ok().json(json!({ "data": res, "Status Code": 200 })),
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// Growers ranked by one scorecard metric: `consistency` (the default), `batches`,
+/// `strains`, `package_lag`, `profiled` or `complete_dates`
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/growers/leaderboard?by=profiled&min_batches=5`
+#[get("/growers/leaderboard")]
+async fn get_grower_leaderboard(
+    pool: web::Data<DbPool>,
+    query: web::Query<LeaderboardQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LEADERBOARD_LIMIT);
+    if !(1..=MAX_LEADERBOARD_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_LEADERBOARD_LIMIT
+        )));
+    }
+    let metric = query.by.unwrap_or_default();
+    let min_batches = query.min_batches.unwrap_or(1);
+    let conn = pool.get()?;
+    web::block(move || Grower::leaderboard(&conn, metric, min_batches, limit))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Other names grower {id} trades under
+#[get("/growers/{id}/aliases")]
+async fn get_grower_aliases(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || growers.find(path.0).first::<Grower>(&conn)?.aliases(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Record another name grower {id} trades under. An alias names a single grower.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"alias": "Cresco Labs"}'
+///      $ localhost:8008/growers/1/aliases`
+#[post("/growers/{id}/aliases")]
+async fn post_grower_alias(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<AliasBody>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let new = NewGrowerAlias {
+        grower_id: path.0,
+        alias: data.into_inner().alias,
+    };
+    web::block(move || {
+        growers.find(new.grower_id).first::<Grower>(&conn)?;
+        new.create(&conn)
     })
-    .map_err(|e| {
-        HttpResponse::InternalServerError()
-            .json(json!({"message": e.to_string(), "Status Code": 500 }))
+    .await
+    .map(created)
+    .map_err(|e| ApiError::from(e).on_conflict("Alias is already taken"))
+}
+
+#[delete("/growers/{id}/aliases/{alias_id}")]
+async fn delete_grower_alias(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        grower_aliases
+            .find(path.0 .1)
+            .filter(aliased_grower.eq(path.0 .0))
+            .first::<GrowerAlias>(&conn)?
+            .delete(&conn)
     })
+    .await
+    .map(ok)
+    .map_err(|e| ApiError::from(e).on_not_found("Grower has no such alias"))
 }
 
-/// Get grower by {id}
-#[get("/growers/{id}")]
-async fn get_grower_by_id(pool: web::Data<DbPool>, path: web::Path<i32>) -> impl Responder {
-    let conn = pool.get().expect("Could not get connection.");
-    web::block(move || growers.find(path.0).first::<Grower>(&conn))
+/// Strains and growers whose name or an alias resembles `q`, best match first. Prefixes
+/// and small misspellings match too.
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/search?q=gelat&limit=5`
+///
+///     Response:
+///     `{"data": {"strains": [{"id": 5, "name": "Gelato", "matched": "Gelato", "score": 0.8}],
+///      "growers": []}, "status code": 200}`
+#[get("/search")]
+async fn search(
+    pool: web::Data<DbPool>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    if query.q.trim().is_empty() {
+        return Err(ApiError::BadRequest("q must not be empty".to_owned()));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if !(1..=MAX_SEARCH_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_SEARCH_LIMIT
+        )));
+    }
+    let conn = pool.get()?;
+    web::block(move || SearchResults::find(&conn, query.q.trim(), limit))
         .await
-        .map(|res| HttpResponse::Ok().json(json!({ "data": res, "status code": 200 })))
-        .map_err(|e| {
-            HttpResponse::NotFound().json(json!({"message": e.to_string(), "status code": 404 }))
-        })
+        .map(ok)
+        .map_err(ApiError::from)
 }
 
-/// Return an array of batches that match a given query.
+/// Return an array of batches that match a given query. Any combination of criteria may be
+/// given and all of them must match; with no criteria every batch is returned.
+///
+/// Supported parameters: `strain_id`, `strain`, `grower_id`, `grower`, `species`,
+/// `harvest_date_min`/`_max`, `final_test_date_min`/`_max`, `package_date_min`/`_max`,
+/// `thc_min`/`thc_max` and `cbd_min`/`cbd_max`.
 ///
 /// Ex:
 ///     Request:
-///     `$ curl localhost:8008/batches?strain_id=15&grower_id=3`
-//#[get("/batches")]
-//async fn get_batches(pool: web::Data<DbPool>, query: web::Query<BatchQuery>) -> impl Responder {
-//let conn = pool.get().expect("Could not get connection.");
-//web::block(move || match query.0 {})
-//}
+///     `$ curl localhost:8008/batches?strain_id=15&grower_id=3&thc_min=20`
 #[get("/batches")]
-async fn get_all_batches(pool: web::Data<DbPool>) -> impl Responder {
-    let conn = pool.get().expect("Could not get connection.");
-    web::block(move || Batch::all(&conn))
-        .await
-        .map(|res| HttpResponse::Ok().json(json!({ "data": res, "status code": 200 })))
-        .map_err(|e| {
-            HttpResponse::InternalServerError()
-                .json(json!({"message": e.to_string(), "status code": 500 }))
-        })
+async fn get_batches(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    filter: web::Query<BatchFilter>,
+    page: web::Query<PageQuery>,
+    query: web::Query<BatchResponseQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Batch::page(&conn, filter.into_inner(), &page))
+        .await
+        .map(|res| page_response(&req, res.map(|b| query.render(b))))
+        .map_err(ApiError::from)
 }
 
 #[post("/batches")]
-async fn post_new_batch(pool: web::Data<DbPool>, data: web::Json<NewBatch>) -> impl Responder {
-    let conn = pool.get().expect("Could not get connection.");
+async fn post_new_batch(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewBatch>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
     let batch = data.into_inner();
     web::block(move || batch.create(&conn))
         .await
-        .map(|b| HttpResponse::Ok().json(json!({ "data": b, "status code": 201 })))
-        .map_err(|e| {
-            HttpResponse::InternalServerError()
-                .json(json!({"message": e.to_string(), "status code": 500 }))
-        })
+        .map(created)
+        .map_err(ApiError::from)
 }
 
+/// Create every batch in the body or none of them
+#[post("/batches/bulk")]
+async fn post_new_batches(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<Vec<NewBatch>>,
+) -> Result<HttpResponse, ApiError> {
+    let new = bulk_records(data)?;
+    let conn = pool.get()?;
+    web::block(move || NewBatch::create_all(&conn, &new, OnConflict::Error))
+        .await
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+/// Create batches from a lab's Certificate of Analysis export. Rows that can't be imported
+/// are listed as `rows[N].field` errors and nothing is saved.
+#[post("/batches/import")]
+async fn post_batch_import(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let format = query.format.unwrap_or_default();
+    let dry_run = query.dry_run.unwrap_or(false);
+    let rows = import::parse_rows(format, &body)
+        .map_err(|e| ApiError::BadRequest(format!("Could not read COA export: {}", e)))?;
+    if rows.is_empty() {
+        return Err(ApiError::BadRequest("COA export has no rows".to_owned()));
+    }
+    let conn = pool.get()?;
+    let report = web::block(move || import::import_coa(&conn, &rows, dry_run)).await?;
+    if !report.errors.is_empty() {
+        Err(ApiError::Invalid(report.validation_errors()))
+    } else if dry_run {
+        Ok(ok(report))
+    } else {
+        Ok(created(report))
+    }
+}
+
+/// Retrieve strains matching every given criterion: `name`, `species`, bounds on the
+/// indica/sativa split and the `chemotype` (`I`, `II` or `III`) of the strain's batches.
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/strains?indica_min=60&chemotype=I`
 #[get("/strains")]
-async fn query_strain(pool: web::Data<DbPool>, query: web::Query<StrainQuery>) -> impl Responder {
-    let conn = pool.get().expect("Could not get connection.");
-    web::block(move || match (query.0.name, query.0.species) {
-        (Some(n), None) => Strain::filter(&conn, StrainField::Name(n)),
-        (None, Some(s)) => Strain::filter(&conn, StrainField::Species(s.clone())),
-        _ => Strain::all(&conn),
-    })
-    .await
-    .map(|res| HttpResponse::Ok().json(json!({ "data": res, "status code": 200 })))
-    .map_err(|e| {
-        HttpResponse::InternalServerError()
-            .json(json!({"message": e.to_string(), "status code": 500 }))
-    })
+async fn query_strain(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    filter: web::Query<StrainFilter>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Strain::page(&conn, filter.into_inner(), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
 }
 
 #[post("/strains")]
-async fn post_new_strain(pool: web::Data<DbPool>, data: web::Json<NewStrain>) -> impl Responder {
-    let conn = pool.get().expect("Couldn't get connection.");
+async fn post_new_strain(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewStrain>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
     let strain = data.into_inner();
     web::block(move || strain.create(&conn))
         .await
-        .map(|s| HttpResponse::Ok().json(json!({ "data": s, "status code": 201 })))
-        .map_err(|e| {
-            HttpResponse::InternalServerError()
-                .json(json!({"status code": 500, "message": e.to_string()}))
-        })
+        .map(created)
+        .map_err(ApiError::from)
 }
 
-#[get("/strains/{id}")]
-async fn get_strains_by_id(pool: web::Data<DbPool>, path: web::Path<i32>) -> impl Responder {
-    let conn = pool.get().expect("Couldn't get connection.");
-    web::block(move || strains.find(path.0).first::<Strain>(&conn))
+/// Stream the catalog as a JSON Lines archive, or a single table of it as CSV or JSON
+/// Lines. Records are read and sent a chunk at a time, so the whole catalog is never
+/// held in memory.
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/export?format=csv&table=batch_responses`
+///
+///     Response:
+///     `id,strain_id,strain,grower_id,grower,harvest_date,...
+///      1,3,Blackwater OG,3,Summa,2022-01-10,...`
+#[get("/export")]
+async fn get_export(
+    pool: web::Data<DbPool>,
+    query: web::Query<ArchiveQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let format = query.format.unwrap_or_default();
+    let tables = match (query.table, format) {
+        (Some(t), _) => vec![t],
+        (None, ArchiveFormat::Jsonl) => ArchiveTable::ALL.to_vec(),
+        (None, ArchiveFormat::Csv) => {
+            return Err(ApiError::BadRequest(
+                "A CSV export holds one table; pass `table`".to_owned(),
+            ))
+        }
+    };
+    let filename = format!(
+        "{}.{}",
+        query.table.map_or("catalog", |t| t.name()),
+        format.extension()
+    );
+
+    // Every chunk is read through the same snapshot, so a write landing mid-export
+    // can't leave the archive with a batch whose strain or parent is missing
+    let snapshot = web::block(move || {
+        let conn = pool.get().map_err(ApiError::from)?;
+        ExportSnapshot::begin(conn).map_err(ApiError::from)
+    })
+    .await?;
+
+    // State: the snapshot, tables left to export, key of the last record sent and
+    // whether the current table has sent anything yet
+    let chunks = stream::try_unfold(
+        (Some(snapshot), tables, 0, true),
+        move |(snapshot, mut tables, mut after, mut first)| async move {
+            let mut snapshot = match snapshot {
+                Some(snapshot) => snapshot,
+                None => return Ok(None),
+            };
+            while let Some(table) = tables.first().copied() {
+                let (returned, records, last) = web::block(move || {
+                    let (records, last) = snapshot.chunk(table, after).map_err(ApiError::from)?;
+                    Ok::<_, ApiError>((snapshot, records, last))
+                })
+                .await?;
+                snapshot = returned;
+                match last {
+                    Some(key) => {
+                        let bytes =
+                            archive::render(format, &records, first).map_err(ApiError::Internal)?;
+                        return Ok(Some((
+                            Bytes::from(bytes),
+                            (Some(snapshot), tables, key, false),
+                        )));
+                    }
+                    None => {
+                        tables.remove(0);
+                        after = 0;
+                        first = true;
+                    }
+                }
+            }
+            web::block(move || snapshot.finish().map_err(ApiError::from)).await?;
+            Ok::<_, ApiError>(None)
+        },
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .header(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .streaming(Box::pin(chunks)))
+}
+
+/// Restore an archive made by `GET /export` into a catalog with no strains, growers or
+/// batches yet. A CSV file needs the `table` it holds.
+#[post("/import")]
+async fn post_import(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    query: web::Query<ArchiveQuery>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    let records = archive::parse(query.format.unwrap_or_default(), query.table, &body)
+        .map_err(|e| ApiError::BadRequest(format!("Could not read archive: {}", e)))?;
+    let conn = pool.get()?;
+    web::block(move || archive::restore(&conn, records))
+        .await
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+/// Create every strain in the body or none of them
+#[post("/strains/bulk")]
+async fn post_new_strains(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    query: web::Query<BulkQuery>,
+    data: web::Json<Vec<NewStrain>>,
+) -> Result<HttpResponse, ApiError> {
+    let new = bulk_records(data)?;
+    let on_conflict = query.on_conflict.unwrap_or_default();
+    let conn = pool.get()?;
+    web::block(move || NewStrain::create_all(&conn, &new, on_conflict))
         .await
-        .map(|res| HttpResponse::Ok().json(json!({ "data": res, "status code": 200 })))
-        .map_err(|e| {
-            HttpResponse::NotFound().json(json!({"message": e.to_string(), "status code": 404 }))
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+#[get("/strains/{id}")]
+async fn get_strains_by_id(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        let strain = strains.find(path.0).first::<Strain>(&conn)?;
+        let rating = strain.rating(&conn)?;
+        let chemotype = strain.chemotype(&conn)?;
+        Ok::<_, Error>(StrainResponse {
+            strain,
+            rating,
+            chemotype,
         })
+    })
+    .await
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// Strains that strain {id} was bred from
+#[get("/strains/{id}/parents")]
+async fn get_strain_parents(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || strains.find(path.0).first::<Strain>(&conn)?.parents(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Record that strain {id} was bred from strain `parent_id`. A parent that is itself
+/// bred from strain {id} would make a cycle and is refused with a 422.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"parent_id": 7}'
+///      $ localhost:8008/strains/1/parents`
+#[post("/strains/{id}/parents")]
+async fn post_strain_parent(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<ParentBody>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let link = StrainParent {
+        strain_id: path.0,
+        parent_id: data.parent_id,
+    };
+    web::block(move || {
+        strains.find(link.strain_id).first::<Strain>(&conn)?;
+        link.create(&conn)
+    })
+    .await
+    .map(created)
+    .map_err(|e| ApiError::from(e).on_conflict("Strain already has that parent"))
+}
+
+#[delete("/strains/{id}/parents/{parent_id}")]
+async fn delete_strain_parent(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let link = StrainParent {
+        strain_id: path.0 .0,
+        parent_id: path.0 .1,
+    };
+    web::block(move || link.delete(&conn))
+        .await
+        .map(ok)
+        .map_err(|e| ApiError::from(e).on_not_found("Strain has no such parent"))
+}
+
+/// Strains bred from strain {id}
+#[get("/strains/{id}/children")]
+async fn get_strain_children(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || strains.find(path.0).first::<Strain>(&conn)?.children(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Family tree of strain {id}, `depth` generations up (3 by default, at most 10)
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/strains/1/lineage?depth=2`
+///
+///     Response:
+///     `{"data": {"id": 1, "name": "Wedding Cake", "species": "Hybrid", ...,
+///        "parents": [{"id": 7, "name": "Triangle Kush", ..., "parents": []}, ...]},
+///       "status code": 200}`
+#[get("/strains/{id}/lineage")]
+async fn get_strain_lineage(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    query: web::Query<LineageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let depth = query.depth.unwrap_or(DEFAULT_LINEAGE_DEPTH);
+    if !(1..=MAX_LINEAGE_DEPTH).contains(&depth) {
+        return Err(ApiError::BadRequest(format!(
+            "depth must be between 1 and {}",
+            MAX_LINEAGE_DEPTH
+        )));
+    }
+    let conn = pool.get()?;
+    web::block(move || {
+        strains
+            .find(path.0)
+            .first::<Strain>(&conn)?
+            .lineage(&conn, depth)
+    })
+    .await
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// How the potency of strain {id} varies across its batches: batch count, spread of total
+/// THC and CBD, mean terpene profile, and the same per grower and per month of harvest
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/strains/3/stats`
+#[get("/strains/{id}/stats")]
+async fn get_strain_stats(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || strains.find(path.0).first::<Strain>(&conn)?.stats(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Other names strain {id} is sold under
+#[get("/strains/{id}/aliases")]
+async fn get_strain_aliases(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || strains.find(path.0).first::<Strain>(&conn)?.aliases(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Record another name strain {id} is sold under. An alias names a single strain.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"alias": "GSC"}'
+///      $ localhost:8008/strains/9/aliases`
+#[post("/strains/{id}/aliases")]
+async fn post_strain_alias(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<AliasBody>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let new = NewStrainAlias {
+        strain_id: path.0,
+        alias: data.into_inner().alias,
+    };
+    web::block(move || {
+        strains.find(new.strain_id).first::<Strain>(&conn)?;
+        new.create(&conn)
+    })
+    .await
+    .map(created)
+    .map_err(|e| ApiError::from(e).on_conflict("Alias is already taken"))
+}
+
+#[delete("/strains/{id}/aliases/{alias_id}")]
+async fn delete_strain_alias(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        strain_aliases
+            .find(path.0 .1)
+            .filter(aliased_strain.eq(path.0 .0))
+            .first::<StrainAlias>(&conn)?
+            .delete(&conn)
+    })
+    .await
+    .map(ok)
+    .map_err(|e| ApiError::from(e).on_not_found("Strain has no such alias"))
 }
 
+/// Batches of strain {strain_id}. Responds with 404 if the strain doesn't exist and with
+/// an empty page if it has no batches.
 #[get("/strains/{strain_id}/batches")]
-async fn get_batches_by_strain_id(pool: web::Data<DbPool>, path: web::Path<i32>) -> impl Responder {
-    let conn = pool.get().expect("Could not get connection.");
-    web::block(move || Batch::filter(&conn, BatchField::StrainID(path.0)))
-        .await
-        .map(|res| match res.len() {
-            0 => HttpResponse::NotFound().json(json!({
-                "status code": 404,
-                "message": "NotFound"
-            })),
-            _ => HttpResponse::Ok().json(json!({ "data": res, "status code": 200 })),
+async fn get_batches_by_strain_id(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    filter: web::Query<BatchFilter>,
+    page: web::Query<PageQuery>,
+    query: web::Query<BatchResponseQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let filter = BatchFilter {
+        strain_id: Some(path.0),
+        ..filter.into_inner()
+    };
+    web::block(move || {
+        strains.find(path.0).first::<Strain>(&conn)?;
+        Batch::page(&conn, filter, &page)
+    })
+    .await
+    .map(|res| page_response(&req, res.map(|b| query.render(b))))
+    .map_err(ApiError::from)
+}
+
+/// Make a POST request to attach a terpene profile to batch {id}. Each key names a compound
+/// from the catalog at `/terpenes`; unknown compounds are rejected with a 422.
+/// Responds with 404 if the batch doesn't exist and 409 if it already has a profile.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"limonene": 0.42, "myrcene": 0.31}'
+///      $ localhost:8008/batches/7/terpenes`
+///
+///     Response:
+///     `{"data": {"id":2, "batch_id":7, "limonene":0.42, "myrcene":0.31}, "status code": 201}`
+#[post("/batches/{id}/terpenes")]
+async fn post_new_terpenes(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewTerpenes>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut terps = data.into_inner();
+    terps.batch_id = path.0;
+    web::block(move || {
+        batches.find(terps.batch_id).first::<Batch>(&conn)?;
+        terps.create(&conn)
+    })
+    .await
+    .map(created)
+    .map_err(terpenes_error)
+}
+
+/// Get the terpene profile of batch {id}
+#[get("/batches/{id}/terpenes")]
+async fn get_terpenes_by_batch_id(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        Terpenes::filter(&conn, TerpenesField::BatchID(path.0))?
+            .pop()
+            .ok_or(Error::NotFound)
+    })
+    .await
+    .map(ok)
+    .map_err(terpenes_error)
+}
+
+/// Replace the terpene profile of batch {id}. Compounds left out of the body are cleared.
+#[put("/batches/{id}/terpenes")]
+async fn put_terpenes_by_batch_id(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewTerpenes>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut terps = data.into_inner();
+    terps.batch_id = path.0;
+    web::block(move || terps.replace(&conn))
+        .await
+        .map(ok)
+        .map_err(terpenes_error)
+}
+
+/// Remove the terpene profile of batch {id}
+#[delete("/batches/{id}/terpenes")]
+async fn delete_terpenes_by_batch_id(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        Terpenes::filter(&conn, TerpenesField::BatchID(path.0))?
+            .pop()
+            .ok_or(Error::NotFound)?
+            .delete(&conn)
+    })
+    .await
+    .map(ok)
+    .map_err(terpenes_error)
+}
+
+/// List every compound a terpene profile may include
+#[get("/terpenes")]
+async fn get_terpene_catalog(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || TerpeneCompound::catalog(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Add a compound to the terpene catalog. Names are stored lowercased.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Authorization: Bearer $TOKEN" \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"name": "germacrene"}'
+///      $ localhost:8008/terpenes`
+#[post("/terpenes")]
+async fn post_new_terpene_compound(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewTerpeneCompound>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.create(&conn))
+        .await
+        .map(created)
+        .map_err(|e| ApiError::from(e).on_conflict("Terpene is already in the catalog"))
+}
+
+/// A missing batch or panel is reported as "No Cannabinoids Found" and a second panel
+/// for the same batch as a conflict
+fn cannabinoids_error(e: BlockingError<Error>) -> ApiError {
+    ApiError::from(e)
+        .on_not_found("No Cannabinoids Found")
+        .on_conflict("Batch already has a cannabinoid panel")
+}
+
+/// Make a POST request to attach a cannabinoid panel to batch {id}. The response adds the
+/// batch's THC and CBD and the total THC/CBD derived from THCA and CBDA.
+/// Responds with 404 if the batch doesn't exist and 409 if it already has a panel.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"thca": 24.1, "cbda": 0.05, "cbg": 0.9, "cbga": 1.2}'
+///      $ localhost:8008/batches/7/cannabinoids`
+///
+///     Response:
+///     `{"data": {"id":2, "batch_id":7, "thca":24.1, ..., "thc":0.8, "cbd":0.1,
+///       "total_thc":21.94, "total_cbd":0.14}, "status code": 201}`
+#[post("/batches/{id}/cannabinoids")]
+async fn post_new_cannabinoids(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewCannabinoids>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut panel = data.into_inner();
+    panel.batch_id = path.0;
+    web::block(move || {
+        let batch = batches.find(panel.batch_id).first::<Batch>(&conn)?;
+        panel
+            .create(&conn)
+            .map(|c| CannabinoidPanel::new(&batch, c))
+    })
+    .await
+    .map(created)
+    .map_err(cannabinoids_error)
+}
+
+/// Get the cannabinoid panel of batch {id}, with total THC and CBD
+#[get("/batches/{id}/cannabinoids")]
+async fn get_cannabinoids_by_batch_id(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        Cannabinoids::filter(&conn, CannabinoidsField::BatchID(path.0))?
+            .pop()
+            .ok_or(Error::NotFound)?
+            .panel(&conn)
+    })
+    .await
+    .map(ok)
+    .map_err(cannabinoids_error)
+}
+
+/// Replace the cannabinoid panel of batch {id}. Compounds left out of the body are cleared.
+#[put("/batches/{id}/cannabinoids")]
+async fn put_cannabinoids_by_batch_id(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewCannabinoids>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut panel = data.into_inner();
+    panel.batch_id = path.0;
+    web::block(move || panel.replace(&conn)?.panel(&conn))
+        .await
+        .map(ok)
+        .map_err(cannabinoids_error)
+}
+
+/// Remove the cannabinoid panel of batch {id}
+#[delete("/batches/{id}/cannabinoids")]
+async fn delete_cannabinoids_by_batch_id(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        Cannabinoids::filter(&conn, CannabinoidsField::BatchID(path.0))?
+            .pop()
+            .ok_or(Error::NotFound)?
+            .delete(&conn)
+    })
+    .await
+    .map(ok)
+    .map_err(cannabinoids_error)
+}
+
+/// Replace every field of strain {id}
+///
+/// EX:
+///     Request:
+///     `$ curl -X PUT \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"name": "Wedding Cake", "species": "Hybrid"}'
+///      $ localhost:8008/strains/1`
+#[put("/strains/{id}")]
+async fn put_strain(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewStrain>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of strain {id} that are present in the body
+///
+/// EX:
+///     Request:
+///     `$ curl -X PATCH \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"species": "Hybrid"}'
+///      $ localhost:8008/strains/1`
+#[patch("/strains/{id}")]
+async fn patch_strain(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<StrainChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Delete strain {id}. Every batch of the strain is deleted along with it, so if any exist
+/// the request is refused with a 409 reporting how many unless `?cascade=true` is passed.
+#[delete("/strains/{id}")]
+async fn delete_strain(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    query: web::Query<CascadeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let cascade = query.cascade.unwrap_or(false);
+    web::block(move || {
+        conn.transaction(|| {
+            let strain = strains.find(path.0).first::<Strain>(&conn)?;
+            let n = strain.batch_count(&conn)?;
+            if n > 0 && !cascade {
+                return Ok(Err(n));
+            }
+            strain.delete(&conn).map(|s| Ok((s, n)))
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+    .and_then(|res| cascade_response(res, "strain"))
+}
+
+/// Replace every field of grower {id}
+#[put("/growers/{id}")]
+async fn put_grower(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewGrower>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of grower {id} that are present in the body
+#[patch("/growers/{id}")]
+async fn patch_grower(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<GrowerChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Delete grower {id}. Like strains, a grower with batches is only deleted with `?cascade=true`.
+#[delete("/growers/{id}")]
+async fn delete_grower(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    query: web::Query<CascadeQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let cascade = query.cascade.unwrap_or(false);
+    web::block(move || {
+        conn.transaction(|| {
+            let grower = growers.find(path.0).first::<Grower>(&conn)?;
+            let n = grower.batch_count(&conn)?;
+            if n > 0 && !cascade {
+                return Ok(Err(n));
+            }
+            grower.delete(&conn).map(|g| Ok((g, n)))
+        })
+    })
+    .await
+    .map_err(ApiError::from)
+    .and_then(|res| cascade_response(res, "grower"))
+}
+
+/// Replace every field of batch {id}. Dates left out of the body are cleared.
+#[put("/batches/{id}")]
+async fn put_batch(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewBatch>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of batch {id} that are present in the body
+#[patch("/batches/{id}")]
+async fn patch_batch(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<BatchChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Delete batch {id} along with its terpene profile
+#[delete("/batches/{id}")]
+async fn delete_batch(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || batches.find(path.0).first::<Batch>(&conn)?.delete(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Create a new user account. Passwords must be at least 8 characters long.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"username": "stoner", "password": "correct horse"}'
+///      $ localhost:8008/auth/register`
+///
+///     Response:
+///     `{"data": {"id": 1, "username": "stoner", "created_at": "..."}, "status code": 201}`
+#[post("/auth/register")]
+async fn register(
+    pool: web::Data<DbPool>,
+    data: web::Json<Credentials>,
+) -> Result<HttpResponse, ApiError> {
+    let creds = data.into_inner();
+    if creds.username.is_empty() || creds.password.len() < auth::MIN_PASSWORD_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "A username and a password of at least {} characters are required",
+            auth::MIN_PASSWORD_LENGTH
+        )));
+    }
+
+    let conn = pool.get()?;
+    web::block(move || {
+        let new = NewUser {
+            username: creds.username,
+            password_hash: auth::hash_password(&creds.password)
+                .map_err(|e| ApiError::Internal(e.to_string()))?,
+        };
+        new.create(&conn).map_err(ApiError::from)
+    })
+    .await
+    .map(created)
+    .map_err(|e| ApiError::from(e).on_conflict("Username is taken"))
+}
+
+/// Exchange a username and password for a bearer token. Send it as
+/// `Authorization: Bearer <token>` on routes that create, change or delete records.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"username": "stoner", "password": "correct horse"}'
+///      $ localhost:8008/auth/login`
+///
+///     Response:
+///     `{"data": {"token": "eyJ0eXAi...", "token_type": "Bearer", "expires_at": "..."}, "status code": 200}`
+#[post("/auth/login")]
+async fn login(
+    pool: web::Data<DbPool>,
+    secret: web::Data<TokenSecret>,
+    data: web::Json<Credentials>,
+) -> Result<HttpResponse, ApiError> {
+    let creds = data.into_inner();
+    let conn = pool.get()?;
+    web::block(move || {
+        let user = User::filter(&conn, UserField::Username(creds.username))?
+            .pop()
+            .filter(|u| auth::verify_password(&u.password_hash, &creds.password))
+            .ok_or_else(|| ApiError::Unauthorized("Invalid username or password".to_owned()))?;
+        let (token, row) =
+            auth::issue_token(&secret, user.id).map_err(|e| ApiError::Internal(e.to_string()))?;
+        row.create(&conn)
+            .map(|t| (token, t))
+            .map_err(ApiError::from)
+    })
+    .await
+    .map(|(token, row)| {
+        ok(json!({ "token": token, "token_type": "Bearer", "expires_at": row.expires_at }))
+    })
+    .map_err(ApiError::from)
+}
+
+/// Revoke the bearer token used to make this request
+#[post("/auth/logout")]
+async fn logout(user: AuthUser, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || AuthToken::revoke(&conn, &user.jti))
+        .await
+        .map(|_| HttpResponse::Ok().json(json!({"message": "Logged out", "status code": 200 })))
+        .map_err(ApiError::from)
+}
+
+/// Retrieve the current user's smoke sessions, newest first
+#[get("/me/sessions")]
+async fn get_my_sessions(
+    user: AuthUser,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Session::page(&conn, SessionField::UserID(user.user.id), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Log a smoke session for the current user. `consumed_at` defaults to now.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Authorization: Bearer $TOKEN" \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"batch_id": 7, "method": "Vape", "amount": 0.25, "notes": "Couch lock"}'
+///      $ localhost:8008/me/sessions`
+#[post("/me/sessions")]
+async fn post_new_session(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewSession>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut session = data.into_inner();
+    session.user_id = user.user.id;
+    web::block(move || session.create(&conn))
+        .await
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+/// Get one of the current user's sessions by {id}
+#[get("/me/sessions/{id}")]
+async fn get_my_session_by_id(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Session::find_owned(&conn, path.0, user.user.id))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of session {id} that are present in the body
+#[patch("/me/sessions/{id}")]
+async fn patch_my_session(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<SessionChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        conn.transaction(|| {
+            let session = Session::find_owned(&conn, path.0, user.user.id)?;
+            data.update(&conn, session.id)
+        })
+    })
+    .await
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// Delete one of the current user's sessions
+#[delete("/me/sessions/{id}")]
+async fn delete_my_session(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Session::find_owned(&conn, path.0, user.user.id)?.delete(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Retrieve the current user's sessions of batch {id}, newest first
+#[get("/batches/{id}/sessions")]
+async fn get_sessions_by_batch_id(
+    user: AuthUser,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let field = SessionField::UserBatch(user.user.id, path.0);
+    web::block(move || Session::page(&conn, field, &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Log a session of batch {id} for the current user. Responds with 404 if the batch doesn't exist.
+#[post("/batches/{id}/sessions")]
+async fn post_new_session_by_batch_id(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewSession>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut session = data.into_inner();
+    session.user_id = user.user.id;
+    session.batch_id = path.0;
+    web::block(move || {
+        batches.find(session.batch_id).first::<Batch>(&conn)?;
+        session.create(&conn)
+    })
+    .await
+    .map(created)
+    .map_err(ApiError::from)
+}
+
+/// A second review of the same batch by the same user is a conflict
+fn review_error(e: BlockingError<Error>) -> ApiError {
+    ApiError::from(e).on_conflict("You have already reviewed this batch")
+}
+
+/// Review batch {id} as the current user. Each user may review a batch once.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Authorization: Bearer $TOKEN" \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"rating": 4, "body": "Smooth", "effects": ["relaxed"], "flavors": ["citrus", "pine"]}'
+///      $ localhost:8008/batches/7/reviews`
+#[post("/batches/{id}/reviews")]
+async fn post_new_review(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewReview>,
+) -> Result<HttpResponse, ApiError> {
+    let mut review = data.into_inner();
+    review.user_id = user.user.id;
+    review.batch_id = path.0;
+
+    let conn = pool.get()?;
+    web::block(move || {
+        batches.find(review.batch_id).first::<Batch>(&conn)?;
+        review.create(&conn)
+    })
+    .await
+    .map(created)
+    .map_err(review_error)
+}
+
+/// Retrieve every review of batch {id}, newest first
+#[get("/batches/{id}/reviews")]
+async fn get_reviews_by_batch_id(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Review::page(&conn, ReviewField::BatchID(path.0), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Retrieve every review of every batch of strain {id}, newest first
+#[get("/strains/{id}/reviews")]
+async fn get_reviews_by_strain_id(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Review::page(&conn, ReviewField::StrainID(path.0), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Retrieve the current user's reviews, newest first
+#[get("/me/reviews")]
+async fn get_my_reviews(
+    user: AuthUser,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Review::page(&conn, ReviewField::UserID(user.user.id), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of review {id} that are present in the body
+#[patch("/me/reviews/{id}")]
+async fn patch_my_review(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<ReviewChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        conn.transaction(|| {
+            let review = Review::find_owned(&conn, path.0, user.user.id)?;
+            data.update(&conn, review.id)
         })
-        .map_err(|e| {
-            HttpResponse::InternalServerError()
-                .json(json!({"message": e.to_string(), "status code": 500 }))
+    })
+    .await
+    .map(ok)
+    .map_err(review_error)
+}
+
+/// Delete one of the current user's reviews
+#[delete("/me/reviews/{id}")]
+async fn delete_my_review(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Review::find_owned(&conn, path.0, user.user.id)?.delete(&conn))
+        .await
+        .map(ok)
+        .map_err(review_error)
+}
+
+/// `?low_stock=true` limits `/inventory` to items at or below their low-stock threshold
+#[derive(Debug, Deserialize, Clone)]
+struct InventoryQuery {
+    low_stock: Option<bool>,
+}
+
+/// Retrieve the current user's inventory, optionally only items that are running low
+#[get("/inventory")]
+async fn get_inventory(
+    user: AuthUser,
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<InventoryQuery>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let field = match query.low_stock {
+        Some(true) => InventoryField::LowStock(user.user.id),
+        _ => InventoryField::UserID(user.user.id),
+    };
+    web::block(move || Inventory::page(&conn, field, &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Add a purchase to the current user's inventory. `quantity` is counted in `unit`
+/// (Grams, Eighths or Units); `low_stock_threshold` defaults to a per-unit amount.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Authorization: Bearer $TOKEN" \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"batch_id": 7, "quantity": 2, "unit": "Eighths", "purchase_price_cents": 9000}'
+///      $ localhost:8008/inventory`
+#[post("/inventory")]
+async fn post_new_inventory(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewInventory>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut item = data.into_inner();
+    item.user_id = user.user.id;
+    web::block(move || item.create(&conn))
+        .await
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+/// Totals of the current user's inventory per strain: grams on hand (eighths converted),
+/// units on hand and total spent
+#[get("/inventory/summary")]
+async fn get_inventory_summary(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || InventorySummary::for_user(&conn, user.user.id))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Get one of the current user's inventory items by {id}
+#[get("/inventory/{id}")]
+async fn get_inventory_by_id(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Inventory::find_owned(&conn, path.0, user.user.id))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of inventory item {id} that are present in the body
+#[patch("/inventory/{id}")]
+async fn patch_inventory(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<InventoryChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        conn.transaction(|| {
+            let item = Inventory::find_owned(&conn, path.0, user.user.id)?;
+            data.update(&conn, item.id)
         })
+    })
+    .await
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// Remove an item from the current user's inventory
+#[delete("/inventory/{id}")]
+async fn delete_inventory(
+    user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Inventory::find_owned(&conn, path.0, user.user.id)?.delete(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Retrieve dispensaries, or only those whose `name` matches
+#[get("/dispensaries")]
+async fn get_dispensaries(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    filter: web::Query<DispensaryFilter>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Dispensary::page(&conn, filter.into_inner(), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"name": "Sunnyside", "address": "436 N Clark St, Chicago, IL"}'
+///      $ localhost:8008/dispensaries`
+#[post("/dispensaries")]
+async fn post_new_dispensary(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewDispensary>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.create(&conn))
+        .await
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+#[get("/dispensaries/{id}")]
+async fn get_dispensary_by_id(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || dispensaries.find(path.0).first::<Dispensary>(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Replace every field of dispensary {id}
+#[put("/dispensaries/{id}")]
+async fn put_dispensary(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewDispensary>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of dispensary {id} that are present in the body
+#[patch("/dispensaries/{id}")]
+async fn patch_dispensary(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<DispensaryChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Delete dispensary {id} along with its listings
+#[delete("/dispensaries/{id}")]
+async fn delete_dispensary(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        dispensaries
+            .find(path.0)
+            .first::<Dispensary>(&conn)?
+            .delete(&conn)
+    })
+    .await
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// Every listing on the menu of dispensary {id}
+#[get("/dispensaries/{id}/listings")]
+async fn get_listings_by_dispensary_id(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    filter: web::Query<ListingFilter>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut filter = filter.into_inner();
+    filter.dispensary_id = Some(path.0);
+    web::block(move || {
+        dispensaries.find(path.0).first::<Dispensary>(&conn)?;
+        Listing::page(&conn, filter, &page)
+    })
+    .await
+    .map(|res| page_response(&req, res))
+    .map_err(ApiError::from)
+}
+
+/// Retrieve listings matching every given criterion: `dispensary_id`, `batch_id`,
+/// `strain_id`, `format` and `in_stock`
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/listings?strain_id=3&in_stock=true`
+#[get("/listings")]
+async fn get_listings(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    filter: web::Query<ListingFilter>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || Listing::page(&conn, filter.into_inner(), &page))
+        .await
+        .map(|res| page_response(&req, res))
+        .map_err(ApiError::from)
+}
+
+/// Put a batch on a dispensary's menu. `format` defaults to `Flower`, `in_stock` to true
+/// and `observed_at` to now.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"dispensary_id": 2, "batch_id": 7, "weight_grams": 3.5, "price_cents": 4500}'
+///      $ localhost:8008/listings`
+#[post("/listings")]
+async fn post_new_listing(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    data: web::Json<NewListing>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.create(&conn))
+        .await
+        .map(created)
+        .map_err(ApiError::from)
+}
+
+#[get("/listings/{id}")]
+async fn get_listing_by_id(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || listings.find(path.0).first::<Listing>(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Update only the fields of listing {id} that are present in the body. The listing
+/// counts as observed now unless `observed_at` is given.
+#[patch("/listings/{id}")]
+async fn patch_listing(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<ListingChanges>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || data.update(&conn, path.0))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+#[delete("/listings/{id}")]
+async fn delete_listing(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || listings.find(path.0).first::<Listing>(&conn)?.delete(&conn))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// `?format=` limits where-to-buy results to one product format
+#[derive(Debug, Deserialize, Clone)]
+struct WhereToBuyQuery {
+    format: Option<ProductFormat>,
+}
+
+/// In-stock listings of strain {id} across dispensaries, cheapest per gram first
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/strains/3/where-to-buy?format=Flower`
+#[get("/strains/{id}/where-to-buy")]
+async fn get_where_to_buy(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    query: web::Query<WhereToBuyQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        strains
+            .find(path.0)
+            .first::<Strain>(&conn)?
+            .where_to_buy(&conn, query.format)
+    })
+    .await
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// Every price batch {id} was seen at, oldest first. `?from=` and `?to=` limit the
+/// history to observations made within those days.
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/batches/7/prices?from=2022-07-01`
+#[get("/batches/{id}/prices")]
+async fn get_prices_by_batch_id(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    filter: web::Query<PriceHistoryFilter>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        batches
+            .find(path.0)
+            .first::<Batch>(&conn)?
+            .prices(&conn, filter.into_inner())
+    })
+    .await
+    .map(ok)
+    .map_err(ApiError::from)
+}
+
+/// Record a price batch {id} was seen at outside of any listing. `observed_at` defaults
+/// to now. Responds with 404 if the batch doesn't exist.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"weight_grams": 3.5, "price_cents": 4000, "observed_at": "2022-07-02T18:00:00"}'
+///      $ localhost:8008/batches/7/prices`
+#[post("/batches/{id}/prices")]
+async fn post_new_price_by_batch_id(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewPriceObservation>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let mut observation = data.into_inner();
+    observation.batch_id = path.0;
+    web::block(move || {
+        batches.find(observation.batch_id).first::<Batch>(&conn)?;
+        observation.create(&conn)
+    })
+    .await
+    .map(created)
+    .map_err(ApiError::from)
+}
+
+/// `?limit=N` caps the number of listings ranked by value
+#[derive(Debug, Deserialize, Clone)]
+struct ValueQuery {
+    limit: Option<i64>,
+}
+
+/// Listings ranked by value when `limit` isn't given
+const DEFAULT_VALUE_LIMIT: i64 = 10;
+
+/// Most listings ranked by value in one request
+const MAX_VALUE_LIMIT: i64 = 100;
+
+/// In-stock listings ranked by price per gram of THC, best value first, within a
+/// `species`, `strain_id` or `format` if given
+///
+/// Ex:
+///     Request:
+///     `$ curl localhost:8008/listings/value?species=Indica&limit=5`
+#[get("/listings/value")]
+async fn get_listings_by_value(
+    pool: web::Data<DbPool>,
+    filter: web::Query<ValueFilter>,
+    query: web::Query<ValueQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_VALUE_LIMIT);
+    if !(1..=MAX_VALUE_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_VALUE_LIMIT
+        )));
+    }
+    let conn = pool.get()?;
+    web::block(move || Listing::by_value(&conn, filter.into_inner(), limit))
+        .await
+        .map(ok)
+        .map_err(ApiError::from)
+}
+
+/// Products made from flower batch {id}, in the same shape as `/batches`. Responds with
+/// 404 if the batch doesn't exist.
+#[get("/batches/{id}/products")]
+async fn get_products_by_batch_id(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    filter: web::Query<BatchFilter>,
+    page: web::Query<PageQuery>,
+    query: web::Query<BatchResponseQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    let filter = BatchFilter {
+        parent_batch_id: Some(path.0),
+        ..filter.into_inner()
+    };
+    web::block(move || {
+        batches.find(path.0).first::<Batch>(&conn)?;
+        Batch::page(&conn, filter, &page)
+    })
+    .await
+    .map(|res| page_response(&req, res.map(|b| query.render(b))))
+    .map_err(ApiError::from)
+}
+
+/// Make a product out of flower batch {id}. The product takes the batch's strain, grower
+/// and harvest date; its potency is given in the unit of its `format`.
+///
+/// EX:
+///     Request:
+///     `$ curl -X POST \
+///      $ -H "Content-Type: application/json" \
+///      $ -d '{"format": "Edible", "thc_content": 10.0, "cbd_content": 0.0}'
+///      $ localhost:8008/batches/7/products`
+#[post("/batches/{id}/products")]
+async fn post_new_product_by_batch_id(
+    _user: AuthUser,
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    data: web::Json<NewProduct>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = pool.get()?;
+    web::block(move || {
+        let parent = batches.find(path.0).first::<Batch>(&conn)?;
+        data.of(&parent).create(&conn)
+    })
+    .await
+    .map(created)
+    .map_err(ApiError::from)
 }
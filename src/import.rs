@@ -0,0 +1,472 @@
+use super::db::{Creatable, GrowerField, Retrievable, StrainField};
+use super::models::{
+    Batch, Cannabinoids, Grower, NewBatch, NewCannabinoids, NewTerpenes, Strain, TerpeneCompound,
+    Terpenes,
+};
+use super::validation::{FieldError, Validate, ValidationErrors};
+
+use chrono::NaiveDate;
+use diesel::pg::PgConnection;
+use diesel::result::Error;
+use diesel::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// File format of a lab's Certificate of Analysis export
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CoaFormat {
+    Csv,
+    #[default]
+    Json,
+}
+
+/// One sample of a COA export: column name (lowercased, spaces and dashes as underscores)
+/// to its raw value. Empty cells are left out.
+pub type CoaRow = BTreeMap<String, String>;
+
+/// Date formats accepted in COA exports
+const DATE_FORMATS: [&str; 2] = ["%Y-%m-%d", "%m/%d/%Y"];
+
+/// Values labs report for a compound that was tested for but not found
+const NOT_DETECTED: [&str; 5] = ["nd", "n/d", "<loq", "bql", "not detected"];
+
+const CANNABINOID_COLUMNS: [&str; 7] = ["thca", "cbda", "cbg", "cbga", "cbn", "cbc", "thcv"];
+
+const BATCH_COLUMNS: [&str; 9] = [
+    "strain",
+    "grower",
+    "harvest_date",
+    "final_test_date",
+    "package_date",
+    "thc",
+    "thc_content",
+    "cbd",
+    "cbd_content",
+];
+
+fn normalize_column(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+/// Split a lab export into rows. CSV exports need a header row; JSON exports are an
+/// array of flat objects (or a single object for one sample).
+///
+/// Ex:
+///     `strain,grower,harvest_date,thc,thca,cbd,limonene,myrcene
+///      Gelato,Cresco,2022-05-02,0.8,24.1,ND,0.42,0.31`
+pub fn parse_rows(format: CoaFormat, body: &[u8]) -> Result<Vec<CoaRow>, String> {
+    match format {
+        CoaFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(body);
+            let headers: Vec<String> = reader
+                .headers()
+                .map_err(|e| e.to_string())?
+                .iter()
+                .map(normalize_column)
+                .collect();
+            reader
+                .records()
+                .map(|record| {
+                    let record = record.map_err(|e| e.to_string())?;
+                    Ok(headers
+                        .iter()
+                        .zip(record.iter())
+                        .filter(|(_, v)| !v.is_empty())
+                        .map(|(h, v)| (h.clone(), v.to_owned()))
+                        .collect())
+                })
+                .collect()
+        }
+        CoaFormat::Json => {
+            let objects = match serde_json::from_slice(body).map_err(|e| e.to_string())? {
+                Value::Array(values) => values,
+                obj @ Value::Object(_) => vec![obj],
+                _ => return Err("expected an array of objects".to_owned()),
+            };
+            objects
+                .into_iter()
+                .map(|obj| match obj {
+                    Value::Object(fields) => Ok(fields
+                        .into_iter()
+                        .filter_map(|(k, v)| {
+                            let v = match v {
+                                Value::Null => return None,
+                                Value::String(s) => s.trim().to_owned(),
+                                other => other.to_string(),
+                            };
+                            Some((normalize_column(&k), v)).filter(|(_, v)| !v.is_empty())
+                        })
+                        .collect()),
+                    _ => Err("expected an array of objects".to_owned()),
+                })
+                .collect()
+        }
+    }
+}
+
+/// A percentage such as `24.1` or `24.1%`. Not-detected markers read as `None`.
+fn parse_percent(value: &str) -> Result<Option<f32>, String> {
+    let value = value.trim().trim_end_matches('%').trim();
+    if NOT_DETECTED.contains(&value.to_lowercase().as_str()) {
+        return Ok(None);
+    }
+    value
+        .parse::<f32>()
+        .map(Some)
+        .map_err(|_| "is not a number".to_owned())
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    DATE_FORMATS
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(value, f).ok())
+        .ok_or_else(|| "is not a date (YYYY-MM-DD)".to_owned())
+}
+
+/// A COA row read into the records it will create. Ids are resolved later.
+#[derive(Debug)]
+struct CoaRecord {
+    strain: Option<String>,
+    grower: Option<String>,
+    batch: NewBatch,
+    cannabinoids: NewCannabinoids,
+    terpenes: NewTerpenes,
+}
+
+impl CoaRecord {
+    /// Read every known column of `row`, adding an error for each unreadable value
+    fn parse(row: &CoaRow, catalog: &[TerpeneCompound], errors: &mut ValidationErrors) -> Self {
+        let mut record = CoaRecord {
+            strain: row.get("strain").cloned(),
+            grower: row.get("grower").cloned(),
+            batch: NewBatch::new(),
+            cannabinoids: NewCannabinoids::default(),
+            terpenes: NewTerpenes::new(),
+        };
+        if record.strain.is_none() {
+            errors.add("strain", "is required");
+        }
+        if record.grower.is_none() {
+            errors.add("grower", "is required");
+        }
+        if !row.contains_key("thc") && !row.contains_key("thc_content") {
+            errors.add("thc", "is required");
+        }
+
+        let mut percent = |field: &str| {
+            row.get(field)
+                .map(|v| parse_percent(v))
+                .transpose()
+                .unwrap_or_else(|e| {
+                    errors.add(field, e);
+                    None
+                })
+                .flatten()
+        };
+        record.batch.thc_content = percent("thc")
+            .or_else(|| percent("thc_content"))
+            .unwrap_or(0.0);
+        record.batch.cbd_content = percent("cbd")
+            .or_else(|| percent("cbd_content"))
+            .unwrap_or(0.0);
+
+        let c = &mut record.cannabinoids;
+        c.thca = percent("thca");
+        c.cbda = percent("cbda");
+        c.cbg = percent("cbg");
+        c.cbga = percent("cbga");
+        c.cbn = percent("cbn");
+        c.cbc = percent("cbc");
+        c.thcv = percent("thcv");
+        for compound in catalog {
+            if let Some(amt) = percent(&compound.name) {
+                record.terpenes.compounds.insert(compound.name.clone(), amt);
+            }
+        }
+
+        let mut date = |field: &str| {
+            row.get(field)
+                .and_then(|v| parse_date(v).map_err(|e| errors.add(field, e)).ok())
+        };
+        record.batch.harvest_date = date("harvest_date");
+        record.batch.final_test_date = date("final_test_date");
+        record.batch.package_date = date("package_date");
+        record
+    }
+
+    fn has_cannabinoids(&self) -> bool {
+        let c = &self.cannabinoids;
+        [c.thca, c.cbda, c.cbg, c.cbga, c.cbn, c.cbc, c.thcv]
+            .iter()
+            .any(Option::is_some)
+    }
+}
+
+/// A batch created (or, in a dry run, that would be created) from one COA row
+#[derive(Debug, Serialize)]
+pub struct ImportedBatch {
+    pub row: usize,
+    pub strain: String,
+    pub grower: String,
+    pub batch: Batch,
+    pub cannabinoids: Option<Cannabinoids>,
+    pub terpenes: Option<Terpenes>,
+}
+
+/// Everything wrong with one COA row
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    pub row: usize,
+    pub errors: Vec<FieldError>,
+}
+
+/// Outcome of a COA import. Rows are numbered from 1, not counting a CSV header.
+/// Unless every row imports cleanly nothing is saved; a dry run never saves anything.
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: Vec<ImportedBatch>,
+    pub errors: Vec<RowError>,
+    /// Columns that are neither batch fields, cannabinoids nor catalog terpenes
+    pub ignored_columns: Vec<String>,
+}
+
+impl ImportReport {
+    /// The report's row errors as one set of field errors named `rows[N].field`
+    pub fn validation_errors(&self) -> ValidationErrors {
+        let mut all = ValidationErrors::default();
+        for row in &self.errors {
+            for e in &row.errors {
+                all.add(&format!("rows[{}].{}", row.row, e.field), e.message.clone());
+            }
+        }
+        all
+    }
+}
+
+/// Find the one strain or grower whose name matches `name`
+fn resolve<T>(
+    matches: Vec<T>,
+    field: &str,
+    name: &str,
+    errors: &mut ValidationErrors,
+) -> Option<T> {
+    match matches.len() {
+        1 => matches.into_iter().next(),
+        0 => {
+            errors.add(field, format!("no {} named \"{}\"", field, name));
+            None
+        }
+        n => {
+            errors.add(field, format!("\"{}\" matches {} {}s", name, n, field));
+            None
+        }
+    }
+}
+
+/// Keep `result`'s errors except those for `skip`ped fields
+fn merge(errors: &mut ValidationErrors, result: Result<(), ValidationErrors>, skip: &[&str]) {
+    if let Err(e) = result {
+        for f in
+            e.0.into_iter()
+                .filter(|f| !skip.contains(&f.field.as_str()))
+        {
+            errors.add(&f.field, f.message);
+        }
+    }
+}
+
+fn import_row(
+    conn: &PgConnection,
+    row: &CoaRow,
+    catalog: &[TerpeneCompound],
+) -> Result<ImportedBatch, Error> {
+    let mut errors = ValidationErrors::default();
+    let mut record = CoaRecord::parse(row, catalog, &mut errors);
+
+    let strain = match &record.strain {
+        Some(n) => resolve(
+            Strain::filter(conn, StrainField::Name(n.clone()))?,
+            "strain",
+            n,
+            &mut errors,
+        ),
+        None => None,
+    };
+    let grower = match &record.grower {
+        Some(n) => resolve(
+            Grower::filter(conn, GrowerField::Name(n.clone()))?,
+            "grower",
+            n,
+            &mut errors,
+        ),
+        None => None,
+    };
+    record.batch.strain_id = strain.as_ref().map_or(-1, |s| s.id);
+    record.batch.grower_id = grower.as_ref().map_or(-1, |g| g.id);
+    merge(
+        &mut errors,
+        record.batch.validate(),
+        &["strain_id", "grower_id"],
+    );
+    merge(&mut errors, record.cannabinoids.validate(), &["batch_id"]);
+    merge(&mut errors, record.terpenes.validate(), &["batch_id"]);
+    errors.into_result()?;
+
+    let batch = record.batch.create(conn)?;
+    let cannabinoids = if record.has_cannabinoids() {
+        record.cannabinoids.batch_id = batch.id;
+        Some(record.cannabinoids.create(conn)?)
+    } else {
+        None
+    };
+    let terpenes = if record.terpenes.compounds.is_empty() {
+        None
+    } else {
+        record.terpenes.batch_id = batch.id;
+        Some(record.terpenes.create(conn)?)
+    };
+
+    Ok(ImportedBatch {
+        row: 0,
+        strain: strain.map(|s| s.name).unwrap_or_default(),
+        grower: grower.map(|g| g.name).unwrap_or_default(),
+        batch,
+        cannabinoids,
+        terpenes,
+    })
+}
+
+/// Errors of a failed row: the validation errors it raised, or the database error under `row`
+fn row_errors(e: Error) -> Vec<FieldError> {
+    match e {
+        Error::QueryBuilderError(e) => match e.downcast::<ValidationErrors>() {
+            Ok(errors) => errors.0,
+            Err(e) => vec![FieldError {
+                field: "row".to_owned(),
+                message: e.to_string(),
+            }],
+        },
+        e => vec![FieldError {
+            field: "row".to_owned(),
+            message: e.to_string(),
+        }],
+    }
+}
+
+/// Create a batch, plus its cannabinoid panel and terpene profile when the row has them,
+/// for every row of a COA export. Strains and growers are matched by name with `ILIKE`.
+///
+/// All rows run in one transaction that is only committed if every row succeeds and
+/// `dry_run` is false. Each row runs in its own savepoint, so one bad row doesn't stop
+/// the rest from being checked.
+///
+/// Example:
+/// let rows = parse_rows(CoaFormat::Csv, b"strain,grower,thc\nGelato,Cresco,21.4").unwrap();
+/// let report = import_coa(&conn, &rows, true).unwrap();
+/// assert_eq!(report.created.len(), 1);
+pub fn import_coa(
+    conn: &PgConnection,
+    rows: &[CoaRow],
+    dry_run: bool,
+) -> Result<ImportReport, Error> {
+    let catalog = TerpeneCompound::catalog(conn)?;
+    let known: BTreeSet<&str> = BATCH_COLUMNS
+        .iter()
+        .chain(CANNABINOID_COLUMNS.iter())
+        .copied()
+        .chain(catalog.iter().map(|c| c.name.as_str()))
+        .collect();
+    let mut report = ImportReport {
+        dry_run,
+        created: Vec::new(),
+        errors: Vec::new(),
+        ignored_columns: rows
+            .iter()
+            .flat_map(|r| r.keys())
+            .filter(|k| !known.contains(k.as_str()))
+            .cloned()
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect(),
+    };
+
+    let res = conn.transaction::<(), Error, _>(|| {
+        for (i, row) in rows.iter().enumerate() {
+            match conn.transaction(|| import_row(conn, row, &catalog)) {
+                Ok(imported) => report.created.push(ImportedBatch {
+                    row: i + 1,
+                    ..imported
+                }),
+                Err(e) => report.errors.push(RowError {
+                    row: i + 1,
+                    errors: row_errors(e),
+                }),
+            }
+        }
+        if dry_run || !report.errors.is_empty() {
+            Err(Error::RollbackTransaction)
+        } else {
+            Ok(())
+        }
+    });
+
+    match res {
+        Ok(()) | Err(Error::RollbackTransaction) => Ok(report),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rows_parsed() {
+        let body = b"Strain, Grower ,Harvest Date,THC,THCA,CBD,Beta-Myrcene\n\
+                     Gelato,Cresco,2022-05-02,0.8,24.1%,ND,0.3\n";
+        let rows = parse_rows(CoaFormat::Csv, body).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["strain"], "Gelato");
+        assert_eq!(rows[0]["grower"], "Cresco");
+        assert_eq!(rows[0]["harvest_date"], "2022-05-02");
+        assert_eq!(rows[0]["beta_myrcene"], "0.3");
+    }
+
+    #[test]
+    fn json_rows_parsed() {
+        let body = br#"[{"strain": "Gelato", "grower": "Cresco", "thc": 21.5, "cbd": null}]"#;
+        let rows = parse_rows(CoaFormat::Json, body).unwrap();
+        assert_eq!(rows[0]["thc"], "21.5");
+        assert!(!rows[0].contains_key("cbd"));
+        assert!(parse_rows(CoaFormat::Json, b"42").is_err());
+    }
+
+    #[test]
+    fn percentages_and_dates_read() {
+        assert_eq!(parse_percent("24.1%"), Ok(Some(24.1)));
+        assert_eq!(parse_percent("<LOQ"), Ok(None));
+        assert!(parse_percent("lots").is_err());
+        assert_eq!(
+            parse_date("05/02/2022"),
+            Ok(NaiveDate::from_ymd(2022, 5, 2))
+        );
+    }
+
+    #[test]
+    fn unreadable_values_reported() {
+        let mut row = CoaRow::new();
+        row.insert("strain".to_owned(), "Gelato".to_owned());
+        row.insert("thc".to_owned(), "lots".to_owned());
+        row.insert("package_date".to_owned(), "someday".to_owned());
+        let mut errors = ValidationErrors::default();
+        CoaRecord::parse(&row, &[], &mut errors);
+        let fields: Vec<&str> = errors.0.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["grower", "thc", "package_date"]);
+    }
+}
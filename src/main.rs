@@ -1,14 +1,21 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod archive;
+pub mod auth;
 mod db;
+pub mod errors;
 pub mod handlers;
+pub mod import;
 pub mod models;
 pub mod schema;
+pub mod validation;
 
+use self::auth::TokenSecret;
+use self::errors::ApiError;
 use self::handlers::*;
 
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, HttpResponse, HttpServer};
 
 use diesel::pg::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
@@ -18,15 +25,23 @@ use std::env;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 pub mod exports {
+    pub use crate::models::ConsumptionMethodMapping as Consumption_method;
+    pub use crate::models::CultivationTypeMapping as Cultivation_type;
+    pub use crate::models::InventoryUnitMapping as Inventory_unit;
+    pub use crate::models::ProductFormatMapping as Product_format;
     pub use crate::models::SpeciesMapping as Species;
 }
 
+/// Largest request body: a lab export, a catalog archive or a bulk request's records
+const MAX_UPLOAD_SIZE: usize = 16 * 1024 * 1024;
+
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
+    let token_secret = TokenSecret(env::var("TOKEN_SECRET").expect("TOKEN_SECRET must be set."));
     let manager = ConnectionManager::<PgConnection>::new(database_url);
     let pool = r2d2::Pool::builder()
         .build(manager)
@@ -38,16 +53,114 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .data(pool.clone())
+            .data(token_secret.clone())
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(MAX_UPLOAD_SIZE)
+                    .error_handler(|e, _| ApiError::BadRequest(e.to_string()).into()),
+            )
+            .app_data(web::PayloadConfig::new(MAX_UPLOAD_SIZE))
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|e, _| ApiError::BadRequest(e.to_string()).into()),
+            )
+            .app_data(
+                web::PathConfig::default()
+                    .error_handler(|e, _| ApiError::NotFound(e.to_string()).into()),
+            )
+            .default_service(web::route().to(|| async {
+                Err::<HttpResponse, _>(ApiError::NotFound("No such route".to_owned()))
+            }))
+            .service(register)
+            .service(login)
+            .service(logout)
             .service(get_strains_by_id)
+            .service(get_strain_parents)
+            .service(post_strain_parent)
+            .service(delete_strain_parent)
+            .service(get_strain_children)
+            .service(get_strain_lineage)
+            .service(get_strain_stats)
+            .service(get_where_to_buy)
+            .service(get_strain_aliases)
+            .service(post_strain_alias)
+            .service(delete_strain_alias)
             .service(post_new_strain)
+            .service(post_new_strains)
             .service(query_strain)
             .service(post_new_batch)
+            .service(post_new_batches)
+            .service(post_batch_import)
+            .service(get_grower_leaderboard)
             .service(get_grower_by_id)
+            .service(get_grower_scorecard)
+            .service(get_grower_aliases)
+            .service(post_grower_alias)
+            .service(delete_grower_alias)
             .service(query_growers)
+            .service(search)
             .service(post_new_grower)
-            .service(get_all_batches)
+            .service(post_new_growers)
+            .service(get_batches)
+            .service(get_export)
+            .service(post_import)
             .service(get_batches_by_strain_id)
             .service(get_batches_by_grower_id)
+            .service(get_terpene_catalog)
+            .service(post_new_terpene_compound)
+            .service(post_new_terpenes)
+            .service(get_terpenes_by_batch_id)
+            .service(put_terpenes_by_batch_id)
+            .service(delete_terpenes_by_batch_id)
+            .service(post_new_cannabinoids)
+            .service(get_cannabinoids_by_batch_id)
+            .service(put_cannabinoids_by_batch_id)
+            .service(delete_cannabinoids_by_batch_id)
+            .service(put_strain)
+            .service(patch_strain)
+            .service(delete_strain)
+            .service(put_grower)
+            .service(patch_grower)
+            .service(delete_grower)
+            .service(put_batch)
+            .service(patch_batch)
+            .service(delete_batch)
+            .service(get_my_sessions)
+            .service(post_new_session)
+            .service(get_my_session_by_id)
+            .service(patch_my_session)
+            .service(delete_my_session)
+            .service(get_sessions_by_batch_id)
+            .service(post_new_session_by_batch_id)
+            .service(post_new_review)
+            .service(get_reviews_by_batch_id)
+            .service(get_products_by_batch_id)
+            .service(post_new_product_by_batch_id)
+            .service(get_prices_by_batch_id)
+            .service(post_new_price_by_batch_id)
+            .service(get_reviews_by_strain_id)
+            .service(get_my_reviews)
+            .service(patch_my_review)
+            .service(delete_my_review)
+            .service(get_inventory)
+            .service(post_new_inventory)
+            .service(get_inventory_summary)
+            .service(get_inventory_by_id)
+            .service(patch_inventory)
+            .service(delete_inventory)
+            .service(get_dispensaries)
+            .service(post_new_dispensary)
+            .service(get_dispensary_by_id)
+            .service(put_dispensary)
+            .service(patch_dispensary)
+            .service(delete_dispensary)
+            .service(get_listings_by_dispensary_id)
+            .service(get_listings_by_value)
+            .service(get_listings)
+            .service(post_new_listing)
+            .service(get_listing_by_id)
+            .service(patch_listing)
+            .service(delete_listing)
     })
     .bind(addrress)?
     .run()
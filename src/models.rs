@@ -1,12 +1,19 @@
-use super::schema::{batches, growers, strains, terpenes};
+use super::schema::{
+    auth_tokens, batches, cannabinoids, dispensaries, grower_aliases, growers, inventory, listings,
+    price_observations, reviews, session_draws, sessions, strain_aliases, strain_parents, strains,
+    terpene_compounds, terpene_measurements, terpenes, users,
+};
 
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::deserialize::FromSql;
-use diesel::sql_types::{Date, Float4, Int4, Integer, Nullable, VarChar};
+use diesel::sql_types::{
+    Array, BigInt, Bool, Date, Double, Float4, Int4, Integer, Nullable, Timestamp, VarChar,
+};
 use diesel::{QueryDsl, Queryable, QueryableByName, RunQueryDsl};
 use diesel_derive_enum::DbEnum;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
+use std::collections::BTreeMap;
 use std::fmt;
 
 #[derive(Clone, Debug, DbEnum, Deserialize, Serialize, PartialEq)]
@@ -16,17 +23,80 @@ pub enum Species {
     Hybrid,
 }
 
-/// Struct used to create new `Strain` object
-#[derive(Debug, Deserialize, Serialize, Insertable)]
+/// How a batch was consumed during a `Session`
+#[derive(Clone, Copy, Debug, DbEnum, Deserialize, Serialize, PartialEq)]
+pub enum ConsumptionMethod {
+    Flower,
+    Vape,
+    Edible,
+    Concentrate,
+}
+
+/// How a grower cultivates its plants
+#[derive(Clone, Copy, Debug, DbEnum, Deserialize, Serialize, PartialEq)]
+pub enum CultivationType {
+    Indoor,
+    Outdoor,
+    Greenhouse,
+    LivingSoil,
+}
+
+/// The form a product is sold in
+#[derive(Clone, Copy, Debug, Default, DbEnum, Deserialize, Serialize, PartialEq)]
+pub enum ProductFormat {
+    #[default]
+    Flower,
+    PreRoll,
+    Vape,
+    Concentrate,
+    Edible,
+}
+
+/// Unit a batch's `thc_content` and `cbd_content` are given in: a percentage of its weight,
+/// or milligrams in each packaged unit for edibles
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub enum PotencyUnit {
+    Percent,
+    MgPerUnit,
+}
+
+impl ProductFormat {
+    pub fn potency_unit(self) -> PotencyUnit {
+        match self {
+            ProductFormat::Edible => PotencyUnit::MgPerUnit,
+            _ => PotencyUnit::Percent,
+        }
+    }
+}
+
+/// Unit an `Inventory` quantity is counted in. Eighths are 3.5 grams;
+/// units are individually packaged products such as edibles or carts.
+#[derive(Clone, Copy, Debug, DbEnum, Deserialize, Serialize, PartialEq)]
+pub enum InventoryUnit {
+    Grams,
+    Eighths,
+    Units,
+}
+
+/// Struct used to create new `Strain` object, or to replace every field of an existing one
+#[derive(Debug, Deserialize, Serialize, Insertable, AsChangeset)]
 #[table_name = "strains"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewStrain {
+    #[serde(deserialize_with = "trimmed")]
     pub name: String,
     pub species: Species,
+    pub breeder: Option<String>,
+    pub phenotype: Option<String>,
+    pub indica_percent: Option<i32>,
+    pub sativa_percent: Option<i32>,
 }
 
-/// Struct used to create new `Batch` object
-#[derive(Debug, Deserialize, Serialize, Insertable)]
+/// Struct used to create new `Batch` object, or to replace every field of an existing one.
+/// `format` defaults to flower; a product made from a flower batch names it as `parent_batch_id`.
+#[derive(Debug, Deserialize, Serialize, Insertable, AsChangeset)]
 #[table_name = "batches"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewBatch {
     pub strain_id: i32,
     pub harvest_date: Option<NaiveDate>,
@@ -35,41 +105,332 @@ pub struct NewBatch {
     pub grower_id: i32,
     pub thc_content: f32,
     pub cbd_content: f32,
+    #[serde(default)]
+    pub format: ProductFormat,
+    pub parent_batch_id: Option<i32>,
 }
 
-/// Struct used to create new `Grower` object
-#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+/// A product made from a flower batch. Its strain, grower and harvest date are the
+/// parent batch's.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NewProduct {
+    pub format: ProductFormat,
+    pub final_test_date: Option<NaiveDate>,
+    pub package_date: Option<NaiveDate>,
+    pub thc_content: f32,
+    pub cbd_content: f32,
+}
+
+/// Struct used to create new `Grower` object, or to replace every field of an existing one.
+/// Only `name` is required; a grower is `active` unless told otherwise.
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable, AsChangeset)]
 #[table_name = "growers"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewGrower {
+    #[serde(deserialize_with = "trimmed")]
     pub name: String,
+    pub license_number: Option<String>,
+    pub state: Option<String>,
+    pub city: Option<String>,
+    pub website: Option<String>,
+    pub cultivation_type: Option<CultivationType>,
+    #[serde(default = "active_by_default")]
+    pub active: bool,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
-#[table_name = "terpenes"]
+fn active_by_default() -> bool {
+    true
+}
+
+/// Names are stored without the whitespace around them, as they are validated
+fn trimmed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    String::deserialize(deserializer).map(|s| s.trim().to_owned())
+}
+
+fn trimmed_opt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(|s| s.map(|s| s.trim().to_owned()))
+}
+
+impl Default for NewGrower {
+    fn default() -> Self {
+        NewGrower {
+            name: String::new(),
+            license_number: None,
+            state: None,
+            city: None,
+            website: None,
+            cultivation_type: None,
+            active: true,
+        }
+    }
+}
+
+/// Partial update of a `Strain`. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "strains"]
+pub struct StrainChanges {
+    #[serde(default, deserialize_with = "trimmed_opt")]
+    pub name: Option<String>,
+    pub species: Option<Species>,
+    pub breeder: Option<String>,
+    pub phenotype: Option<String>,
+    pub indica_percent: Option<i32>,
+    pub sativa_percent: Option<i32>,
+}
+
+/// Partial update of a `Batch`. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "batches"]
+pub struct BatchChanges {
+    pub strain_id: Option<i32>,
+    pub harvest_date: Option<NaiveDate>,
+    pub final_test_date: Option<NaiveDate>,
+    pub package_date: Option<NaiveDate>,
+    pub grower_id: Option<i32>,
+    pub thc_content: Option<f32>,
+    pub cbd_content: Option<f32>,
+    pub format: Option<ProductFormat>,
+    pub parent_batch_id: Option<i32>,
+}
+
+/// Partial update of a `Grower`. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "growers"]
+pub struct GrowerChanges {
+    #[serde(default, deserialize_with = "trimmed_opt")]
+    pub name: Option<String>,
+    pub license_number: Option<String>,
+    pub state: Option<String>,
+    pub city: Option<String>,
+    pub website: Option<String>,
+    pub cultivation_type: Option<CultivationType>,
+    pub active: Option<bool>,
+}
+
+/// Struct used to create or replace a batch's `Terpenes` profile. Every key other than
+/// `batch_id` names a compound from the terpene catalog and maps to its percentage.
+/// `batch_id` may be omitted from request bodies; handlers take it from the path.
+///
+/// Ex:
+///     `{"limonene": 0.42, "myrcene": 0.31, "terpinolene": 0.08}`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
 pub struct NewTerpenes {
     pub batch_id: i32,
-    pub caryophyllene: Option<f32>,
-    pub humulene: Option<f32>,
-    pub limonene: Option<f32>,
-    pub linalool: Option<f32>,
-    pub myrcene: Option<f32>,
-    pub pinene: Option<f32>,
+    #[serde(flatten)]
+    pub compounds: BTreeMap<String, f32>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+/// A batch's terpene profile, serialized with one key per measured compound
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Terpenes {
     pub id: i32,
     pub batch_id: i32,
-    pub caryophyllene: Option<f32>,
-    pub humulene: Option<f32>,
-    pub limonene: Option<f32>,
-    pub linalool: Option<f32>,
-    pub myrcene: Option<f32>,
-    pub pinene: Option<f32>,
+    #[serde(flatten)]
+    pub compounds: BTreeMap<String, f32>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, QueryableByName)]
+/// A terpene the API knows how to store. Measurements may only name compounds
+/// in this catalog.
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+pub struct TerpeneCompound {
+    pub id: i32,
+    pub name: String,
+}
+
+/// Struct used to add a compound to the terpene catalog
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "terpene_compounds"]
+pub struct NewTerpeneCompound {
+    pub name: String,
+}
+
+/// One compound's share of a terpene profile
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "terpene_measurements"]
+pub struct NewTerpeneMeasurement {
+    pub terpenes_id: i32,
+    pub compound_id: i32,
+    pub amount: f32,
+}
+
+/// Share of an acid cannabinoid's mass left after decarboxylation, used to turn THCA
+/// and CBDA into the THC and CBD they yield when heated
+pub const DECARB_FACTOR: f32 = 0.877;
+
+/// Total potential THC (or CBD) of a sample: the neutral form plus the decarboxylated acid
+///
+/// Ex:
+///     `total_potency(0.8, Some(24.0))` is `0.8 + 24.0 * 0.877`, about 21.85
+pub fn total_potency(neutral: f32, acid: Option<f32>) -> f32 {
+    neutral + acid.unwrap_or(0.0) * DECARB_FACTOR
+}
+
+/// How many times more THC than CBD (or CBD than THC) makes a strain dominant in it
+pub const CHEMOTYPE_RATIO: f32 = 5.0;
+
+/// Chemical classification of a strain by the balance of THC to CBD in its batches
+///
+/// Ex:
+///     `Chemotype::classify(21.0, 0.3)` is `Some(Chemotype::TypeI)`, serialized as `"I"`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum Chemotype {
+    /// THC-dominant
+    #[serde(rename = "I")]
+    TypeI,
+    /// THC and CBD in balance
+    #[serde(rename = "II")]
+    TypeII,
+    /// CBD-dominant
+    #[serde(rename = "III")]
+    TypeIII,
+}
+
+impl Chemotype {
+    /// Classify mean total THC and CBD. Without either there is nothing to classify.
+    pub fn classify(thc: f32, cbd: f32) -> Option<Chemotype> {
+        if thc <= 0.0 && cbd <= 0.0 {
+            None
+        } else if thc >= CHEMOTYPE_RATIO * cbd {
+            Some(Chemotype::TypeI)
+        } else if cbd >= CHEMOTYPE_RATIO * thc {
+            Some(Chemotype::TypeIII)
+        } else {
+            Some(Chemotype::TypeII)
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Chemotype::TypeI => "I",
+            Chemotype::TypeII => "II",
+            Chemotype::TypeIII => "III",
+        }
+    }
+}
+
+/// Mean total THC and CBD of a strain's batches, as selected by SQL. Both are NULL for a
+/// strain without batches.
+#[derive(Debug, Clone, QueryableByName)]
+pub struct PotencyRow {
+    #[sql_type = "Nullable<Float4>"]
+    pub thc: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbd: Option<f32>,
+}
+
+impl PotencyRow {
+    pub fn chemotype(&self) -> Option<Chemotype> {
+        match (self.thc, self.cbd) {
+            (Some(t), Some(c)) => Chemotype::classify(t, c),
+            _ => None,
+        }
+    }
+}
+
+/// Struct used to create or replace a batch's cannabinoid panel. THC and CBD
+/// themselves stay on the batch as `thc_content` and `cbd_content`.
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable, AsChangeset)]
+#[table_name = "cannabinoids"]
+#[changeset_options(treat_none_as_null = "true")]
+#[serde(default)]
+pub struct NewCannabinoids {
+    pub batch_id: i32,
+    pub thca: Option<f32>,
+    pub cbda: Option<f32>,
+    pub cbg: Option<f32>,
+    pub cbga: Option<f32>,
+    pub cbn: Option<f32>,
+    pub cbc: Option<f32>,
+    pub thcv: Option<f32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+pub struct Cannabinoids {
+    pub id: i32,
+    pub batch_id: i32,
+    pub thca: Option<f32>,
+    pub cbda: Option<f32>,
+    pub cbg: Option<f32>,
+    pub cbga: Option<f32>,
+    pub cbn: Option<f32>,
+    pub cbc: Option<f32>,
+    pub thcv: Option<f32>,
+}
+
+/// A batch's full cannabinoid panel: the stored panel plus the batch's THC and CBD
+/// and the totals derived from them
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CannabinoidPanel {
+    #[serde(flatten)]
+    pub cannabinoids: Cannabinoids,
+    pub thc: f32,
+    pub cbd: f32,
+    pub total_thc: f32,
+    pub total_cbd: f32,
+}
+
+impl CannabinoidPanel {
+    pub fn new(batch: &Batch, cannabinoids: Cannabinoids) -> CannabinoidPanel {
+        CannabinoidPanel {
+            thc: batch.thc_content,
+            cbd: batch.cbd_content,
+            total_thc: total_potency(batch.thc_content, cannabinoids.thca),
+            total_cbd: total_potency(batch.cbd_content, cannabinoids.cbda),
+            cannabinoids,
+        }
+    }
+}
+
+/// A `Batch` joined with its strain, grower and (if on file) terpene profile and cannabinoid
+/// panel. `total_thc` and `total_cbd` fall back to `thc_content` and `cbd_content` without a panel.
+/// All four are given in `potency_unit`, which follows from the batch's `format`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BatchResponse {
+    pub id: i32,
+    pub strain_id: i32,
+    pub strain: String,
+    pub harvest_date: Option<NaiveDate>,
+    pub final_test_date: Option<NaiveDate>,
+    pub package_date: Option<NaiveDate>,
+    pub grower_id: i32,
+    pub grower: String,
+    pub thc_content: f32,
+    pub cbd_content: f32,
+    pub format: ProductFormat,
+    pub potency_unit: PotencyUnit,
+    pub parent_batch_id: Option<i32>,
+    pub terpenes: Option<Terpenes>,
+    pub cannabinoids: Option<Cannabinoids>,
+    pub total_thc: f32,
+    pub total_cbd: f32,
+    pub rating: RatingSummary,
+}
+
+/// The original, id-less shape of `BatchResponse` for callers passing `?compact=true`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompactBatchResponse {
+    pub strain: String,
+    pub harvest_date: Option<NaiveDate>,
+    pub final_test_date: Option<NaiveDate>,
+    pub package_date: Option<NaiveDate>,
+    pub grower: String,
+    pub thc_content: f32,
+    pub cbd_content: f32,
+}
+
+/// Flat row produced by the batch query's `LEFT JOIN`s on `terpenes` and `cannabinoids`.
+/// Every terpene or cannabinoid column is NULL when the batch has no profile or panel.
+/// Terpene measurements arrive as two parallel arrays.
+#[derive(Debug, Clone, QueryableByName)]
+pub struct BatchRow {
+    #[sql_type = "Integer"]
+    pub id: i32,
+
+    #[sql_type = "Integer"]
+    pub strain_id: i32,
+
     #[sql_type = "VarChar"]
     pub strain: String,
 
@@ -82,6 +443,9 @@ pub struct BatchResponse {
     #[sql_type = "Nullable<Date>"]
     pub package_date: Option<NaiveDate>,
 
+    #[sql_type = "Integer"]
+    pub grower_id: i32,
+
     #[sql_type = "VarChar"]
     pub grower: String,
 
@@ -90,10 +454,270 @@ pub struct BatchResponse {
 
     #[sql_type = "Float4"]
     pub cbd_content: f32,
+
+    #[sql_type = "ProductFormatMapping"]
+    pub format: ProductFormat,
+
+    #[sql_type = "Nullable<Integer>"]
+    pub parent_batch_id: Option<i32>,
+
+    #[sql_type = "Nullable<Integer>"]
+    pub terpenes_id: Option<i32>,
+
+    /// Compound names of the terpene profile, in the same order as `terpene_amounts`
+    #[sql_type = "Nullable<Array<VarChar>>"]
+    pub terpene_names: Option<Vec<String>>,
+
+    #[sql_type = "Nullable<Array<Float4>>"]
+    pub terpene_amounts: Option<Vec<f32>>,
+
+    #[sql_type = "Nullable<Integer>"]
+    pub cannabinoids_id: Option<i32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thca: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbda: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbg: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbga: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbn: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbc: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thcv: Option<f32>,
+
+    #[diesel(embed)]
+    pub rating: RatingRow,
+}
+
+/// Review aggregates as selected by SQL, before being folded into a `RatingSummary`
+#[derive(Debug, Clone, QueryableByName)]
+pub struct RatingRow {
+    #[sql_type = "BigInt"]
+    pub review_count: i64,
+
+    #[sql_type = "Nullable<Double>"]
+    pub rating_avg: Option<f64>,
+
+    #[sql_type = "BigInt"]
+    pub rating_1: i64,
+
+    #[sql_type = "BigInt"]
+    pub rating_2: i64,
+
+    #[sql_type = "BigInt"]
+    pub rating_3: i64,
+
+    #[sql_type = "BigInt"]
+    pub rating_4: i64,
+
+    #[sql_type = "BigInt"]
+    pub rating_5: i64,
+}
+
+/// Aggregate rating of a batch or strain. `distribution[0]` is the number of
+/// 1-star reviews and `distribution[4]` the number of 5-star reviews.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct RatingSummary {
+    pub average: Option<f64>,
+    pub count: i64,
+    pub distribution: [i64; 5],
+}
+
+/// A `Strain` along with the aggregate rating of all of its batches
+#[derive(Debug, Serialize)]
+pub struct StrainResponse {
+    #[serde(flatten)]
+    pub strain: Strain,
+    pub rating: RatingSummary,
+    pub chemotype: Option<Chemotype>,
+}
+
+/// Potency of one group of a strain's batches as selected by SQL. `grouped_by` is
+/// `"strain"` for all of them, `"grower"` for one grower's and `"month"` for those
+/// harvested in one month.
+#[derive(Debug, Clone, QueryableByName)]
+pub struct StatsRow {
+    #[sql_type = "VarChar"]
+    pub grouped_by: String,
+
+    #[sql_type = "Nullable<Integer>"]
+    pub grower_id: Option<i32>,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub grower: Option<String>,
+
+    #[sql_type = "Nullable<Date>"]
+    pub month: Option<NaiveDate>,
+
+    #[sql_type = "BigInt"]
+    pub batch_count: i64,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thc_min: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thc_max: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thc_mean: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thc_median: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbd_min: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbd_max: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbd_mean: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub cbd_median: Option<f32>,
+}
+
+/// Mean amount of one compound across a strain's terpene profiles, as selected by SQL
+#[derive(Debug, Clone, QueryableByName)]
+pub struct CompoundMean {
+    #[sql_type = "VarChar"]
+    pub compound: String,
+
+    #[sql_type = "Float4"]
+    pub amount: f32,
+}
+
+/// Spread of total THC or CBD across a set of batches. Every field is `None` when the
+/// set is empty.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct PotencyStats {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub mean: Option<f32>,
+    pub median: Option<f32>,
+}
+
+/// Potency of the batches of a strain that one grower produced
+#[derive(Debug, Clone, Serialize)]
+pub struct GrowerPotency {
+    pub grower_id: i32,
+    pub grower: String,
+    pub batch_count: i64,
+    pub thc: PotencyStats,
+    pub cbd: PotencyStats,
+}
+
+/// Potency of the batches of a strain harvested in one month, given by its first day
+#[derive(Debug, Clone, Serialize)]
+pub struct MonthlyPotency {
+    pub month: NaiveDate,
+    pub batch_count: i64,
+    pub thc: PotencyStats,
+    pub cbd: PotencyStats,
+}
+
+/// How a strain's potency varies across its batches, growers and harvests. THC and CBD
+/// are totals (see `total_potency`). `terpenes` is the mean profile of the
+/// `profiled_batches` batches that have one, a compound missing from a profile
+/// counting as none of it.
+///
+/// Ex:
+///     `{"strain_id": 3, "batch_count": 2, "thc": {"min": 21.5, "max": 24.1, ...},
+///       "cbd": {...}, "profiled_batches": 1, "terpenes": {"limonene": 0.42},
+///       "growers": [{"grower_id": 1, "grower": "Cresco", "batch_count": 1, ...}],
+///       "monthly": [{"month": "2022-01-01", "batch_count": 1, ...}]}`
+#[derive(Debug, Clone, Serialize)]
+pub struct StrainStats {
+    pub strain_id: i32,
+    pub batch_count: i64,
+    pub thc: PotencyStats,
+    pub cbd: PotencyStats,
+    pub profiled_batches: i64,
+    pub terpenes: BTreeMap<String, f32>,
+    pub growers: Vec<GrowerPotency>,
+    pub monthly: Vec<MonthlyPotency>,
+}
+
+/// How consistently a grower produces, and how well it documents its batches, as
+/// selected by SQL. THC is total THC (see `total_potency`); `mean_thc_variance` is the
+/// mean over its strains of the sample variance of their batches' THC, so only strains
+/// with two or more batches count. Shares are fractions of the grower's batches from 0
+/// to 1, and `rank` is its place on the leaderboard by the metric that was asked for.
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct GrowerScore {
+    #[sql_type = "BigInt"]
+    pub rank: i64,
+
+    #[sql_type = "Integer"]
+    pub grower_id: i32,
+
+    #[sql_type = "VarChar"]
+    pub grower: String,
+
+    #[sql_type = "BigInt"]
+    pub strain_count: i64,
+
+    #[sql_type = "BigInt"]
+    pub batch_count: i64,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub mean_thc_variance: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub package_lag_days: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub profiled_share: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub complete_dates_share: Option<f32>,
+}
+
+/// Spread of total THC across one grower's batches of one strain. The variance is
+/// `None` for a strain with a single batch.
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct StrainConsistency {
+    #[sql_type = "Integer"]
+    pub strain_id: i32,
+
+    #[sql_type = "VarChar"]
+    pub strain: String,
+
+    #[sql_type = "BigInt"]
+    pub batch_count: i64,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thc_mean: Option<f32>,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub thc_variance: Option<f32>,
+}
+
+/// A grower's `GrowerScore` along with the consistency of each strain it grows
+///
+/// Ex:
+///     `{"rank": 2, "grower_id": 3, "grower": "Summa", "strain_count": 2, "batch_count": 3,
+///       "mean_thc_variance": 3.38, "package_lag_days": 20.5, "profiled_share": 0.33,
+///       "complete_dates_share": 0.33, "strains": [{"strain_id": 3, ...}]}`
+#[derive(Debug, Clone, Serialize)]
+pub struct GrowerScorecard {
+    #[serde(flatten)]
+    pub score: GrowerScore,
+    pub strains: Vec<StrainConsistency>,
 }
 
 /// Struct used for retrieving `Grower` object
-#[derive(Clone, Deserialize, Serialize, QueryableByName, Queryable)]
+#[derive(Debug, Clone, Deserialize, Serialize, QueryableByName, Queryable)]
 #[table_name = "growers"]
 pub struct Grower {
     #[sql_type = "Integer"]
@@ -101,6 +725,24 @@ pub struct Grower {
 
     #[sql_type = "VarChar"]
     pub name: String,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub license_number: Option<String>,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub state: Option<String>,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub city: Option<String>,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub website: Option<String>,
+
+    #[sql_type = "Nullable<CultivationTypeMapping>"]
+    pub cultivation_type: Option<CultivationType>,
+
+    #[sql_type = "Bool"]
+    pub active: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, QueryableByName, Queryable)]
@@ -129,9 +771,17 @@ pub struct Batch {
 
     #[sql_type = "Float4"]
     pub cbd_content: f32,
+
+    #[serde(default)]
+    #[sql_type = "ProductFormatMapping"]
+    pub format: ProductFormat,
+
+    #[serde(default)]
+    #[sql_type = "Nullable<Integer>"]
+    pub parent_batch_id: Option<i32>,
 }
 
-#[derive(Debug, Deserialize, Serialize, QueryableByName, Queryable)]
+#[derive(Debug, Clone, Deserialize, Serialize, QueryableByName, Queryable)]
 pub struct Strain {
     #[sql_type = "Integer"]
     pub id: i32,
@@ -141,6 +791,581 @@ pub struct Strain {
 
     #[sql_type = "SpeciesMapping"]
     pub species: Species,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub breeder: Option<String>,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub phenotype: Option<String>,
+
+    /// Indica share of the strain's genetics, e.g. 70 for "70/30 indica-dominant"
+    #[sql_type = "Nullable<Integer>"]
+    pub indica_percent: Option<i32>,
+
+    #[sql_type = "Nullable<Integer>"]
+    pub sativa_percent: Option<i32>,
+}
+
+/// Records that `parent_id` is one of the strains `strain_id` was bred from
+///
+/// Ex:
+///     `{"strain_id": 1, "parent_id": 7}` // Wedding Cake from Triangle Kush
+#[derive(
+    Debug, Clone, Copy, Deserialize, Serialize, Insertable, Queryable, QueryableByName, PartialEq,
+)]
+#[table_name = "strain_parents"]
+pub struct StrainParent {
+    pub strain_id: i32,
+    pub parent_id: i32,
+}
+
+/// A strain and its ancestors, as deep as was asked for. A strain that appears on more
+/// than one branch is repeated on each of them.
+///
+/// Ex:
+///     `{"id": 1, "name": "Wedding Cake", ..., "parents": [
+///         {"id": 7, "name": "Triangle Kush", ..., "parents": []},
+///         {"id": 8, "name": "Animal Mints", ..., "parents": []}]}`
+#[derive(Debug, Serialize)]
+pub struct StrainLineage {
+    #[serde(flatten)]
+    pub strain: Strain,
+    pub parents: Vec<StrainLineage>,
+}
+
+/// Another name a strain is sold under, such as "GSC" for Girl Scout Cookies
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+pub struct StrainAlias {
+    pub id: i32,
+    pub strain_id: i32,
+    pub alias: String,
+}
+
+/// Struct used to create new `StrainAlias` object
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "strain_aliases"]
+pub struct NewStrainAlias {
+    pub strain_id: i32,
+    #[serde(deserialize_with = "trimmed")]
+    pub alias: String,
+}
+
+/// Another name a grower trades under
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+pub struct GrowerAlias {
+    pub id: i32,
+    pub grower_id: i32,
+    pub alias: String,
+}
+
+/// Struct used to create new `GrowerAlias` object
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "grower_aliases"]
+pub struct NewGrowerAlias {
+    pub grower_id: i32,
+    #[serde(deserialize_with = "trimmed")]
+    pub alias: String,
+}
+
+/// A strain or grower found by a fuzzy search, with the name or alias that matched and
+/// how closely it did, from 0 to 1
+///
+/// Ex:
+///     `{"id": 9, "name": "Girl Scout Cookies", "matched": "GSC", "score": 1.0}`
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct SearchHit {
+    #[sql_type = "Integer"]
+    pub id: i32,
+
+    #[sql_type = "VarChar"]
+    pub name: String,
+
+    #[sql_type = "VarChar"]
+    pub matched: String,
+
+    #[sql_type = "Float4"]
+    pub score: f32,
+}
+
+/// Best matches of a search among strains and among growers
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResults {
+    pub strains: Vec<SearchHit>,
+    pub growers: Vec<SearchHit>,
+}
+
+/// Struct used for retrieving `Inventory` object: a quantity of one batch that a user has on hand.
+/// When `low_stock_threshold` is unset the default for the unit is used
+/// (see `InventoryUnit::default_low_stock`).
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, QueryableByName)]
+#[table_name = "inventory"]
+pub struct Inventory {
+    pub id: i32,
+    pub user_id: i32,
+    pub batch_id: i32,
+    pub quantity: f32,
+    pub unit: InventoryUnit,
+    pub purchase_price_cents: Option<i32>,
+    pub purchase_date: Option<NaiveDate>,
+    pub location: Option<String>,
+    pub low_stock_threshold: Option<f32>,
+}
+
+/// Stock a session took out of one inventory item, in the item's unit
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "session_draws"]
+pub struct NewSessionDraw {
+    pub session_id: i32,
+    pub inventory_id: i32,
+    pub quantity: f32,
+}
+
+/// Struct used to create new `Inventory` object. `user_id` comes from the bearer token.
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "inventory"]
+pub struct NewInventory {
+    #[serde(skip_deserializing, default)]
+    pub user_id: i32,
+    pub batch_id: i32,
+    pub quantity: f32,
+    pub unit: InventoryUnit,
+    pub purchase_price_cents: Option<i32>,
+    pub purchase_date: Option<NaiveDate>,
+    pub location: Option<String>,
+    pub low_stock_threshold: Option<f32>,
+}
+
+/// Partial update of an `Inventory`. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "inventory"]
+pub struct InventoryChanges {
+    pub quantity: Option<f32>,
+    pub unit: Option<InventoryUnit>,
+    pub purchase_price_cents: Option<i32>,
+    pub purchase_date: Option<NaiveDate>,
+    pub location: Option<String>,
+    pub low_stock_threshold: Option<f32>,
+}
+
+/// A user's stock of one strain across every batch and unit
+#[derive(Debug, Clone, Deserialize, Serialize, QueryableByName)]
+pub struct InventorySummary {
+    #[sql_type = "Integer"]
+    pub strain_id: i32,
+
+    #[sql_type = "VarChar"]
+    pub strain: String,
+
+    #[sql_type = "BigInt"]
+    pub items: i64,
+
+    /// Grams on hand, with eighths converted to grams
+    #[sql_type = "Double"]
+    pub grams: f64,
+
+    #[sql_type = "Double"]
+    pub units: f64,
+
+    #[sql_type = "BigInt"]
+    pub spent_cents: i64,
+}
+
+/// Lowest and highest star rating a `Review` may give
+pub const MIN_RATING: i16 = 1;
+pub const MAX_RATING: i16 = 5;
+
+/// Struct used for retrieving `Review` object: one user's rating of one batch
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, QueryableByName)]
+#[table_name = "reviews"]
+pub struct Review {
+    pub id: i32,
+    pub user_id: i32,
+    pub batch_id: i32,
+    pub rating: i16,
+    pub body: Option<String>,
+    pub effects: Vec<String>,
+    pub flavors: Vec<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Struct used to create new `Review` object. `user_id` comes from the bearer token
+/// and `batch_id` from the path.
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "reviews"]
+pub struct NewReview {
+    #[serde(skip_deserializing, default)]
+    pub user_id: i32,
+    #[serde(skip_deserializing, default)]
+    pub batch_id: i32,
+    pub rating: i16,
+    pub body: Option<String>,
+    #[serde(default)]
+    pub effects: Vec<String>,
+    #[serde(default)]
+    pub flavors: Vec<String>,
+}
+
+/// Partial update of a `Review`. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "reviews"]
+pub struct ReviewChanges {
+    pub rating: Option<i16>,
+    pub body: Option<String>,
+    pub effects: Option<Vec<String>>,
+    pub flavors: Option<Vec<String>>,
+}
+
+/// Struct used for retrieving `User` object. The password hash is never serialized.
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// Struct used to create new `User` object. `password_hash` comes from
+/// `auth::hash_password`; the plain password is never stored.
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "users"]
+pub struct NewUser {
+    pub username: String,
+    pub password_hash: String,
+}
+
+/// Request body for `/auth/register` and `/auth/login`. The username is trimmed, so it is
+/// registered and looked up the same way.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Credentials {
+    #[serde(deserialize_with = "trimmed")]
+    pub username: String,
+    pub password: String,
+}
+
+/// A bearer token that has been issued and not yet logged out
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+pub struct AuthToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub jti: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Struct used to record a newly issued bearer token
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "auth_tokens"]
+pub struct NewAuthToken {
+    pub user_id: i32,
+    pub jti: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Struct used for retrieving `Session` object: one logged use of a batch by a user.
+/// `amount` is in grams.
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, QueryableByName)]
+#[table_name = "sessions"]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    pub batch_id: i32,
+    pub consumed_at: NaiveDateTime,
+    pub method: ConsumptionMethod,
+    pub amount: Option<f32>,
+    pub notes: Option<String>,
+}
+
+/// Struct used to create new `Session` object. `user_id` always comes from the bearer
+/// token, and `consumed_at` defaults to now when left out.
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "sessions"]
+pub struct NewSession {
+    #[serde(skip_deserializing, default)]
+    pub user_id: i32,
+    #[serde(default)]
+    pub batch_id: i32,
+    pub consumed_at: Option<NaiveDateTime>,
+    pub method: ConsumptionMethod,
+    pub amount: Option<f32>,
+    pub notes: Option<String>,
+}
+
+/// Partial update of a `Session`. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "sessions"]
+pub struct SessionChanges {
+    pub batch_id: Option<i32>,
+    pub consumed_at: Option<NaiveDateTime>,
+    pub method: Option<ConsumptionMethod>,
+    pub amount: Option<f32>,
+    pub notes: Option<String>,
+}
+
+/// Struct used for retrieving `Dispensary` object: a store that sells batches
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, QueryableByName)]
+#[table_name = "dispensaries"]
+pub struct Dispensary {
+    pub id: i32,
+    pub name: String,
+    pub address: Option<String>,
+    pub license_number: Option<String>,
+}
+
+/// Struct used to create new `Dispensary` object, or to replace every field of an existing one
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable, AsChangeset)]
+#[table_name = "dispensaries"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewDispensary {
+    #[serde(deserialize_with = "trimmed")]
+    pub name: String,
+    pub address: Option<String>,
+    pub license_number: Option<String>,
+}
+
+/// Partial update of a `Dispensary`. Fields left as `None` are not touched.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "dispensaries"]
+pub struct DispensaryChanges {
+    #[serde(default, deserialize_with = "trimmed_opt")]
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub license_number: Option<String>,
+}
+
+/// Struct used for retrieving `Listing` object: a product made from a batch, on a
+/// dispensary's menu at the price last seen at `observed_at`
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, QueryableByName)]
+#[table_name = "listings"]
+pub struct Listing {
+    pub id: i32,
+    pub dispensary_id: i32,
+    pub batch_id: i32,
+    pub format: ProductFormat,
+    pub weight_grams: f32,
+    pub price_cents: i32,
+    pub in_stock: bool,
+    pub observed_at: NaiveDateTime,
+}
+
+/// Struct used to create new `Listing` object. `format` defaults to flower, `in_stock`
+/// to true and `observed_at` to now.
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "listings"]
+pub struct NewListing {
+    pub dispensary_id: i32,
+    pub batch_id: i32,
+    #[serde(default)]
+    pub format: ProductFormat,
+    pub weight_grams: f32,
+    pub price_cents: i32,
+    pub in_stock: Option<bool>,
+    pub observed_at: Option<NaiveDateTime>,
+}
+
+/// Partial update of a `Listing`. Fields left as `None` are not touched, except that
+/// `observed_at` is set to now when left out.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, AsChangeset)]
+#[table_name = "listings"]
+pub struct ListingChanges {
+    pub format: Option<ProductFormat>,
+    pub weight_grams: Option<f32>,
+    pub price_cents: Option<i32>,
+    pub in_stock: Option<bool>,
+    pub observed_at: Option<NaiveDateTime>,
+}
+
+/// An in-stock listing of a strain, with where to find it and what a gram costs
+///
+/// Ex:
+///     `{"listing_id": 4, "dispensary_id": 2, "dispensary": "Sunnyside", "address": "...",
+///       "batch_id": 7, "grower": "Cresco", "format": "Flower", "weight_grams": 3.5,
+///       "price_cents": 4500, "price_per_gram_cents": 1285.7, "observed_at": "..."}`
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct StrainListing {
+    #[sql_type = "Integer"]
+    pub listing_id: i32,
+
+    #[sql_type = "Integer"]
+    pub dispensary_id: i32,
+
+    #[sql_type = "VarChar"]
+    pub dispensary: String,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub address: Option<String>,
+
+    #[sql_type = "Integer"]
+    pub batch_id: i32,
+
+    #[sql_type = "VarChar"]
+    pub grower: String,
+
+    #[sql_type = "ProductFormatMapping"]
+    pub format: ProductFormat,
+
+    #[sql_type = "Float4"]
+    pub weight_grams: f32,
+
+    #[sql_type = "Integer"]
+    pub price_cents: i32,
+
+    #[sql_type = "Float4"]
+    pub price_per_gram_cents: f32,
+
+    #[sql_type = "Timestamp"]
+    pub observed_at: NaiveDateTime,
+}
+
+/// Struct used for retrieving `PriceObservation` object: what a batch sold for at
+/// `observed_at`, either on a listing or seen elsewhere
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable)]
+pub struct PriceObservation {
+    pub id: i32,
+    pub batch_id: i32,
+    pub listing_id: Option<i32>,
+    pub weight_grams: f32,
+    pub price_cents: i32,
+    pub observed_at: NaiveDateTime,
+}
+
+/// Struct used to create new `PriceObservation` object. `batch_id` comes from the path,
+/// `listing_id` is only set when a listing is created or updated, and `observed_at`
+/// defaults to now.
+#[derive(Debug, Clone, Deserialize, Serialize, Insertable)]
+#[table_name = "price_observations"]
+pub struct NewPriceObservation {
+    #[serde(default)]
+    pub batch_id: i32,
+    #[serde(skip_deserializing)]
+    pub listing_id: Option<i32>,
+    pub weight_grams: f32,
+    pub price_cents: i32,
+    pub observed_at: Option<NaiveDateTime>,
+}
+
+impl From<&Listing> for NewPriceObservation {
+    fn from(listing: &Listing) -> Self {
+        NewPriceObservation {
+            batch_id: listing.batch_id,
+            listing_id: Some(listing.id),
+            weight_grams: listing.weight_grams,
+            price_cents: listing.price_cents,
+            observed_at: Some(listing.observed_at),
+        }
+    }
+}
+
+/// One point of a batch's price history. `price_per_gram_thc_cents` is the value
+/// metric: what a gram of THC costs at this price, given the batch's `thc_content`.
+///
+/// Ex:
+///     `{"id": 12, "listing_id": 4, "dispensary": "Sunnyside", "weight_grams": 3.5,
+///       "price_cents": 4500, "observed_at": "...", "price_per_gram_cents": 1285.7,
+///       "price_per_gram_thc_cents": 5844.2}`
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct PricePoint {
+    #[sql_type = "Integer"]
+    pub id: i32,
+
+    #[sql_type = "Nullable<Integer>"]
+    pub listing_id: Option<i32>,
+
+    #[sql_type = "Nullable<VarChar>"]
+    pub dispensary: Option<String>,
+
+    #[sql_type = "Float4"]
+    pub weight_grams: f32,
+
+    #[sql_type = "Integer"]
+    pub price_cents: i32,
+
+    #[sql_type = "Timestamp"]
+    pub observed_at: NaiveDateTime,
+
+    #[sql_type = "Float4"]
+    pub price_per_gram_cents: f32,
+
+    #[sql_type = "Nullable<Float4>"]
+    pub price_per_gram_thc_cents: Option<f32>,
+}
+
+/// An in-stock listing ranked by what a gram of THC costs, cheapest first. THC is counted
+/// as `total_thc`: `thc_content` plus what the batch's THCA yields when heated.
+#[derive(Debug, Clone, Serialize, QueryableByName)]
+pub struct ListingValue {
+    #[sql_type = "BigInt"]
+    pub rank: i64,
+
+    #[sql_type = "Integer"]
+    pub listing_id: i32,
+
+    #[sql_type = "Integer"]
+    pub dispensary_id: i32,
+
+    #[sql_type = "VarChar"]
+    pub dispensary: String,
+
+    #[sql_type = "Integer"]
+    pub batch_id: i32,
+
+    #[sql_type = "Integer"]
+    pub strain_id: i32,
+
+    #[sql_type = "VarChar"]
+    pub strain: String,
+
+    #[sql_type = "SpeciesMapping"]
+    pub species: Species,
+
+    #[sql_type = "VarChar"]
+    pub grower: String,
+
+    #[sql_type = "ProductFormatMapping"]
+    pub format: ProductFormat,
+
+    #[sql_type = "Float4"]
+    pub thc_content: f32,
+
+    #[sql_type = "Float4"]
+    pub total_thc: f32,
+
+    #[sql_type = "Float4"]
+    pub weight_grams: f32,
+
+    #[sql_type = "Integer"]
+    pub price_cents: i32,
+
+    #[sql_type = "Float4"]
+    pub price_per_gram_cents: f32,
+
+    #[sql_type = "Float4"]
+    pub price_per_gram_thc_cents: f32,
+
+    #[sql_type = "Timestamp"]
+    pub observed_at: NaiveDateTime,
+}
+
+/// Grams in one eighth of an ounce, as sold at dispensaries
+pub const GRAMS_PER_EIGHTH: f32 = 3.5;
+
+impl Inventory {
+    pub fn is_low_stock(&self) -> bool {
+        self.quantity
+            <= self
+                .low_stock_threshold
+                .unwrap_or_else(|| self.unit.default_low_stock())
+    }
+}
+
+impl InventoryUnit {
+    /// Quantity at or below which an item counts as low on stock
+    pub fn default_low_stock(&self) -> f32 {
+        match self {
+            InventoryUnit::Grams => GRAMS_PER_EIGHTH,
+            InventoryUnit::Eighths => 1.0,
+            InventoryUnit::Units => 2.0,
+        }
+    }
 }
 
 impl fmt::Display for Species {
@@ -153,7 +1378,7 @@ impl fmt::Display for Species {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct NewTerpenesBuilder<T = NewTerpenes>(T);
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -165,45 +1390,41 @@ impl NewTerpenesBuilder {
         self
     }
 
-    pub fn caryophyllene(mut self, amt: Option<f32>) -> Self {
-        self.0.caryophyllene = amt;
+    /// Set the amount of any catalog compound, or remove it with `None`
+    pub fn compound(mut self, name: &str, amt: Option<f32>) -> Self {
+        match amt {
+            Some(a) => self.0.compounds.insert(name.to_owned(), a),
+            None => self.0.compounds.remove(name),
+        };
         self
     }
 
-    pub fn humulene(mut self, amt: Option<f32>) -> Self {
-        self.0.humulene = amt;
-        self
+    pub fn caryophyllene(self, amt: Option<f32>) -> Self {
+        self.compound("caryophyllene", amt)
     }
-    pub fn limonene(mut self, amt: Option<f32>) -> Self {
-        self.0.limonene = amt;
-        self
+
+    pub fn humulene(self, amt: Option<f32>) -> Self {
+        self.compound("humulene", amt)
     }
 
-    pub fn hinalool(mut self, amt: Option<f32>) -> Self {
-        self.0.linalool = amt;
-        self
+    pub fn limonene(self, amt: Option<f32>) -> Self {
+        self.compound("limonene", amt)
     }
 
-    pub fn myrcene(mut self, amt: Option<f32>) -> Self {
-        self.0.myrcene = amt;
-        self
+    pub fn linalool(self, amt: Option<f32>) -> Self {
+        self.compound("linalool", amt)
     }
 
-    pub fn pinene(mut self, amt: Option<f32>) -> Self {
-        self.0.pinene = amt;
-        self
+    pub fn myrcene(self, amt: Option<f32>) -> Self {
+        self.compound("myrcene", amt)
+    }
+
+    pub fn pinene(self, amt: Option<f32>) -> Self {
+        self.compound("pinene", amt)
     }
 
     pub fn build(self) -> NewTerpenes {
-        NewTerpenes {
-            batch_id: self.0.batch_id,
-            caryophyllene: self.0.caryophyllene,
-            humulene: self.0.humulene,
-            limonene: self.0.limonene,
-            linalool: self.0.linalool,
-            myrcene: self.0.myrcene,
-            pinene: self.0.pinene,
-        }
+        self.0
     }
 }
 
@@ -243,6 +1464,16 @@ impl NewBatchBuilder {
         self
     }
 
+    pub fn format(mut self, format: ProductFormat) -> Self {
+        self.0.format = format;
+        self
+    }
+
+    pub fn parent_batch_id(mut self, id: Option<i32>) -> Self {
+        self.0.parent_batch_id = id;
+        self
+    }
+
     pub fn build(self) -> NewBatch {
         NewBatch {
             strain_id: self.0.strain_id,
@@ -252,6 +1483,152 @@ impl NewBatchBuilder {
             grower_id: self.0.grower_id,
             thc_content: self.0.thc_content,
             cbd_content: self.0.cbd_content,
+            format: self.0.format,
+            parent_batch_id: self.0.parent_batch_id,
+        }
+    }
+}
+
+impl From<&Grower> for NewGrower {
+    fn from(g: &Grower) -> NewGrower {
+        NewGrower {
+            name: g.name.clone(),
+            license_number: g.license_number.clone(),
+            state: g.state.clone(),
+            city: g.city.clone(),
+            website: g.website.clone(),
+            cultivation_type: g.cultivation_type,
+            active: g.active,
+        }
+    }
+}
+
+impl From<&Batch> for NewBatch {
+    fn from(b: &Batch) -> NewBatch {
+        NewBatch {
+            strain_id: b.strain_id,
+            harvest_date: b.harvest_date,
+            final_test_date: b.final_test_date,
+            package_date: b.package_date,
+            grower_id: b.grower_id,
+            thc_content: b.thc_content,
+            cbd_content: b.cbd_content,
+            format: b.format,
+            parent_batch_id: b.parent_batch_id,
+        }
+    }
+}
+
+impl NewProduct {
+    /// The batch this product makes of `parent`
+    pub fn of(&self, parent: &Batch) -> NewBatch {
+        NewBatch {
+            strain_id: parent.strain_id,
+            harvest_date: parent.harvest_date,
+            final_test_date: self.final_test_date,
+            package_date: self.package_date,
+            grower_id: parent.grower_id,
+            thc_content: self.thc_content,
+            cbd_content: self.cbd_content,
+            format: self.format,
+            parent_batch_id: Some(parent.id),
+        }
+    }
+}
+
+impl From<BatchRow> for BatchResponse {
+    fn from(row: BatchRow) -> BatchResponse {
+        let terpenes = row.terpenes_id.map(|tid| Terpenes {
+            id: tid,
+            batch_id: row.id,
+            compounds: row
+                .terpene_names
+                .unwrap_or_default()
+                .into_iter()
+                .zip(row.terpene_amounts.unwrap_or_default())
+                .collect(),
+        });
+        let cannabinoids = row.cannabinoids_id.map(|cid| Cannabinoids {
+            id: cid,
+            batch_id: row.id,
+            thca: row.thca,
+            cbda: row.cbda,
+            cbg: row.cbg,
+            cbga: row.cbga,
+            cbn: row.cbn,
+            cbc: row.cbc,
+            thcv: row.thcv,
+        });
+
+        BatchResponse {
+            id: row.id,
+            strain_id: row.strain_id,
+            strain: row.strain,
+            harvest_date: row.harvest_date,
+            final_test_date: row.final_test_date,
+            package_date: row.package_date,
+            grower_id: row.grower_id,
+            grower: row.grower,
+            thc_content: row.thc_content,
+            cbd_content: row.cbd_content,
+            format: row.format,
+            potency_unit: row.format.potency_unit(),
+            parent_batch_id: row.parent_batch_id,
+            terpenes,
+            total_thc: total_potency(row.thc_content, row.thca),
+            total_cbd: total_potency(row.cbd_content, row.cbda),
+            cannabinoids,
+            rating: row.rating.into(),
+        }
+    }
+}
+
+impl From<RatingRow> for RatingSummary {
+    fn from(row: RatingRow) -> RatingSummary {
+        RatingSummary {
+            average: row.rating_avg,
+            count: row.review_count,
+            distribution: [
+                row.rating_1,
+                row.rating_2,
+                row.rating_3,
+                row.rating_4,
+                row.rating_5,
+            ],
+        }
+    }
+}
+
+impl StatsRow {
+    pub fn thc(&self) -> PotencyStats {
+        PotencyStats {
+            min: self.thc_min,
+            max: self.thc_max,
+            mean: self.thc_mean,
+            median: self.thc_median,
+        }
+    }
+
+    pub fn cbd(&self) -> PotencyStats {
+        PotencyStats {
+            min: self.cbd_min,
+            max: self.cbd_max,
+            mean: self.cbd_mean,
+            median: self.cbd_median,
+        }
+    }
+}
+
+impl From<BatchResponse> for CompactBatchResponse {
+    fn from(res: BatchResponse) -> CompactBatchResponse {
+        CompactBatchResponse {
+            strain: res.strain,
+            harvest_date: res.harvest_date,
+            final_test_date: res.final_test_date,
+            package_date: res.package_date,
+            grower: res.grower,
+            thc_content: res.thc_content,
+            cbd_content: res.cbd_content,
         }
     }
 }
@@ -260,12 +1637,22 @@ impl Default for NewTerpenes {
     fn default() -> NewTerpenes {
         NewTerpenes {
             batch_id: -1,
-            caryophyllene: None,
-            humulene: None,
-            limonene: None,
-            linalool: None,
-            myrcene: None,
-            pinene: None,
+            compounds: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for NewCannabinoids {
+    fn default() -> NewCannabinoids {
+        NewCannabinoids {
+            batch_id: -1,
+            thca: None,
+            cbda: None,
+            cbg: None,
+            cbga: None,
+            cbn: None,
+            cbc: None,
+            thcv: None,
         }
     }
 }
@@ -290,6 +1677,8 @@ impl NewBatch {
             grower_id: -1,
             thc_content: 0.0,
             cbd_content: 0.0,
+            format: ProductFormat::Flower,
+            parent_batch_id: None,
         }
     }
 
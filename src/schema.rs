@@ -1,3 +1,15 @@
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    auth_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        jti -> Varchar,
+        expires_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use crate::exports::*;
@@ -11,6 +23,48 @@ table! {
         grower_id -> Int4,
         thc_content -> Float4,
         cbd_content -> Float4,
+        format -> Product_format,
+        parent_batch_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    cannabinoids (id) {
+        id -> Int4,
+        batch_id -> Int4,
+        thca -> Nullable<Float4>,
+        cbda -> Nullable<Float4>,
+        cbg -> Nullable<Float4>,
+        cbga -> Nullable<Float4>,
+        cbn -> Nullable<Float4>,
+        cbc -> Nullable<Float4>,
+        thcv -> Nullable<Float4>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    dispensaries (id) {
+        id -> Int4,
+        name -> Varchar,
+        address -> Nullable<Varchar>,
+        license_number -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    grower_aliases (id) {
+        id -> Int4,
+        grower_id -> Int4,
+        alias -> Varchar,
     }
 }
 
@@ -21,6 +75,122 @@ table! {
     growers (id) {
         id -> Int4,
         name -> Varchar,
+        license_number -> Nullable<Varchar>,
+        state -> Nullable<Varchar>,
+        city -> Nullable<Varchar>,
+        website -> Nullable<Varchar>,
+        cultivation_type -> Nullable<Cultivation_type>,
+        active -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    inventory (id) {
+        id -> Int4,
+        user_id -> Int4,
+        batch_id -> Int4,
+        quantity -> Float4,
+        unit -> Inventory_unit,
+        purchase_price_cents -> Nullable<Int4>,
+        purchase_date -> Nullable<Date>,
+        location -> Nullable<Varchar>,
+        low_stock_threshold -> Nullable<Float4>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    listings (id) {
+        id -> Int4,
+        dispensary_id -> Int4,
+        batch_id -> Int4,
+        format -> Product_format,
+        weight_grams -> Float4,
+        price_cents -> Int4,
+        in_stock -> Bool,
+        observed_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    price_observations (id) {
+        id -> Int4,
+        batch_id -> Int4,
+        listing_id -> Nullable<Int4>,
+        weight_grams -> Float4,
+        price_cents -> Int4,
+        observed_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    reviews (id) {
+        id -> Int4,
+        user_id -> Int4,
+        batch_id -> Int4,
+        rating -> Int2,
+        body -> Nullable<Text>,
+        effects -> Array<Text>,
+        flavors -> Array<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    session_draws (session_id, inventory_id) {
+        session_id -> Int4,
+        inventory_id -> Int4,
+        quantity -> Float4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        batch_id -> Int4,
+        consumed_at -> Timestamp,
+        method -> Consumption_method,
+        amount -> Nullable<Float4>,
+        notes -> Nullable<Text>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    strain_aliases (id) {
+        id -> Int4,
+        strain_id -> Int4,
+        alias -> Varchar,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    strain_parents (strain_id, parent_id) {
+        strain_id -> Int4,
+        parent_id -> Int4,
     }
 }
 
@@ -32,6 +202,32 @@ table! {
         id -> Int4,
         name -> Varchar,
         species -> Species,
+        breeder -> Nullable<Varchar>,
+        phenotype -> Nullable<Varchar>,
+        indica_percent -> Nullable<Int4>,
+        sativa_percent -> Nullable<Int4>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    terpene_compounds (id) {
+        id -> Int4,
+        name -> Varchar,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    terpene_measurements (id) {
+        id -> Int4,
+        terpenes_id -> Int4,
+        compound_id -> Int4,
+        amount -> Float4,
     }
 }
 
@@ -42,22 +238,61 @@ table! {
     terpenes (id) {
         id -> Int4,
         batch_id -> Int4,
-        caryophyllene -> Nullable<Float4>,
-        humulene -> Nullable<Float4>,
-        limonene -> Nullable<Float4>,
-        linalool -> Nullable<Float4>,
-        myrcene -> Nullable<Float4>,
-        pinene -> Nullable<Float4>,
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use crate::exports::*;
+
+    users (id) {
+        id -> Int4,
+        username -> Varchar,
+        password_hash -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+joinable!(auth_tokens -> users (user_id));
 joinable!(batches -> growers (grower_id));
 joinable!(batches -> strains (strain_id));
+joinable!(cannabinoids -> batches (batch_id));
+joinable!(grower_aliases -> growers (grower_id));
+joinable!(inventory -> batches (batch_id));
+joinable!(inventory -> users (user_id));
+joinable!(listings -> batches (batch_id));
+joinable!(listings -> dispensaries (dispensary_id));
+joinable!(price_observations -> batches (batch_id));
+joinable!(price_observations -> listings (listing_id));
+joinable!(reviews -> batches (batch_id));
+joinable!(reviews -> users (user_id));
+joinable!(session_draws -> inventory (inventory_id));
+joinable!(session_draws -> sessions (session_id));
+joinable!(sessions -> batches (batch_id));
+joinable!(sessions -> users (user_id));
+joinable!(strain_aliases -> strains (strain_id));
+joinable!(terpene_measurements -> terpene_compounds (compound_id));
+joinable!(terpene_measurements -> terpenes (terpenes_id));
 joinable!(terpenes -> batches (batch_id));
 
 allow_tables_to_appear_in_same_query!(
+    auth_tokens,
     batches,
+    cannabinoids,
+    dispensaries,
+    grower_aliases,
     growers,
+    inventory,
+    listings,
+    price_observations,
+    reviews,
+    session_draws,
+    sessions,
+    strain_aliases,
+    strain_parents,
     strains,
+    terpene_compounds,
+    terpene_measurements,
     terpenes,
+    users,
 );
@@ -0,0 +1,979 @@
+use super::models::{
+    BatchChanges, DispensaryChanges, GrowerChanges, InventoryChanges, ListingChanges, NewBatch,
+    NewCannabinoids, NewDispensary, NewGrower, NewGrowerAlias, NewInventory, NewListing,
+    NewPriceObservation, NewReview, NewSession, NewStrain, NewStrainAlias, NewTerpeneCompound,
+    NewTerpenes, PotencyUnit, ProductFormat, ReviewChanges, SessionChanges, StrainChanges,
+    StrainParent, MAX_RATING, MIN_RATING,
+};
+
+use chrono::NaiveDate;
+use diesel::result::Error;
+use serde::Serialize;
+
+use std::fmt;
+
+/// Longest name that fits the `VARCHAR(255)` name columns
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Longest note a session may carry
+pub const MAX_NOTES_LENGTH: usize = 2000;
+
+/// Longest body a review may have
+pub const MAX_REVIEW_LENGTH: usize = 5000;
+
+/// Most THC or CBD a single edible may be labeled with, in milligrams
+pub const MAX_MG_PER_UNIT: f32 = 1000.0;
+
+/// Highest lab results that are plausible for one product format. `thc` and `cbd` are in
+/// the format's potency unit; `terpenes` is the most a terpene profile may add up to, in
+/// percent by weight.
+#[derive(Debug, Clone, Copy)]
+pub struct FormatBounds {
+    pub thc: f32,
+    pub cbd: f32,
+    pub terpenes: f32,
+}
+
+impl FormatBounds {
+    /// Infused pre-rolls test above plain flower; extracts are nearly pure cannabinoids,
+    /// and live resins and sauces carry far more terpenes than the plant they came from.
+    pub fn of(format: ProductFormat) -> FormatBounds {
+        let (thc, cbd, terpenes) = match format {
+            ProductFormat::Flower => (40.0, 30.0, 10.0),
+            ProductFormat::PreRoll => (60.0, 30.0, 10.0),
+            ProductFormat::Vape => (100.0, 100.0, 30.0),
+            ProductFormat::Concentrate => (100.0, 100.0, 50.0),
+            ProductFormat::Edible => (MAX_MG_PER_UNIT, MAX_MG_PER_UNIT, 5.0),
+        };
+        FormatBounds { thc, cbd, terpenes }
+    }
+}
+
+/// License number formats issued by one jurisdiction. In a format `#` stands for a digit,
+/// `@` for a letter and any other character for itself; a number must match one of them.
+#[derive(Debug, Clone, Copy)]
+pub struct LicenseRule {
+    pub state: &'static str,
+    pub formats: &'static [&'static str],
+}
+
+/// License formats the API knows about. Numbers from other jurisdictions are only held
+/// to the rules for names; add a rule here to check them.
+pub const LICENSE_RULES: &[LicenseRule] = &[
+    LicenseRule {
+        state: "CA",
+        formats: &["CCL##-#######"],
+    },
+    LicenseRule {
+        state: "CO",
+        formats: &["403-#####", "403R-#####"],
+    },
+    LicenseRule {
+        state: "MA",
+        formats: &["MC######"],
+    },
+    LicenseRule {
+        state: "MI",
+        formats: &["AU-G-@-######", "GR-@-######"],
+    },
+    LicenseRule {
+        state: "WA",
+        formats: &["######"],
+    },
+];
+
+impl LicenseRule {
+    /// The rule for `state` in `rules`, if there is one
+    pub fn find(rules: &'static [LicenseRule], state: &str) -> Option<&'static LicenseRule> {
+        rules.iter().find(|r| r.state == state)
+    }
+
+    /// Whether `number` matches one of the formats, ignoring case
+    ///
+    /// Ex:
+    ///     `LicenseRule::find(LICENSE_RULES, "CO").unwrap().matches("403r-00123")` is `true`
+    pub fn matches(&self, number: &str) -> bool {
+        self.formats.iter().any(|f| {
+            f.chars().count() == number.chars().count()
+                && f.chars().zip(number.chars()).all(|(p, c)| match p {
+                    '#' => c.is_ascii_digit(),
+                    '@' => c.is_ascii_alphabetic(),
+                    _ => p.eq_ignore_ascii_case(&c),
+                })
+        })
+    }
+}
+
+/// One rule broken by one field of a request body
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Every rule a record broke. Returned as a 422 listing each field:
+///
+/// Ex:
+///     `{"message": "Validation failed",
+///       "errors": [{"field": "thc_content", "message": "must be between 0 and 40 for Flower"}],
+///       "status code": 422}`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `Ok(())` if no rule was broken
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Take over another record's errors, naming each field after the record: `[2].name`
+    pub fn nest(&mut self, prefix: &str, errors: ValidationErrors) {
+        for e in errors.0 {
+            self.add(&format!("{}.{}", prefix, e.field), e.message);
+        }
+    }
+
+    /// Percentages of a test result must fall within 0-100
+    pub fn percent(&mut self, field: &str, value: f32) {
+        if !(0.0..=100.0).contains(&value) {
+            self.add(field, "must be between 0 and 100");
+        }
+    }
+
+    /// A batch's THC or CBD, in the unit its `format` is measured in, at most `max`
+    pub fn potency(&mut self, field: &str, format: ProductFormat, max: f32, value: f32) {
+        if !(0.0..=max).contains(&value) {
+            let unit = match format.potency_unit() {
+                PotencyUnit::Percent => "",
+                PotencyUnit::MgPerUnit => " mg per unit",
+            };
+            self.add(
+                field,
+                format!("must be between 0 and {}{} for {:?}", max, unit, format),
+            );
+        }
+    }
+
+    /// A terpene profile may add up to no more than `format` plausibly holds
+    pub fn terpene_total(&mut self, field: &str, format: ProductFormat, total: f32) {
+        let max = FormatBounds::of(format).terpenes;
+        if total > max {
+            self.add(
+                field,
+                format!("must not add up to more than {} for {:?}", max, format),
+            );
+        }
+    }
+
+    /// Names may not be blank and must fit their column
+    pub fn name(&mut self, field: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.add(field, "must not be empty");
+        } else if value.trim().chars().count() > MAX_NAME_LENGTH {
+            self.add(
+                field,
+                format!("must be at most {} characters", MAX_NAME_LENGTH),
+            );
+        }
+    }
+
+    /// Ids must refer to a real record, which rules out the builders' `-1` placeholder
+    pub fn id(&mut self, field: &str, value: i32) {
+        if value <= 0 {
+            self.add(field, "must be set to an existing id");
+        }
+    }
+
+    /// Every measured compound of a lab panel is a percentage, and together they can't
+    /// exceed the whole sample. An excess total is reported under `field`.
+    pub fn composition(&mut self, field: &str, compounds: &[(&str, Option<f32>)]) {
+        for (name, amt) in compounds {
+            if let Some(a) = amt {
+                self.percent(name, *a);
+            }
+        }
+        let total: f32 = compounds.iter().filter_map(|(_, a)| *a).sum();
+        if total > 100.0 {
+            self.add(field, "must not add up to more than 100");
+        }
+    }
+
+    /// States are two-letter codes such as `CA`. A license number is issued by a state,
+    /// so it needs one, and must match the state's format in `rules` if it has one.
+    pub fn license(
+        &mut self,
+        rules: &'static [LicenseRule],
+        state: Option<&str>,
+        number: Option<&str>,
+    ) {
+        if let Some(s) = state {
+            if s.len() != 2 || !s.chars().all(|c| c.is_ascii_uppercase()) {
+                self.add("state", "must be a two-letter code such as CA");
+            }
+        }
+        match (state, number) {
+            (_, Some(n)) if n.trim().is_empty() || n.chars().count() > MAX_NAME_LENGTH => {
+                self.name("license_number", n)
+            }
+            (None, Some(_)) => self.add("license_number", "must be given with state"),
+            (Some(s), Some(n)) => match LicenseRule::find(rules, s) {
+                Some(rule) if !rule.matches(n.trim()) => self.add(
+                    "license_number",
+                    format!("must match a {} format: {}", s, rule.formats.join(", ")),
+                ),
+                _ => (),
+            },
+            (_, None) => (),
+        }
+    }
+
+    /// Websites are given as full `http` or `https` URLs
+    pub fn website(&mut self, field: &str, value: &str) {
+        let lower = value.trim().to_lowercase();
+        let host = lower
+            .strip_prefix("https://")
+            .or_else(|| lower.strip_prefix("http://"));
+        match host {
+            Some(h) if !h.is_empty() && !h.contains(char::is_whitespace) => {
+                if value.chars().count() > MAX_NAME_LENGTH {
+                    self.name(field, value);
+                }
+            }
+            _ => self.add(field, "must be a URL starting with http:// or https://"),
+        }
+    }
+
+    /// `later` may not come before `earlier` when both are known
+    pub fn date_order(
+        &mut self,
+        earlier: (&str, Option<NaiveDate>),
+        later: (&str, Option<NaiveDate>),
+    ) {
+        if let (Some(a), Some(b)) = (earlier.1, later.1) {
+            if b < a {
+                self.add(later.0, format!("must not be before {}", earlier.0));
+            }
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self
+            .0
+            .iter()
+            .map(|e| format!("{} {}", e.field, e.message))
+            .collect();
+        write!(f, "Validation failed: {}", fields.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Lets `Creatable`/`Updatable` reject a record with `?`. The errors travel inside
+/// `Error::QueryBuilderError` and are unpacked again by `ApiError`.
+impl From<ValidationErrors> for Error {
+    fn from(e: ValidationErrors) -> Error {
+        Error::QueryBuilderError(Box::new(e))
+    }
+}
+
+/// Trait for checking a record before it is written. `Creatable` and `Updatable`
+/// run it ahead of every insert or update.
+///
+/// Example:
+/// let batch = NewBatch::builder().strain_id(3).thc_content(340.0).build();
+/// let errors = batch.validate().unwrap_err();
+/// assert_eq!(errors.0.len(), 2); // grower_id is unset and THC is out of range
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// Breeder and phenotype are optional, but when given are held to the same rules as names.
+/// The indica/sativa split is optional too, but takes both shares and they must add up.
+fn strain_details(
+    errors: &mut ValidationErrors,
+    breeder: &Option<String>,
+    phenotype: &Option<String>,
+    indica: Option<i32>,
+    sativa: Option<i32>,
+) {
+    if let Some(b) = breeder {
+        errors.name("breeder", b);
+    }
+    if let Some(p) = phenotype {
+        errors.name("phenotype", p);
+    }
+    match (indica, sativa) {
+        (Some(i), Some(s)) => {
+            errors.percent("indica_percent", i as f32);
+            errors.percent("sativa_percent", s as f32);
+            if i + s != 100 {
+                errors.add("sativa_percent", "must add up to 100 with indica_percent");
+            }
+        }
+        (Some(_), None) => errors.add("sativa_percent", "must be given with indica_percent"),
+        (None, Some(_)) => errors.add("indica_percent", "must be given with sativa_percent"),
+        (None, None) => (),
+    }
+}
+
+impl Validate for NewStrain {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.name("name", &self.name);
+        strain_details(
+            &mut errors,
+            &self.breeder,
+            &self.phenotype,
+            self.indica_percent,
+            self.sativa_percent,
+        );
+        errors.into_result()
+    }
+}
+
+impl Validate for StrainChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(n) = &self.name {
+            errors.name("name", n);
+        }
+        strain_details(
+            &mut errors,
+            &self.breeder,
+            &self.phenotype,
+            self.indica_percent,
+            self.sativa_percent,
+        );
+        errors.into_result()
+    }
+}
+
+/// A strain can't be its own parent; longer cycles are caught against the stored lineage
+impl Validate for StrainParent {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("strain_id", self.strain_id);
+        errors.id("parent_id", self.parent_id);
+        if self.strain_id == self.parent_id {
+            errors.add("parent_id", "must not be the strain itself");
+        }
+        errors.into_result()
+    }
+}
+
+impl Validate for NewStrainAlias {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("strain_id", self.strain_id);
+        errors.name("alias", &self.alias);
+        errors.into_result()
+    }
+}
+
+impl Validate for NewGrowerAlias {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("grower_id", self.grower_id);
+        errors.name("alias", &self.alias);
+        errors.into_result()
+    }
+}
+
+/// City and website are optional, but checked when given. A license number is checked
+/// against the state given with it.
+fn grower_details(
+    errors: &mut ValidationErrors,
+    license_number: &Option<String>,
+    state: &Option<String>,
+    city: &Option<String>,
+    website: &Option<String>,
+) {
+    errors.license(LICENSE_RULES, state.as_deref(), license_number.as_deref());
+    if let Some(c) = city {
+        errors.name("city", c);
+    }
+    if let Some(w) = website {
+        errors.website("website", w);
+    }
+}
+
+impl Validate for NewGrower {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.name("name", &self.name);
+        grower_details(
+            &mut errors,
+            &self.license_number,
+            &self.state,
+            &self.city,
+            &self.website,
+        );
+        errors.into_result()
+    }
+}
+
+/// Only the fields that stand on their own are checked here. State and license number
+/// depend on each other, so they are checked on the grower the changes produce.
+impl Validate for GrowerChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(n) = &self.name {
+            errors.name("name", n);
+        }
+        if let Some(c) = &self.city {
+            errors.name("city", c);
+        }
+        if let Some(w) = &self.website {
+            errors.website("website", w);
+        }
+        errors.into_result()
+    }
+}
+
+/// Dates must run harvest, final test, package
+fn batch_dates(
+    errors: &mut ValidationErrors,
+    harvest: Option<NaiveDate>,
+    final_test: Option<NaiveDate>,
+    package: Option<NaiveDate>,
+) {
+    errors.date_order(("harvest_date", harvest), ("final_test_date", final_test));
+    errors.date_order(("final_test_date", final_test), ("package_date", package));
+    if final_test.is_none() {
+        errors.date_order(("harvest_date", harvest), ("package_date", package));
+    }
+}
+
+impl Validate for NewBatch {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("strain_id", self.strain_id);
+        errors.id("grower_id", self.grower_id);
+        let bounds = FormatBounds::of(self.format);
+        errors.potency("thc_content", self.format, bounds.thc, self.thc_content);
+        errors.potency("cbd_content", self.format, bounds.cbd, self.cbd_content);
+        if let Some(i) = self.parent_batch_id {
+            errors.id("parent_batch_id", i);
+        }
+        batch_dates(
+            &mut errors,
+            self.harvest_date,
+            self.final_test_date,
+            self.package_date,
+        );
+        errors.into_result()
+    }
+}
+
+/// Only the fields present are checked; dates are ordered against each other but not
+/// against the ones already stored. Potency is only checked here along with `format`;
+/// otherwise its unit is the stored format's, and the updated batch is checked as a whole
+/// before it is saved.
+impl Validate for BatchChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(i) = self.strain_id {
+            errors.id("strain_id", i);
+        }
+        if let Some(i) = self.grower_id {
+            errors.id("grower_id", i);
+        }
+        if let Some(i) = self.parent_batch_id {
+            errors.id("parent_batch_id", i);
+        }
+        if let Some(f) = self.format {
+            let bounds = FormatBounds::of(f);
+            if let Some(t) = self.thc_content {
+                errors.potency("thc_content", f, bounds.thc, t);
+            }
+            if let Some(c) = self.cbd_content {
+                errors.potency("cbd_content", f, bounds.cbd, c);
+            }
+        }
+        batch_dates(
+            &mut errors,
+            self.harvest_date,
+            self.final_test_date,
+            self.package_date,
+        );
+        errors.into_result()
+    }
+}
+
+/// Amounts are percentages by weight. How much they may add up to depends on the format
+/// of the batch, which is checked against `FormatBounds` once the profile is stored.
+impl Validate for NewTerpenes {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("batch_id", self.batch_id);
+        let compounds: Vec<(&str, Option<f32>)> = self
+            .compounds
+            .iter()
+            .map(|(n, a)| (n.as_str(), Some(*a)))
+            .collect();
+        errors.composition("terpenes", &compounds);
+        errors.into_result()
+    }
+}
+
+impl Validate for NewTerpeneCompound {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.name("name", &self.name);
+        errors.into_result()
+    }
+}
+
+impl Validate for NewCannabinoids {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("batch_id", self.batch_id);
+        let compounds = [
+            ("thca", self.thca),
+            ("cbda", self.cbda),
+            ("cbg", self.cbg),
+            ("cbga", self.cbga),
+            ("cbn", self.cbn),
+            ("cbc", self.cbc),
+            ("thcv", self.thcv),
+        ];
+        errors.composition("cannabinoids", &compounds);
+        errors.into_result()
+    }
+}
+
+/// Address and license number are optional, but held to the rules for names when given
+fn dispensary_details(
+    errors: &mut ValidationErrors,
+    address: &Option<String>,
+    license_number: &Option<String>,
+) {
+    if let Some(a) = address {
+        errors.name("address", a);
+    }
+    if let Some(l) = license_number {
+        errors.name("license_number", l);
+    }
+}
+
+impl Validate for NewDispensary {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.name("name", &self.name);
+        dispensary_details(&mut errors, &self.address, &self.license_number);
+        errors.into_result()
+    }
+}
+
+impl Validate for DispensaryChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(n) = &self.name {
+            errors.name("name", n);
+        }
+        dispensary_details(&mut errors, &self.address, &self.license_number);
+        errors.into_result()
+    }
+}
+
+/// Something is always sold by weight, and never for less than nothing
+fn listing_amounts(errors: &mut ValidationErrors, weight: Option<f32>, price: Option<i32>) {
+    if let Some(w) = weight {
+        if w <= 0.0 {
+            errors.add("weight_grams", "must be more than 0");
+        }
+    }
+    if let Some(p) = price {
+        if p < 0 {
+            errors.add("price_cents", "must not be negative");
+        }
+    }
+}
+
+impl Validate for NewListing {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("dispensary_id", self.dispensary_id);
+        errors.id("batch_id", self.batch_id);
+        listing_amounts(&mut errors, Some(self.weight_grams), Some(self.price_cents));
+        errors.into_result()
+    }
+}
+
+impl Validate for ListingChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        listing_amounts(&mut errors, self.weight_grams, self.price_cents);
+        errors.into_result()
+    }
+}
+
+impl Validate for NewPriceObservation {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        listing_amounts(&mut errors, Some(self.weight_grams), Some(self.price_cents));
+        errors.into_result()
+    }
+}
+
+/// A session can't use up less than nothing, and its notes must stay readable
+fn session_details(errors: &mut ValidationErrors, amount: Option<f32>, notes: &Option<String>) {
+    if let Some(a) = amount {
+        if a < 0.0 {
+            errors.add("amount", "must not be negative");
+        }
+    }
+    if let Some(n) = notes {
+        if n.chars().count() > MAX_NOTES_LENGTH {
+            errors.add(
+                "notes",
+                format!("must be at most {} characters", MAX_NOTES_LENGTH),
+            );
+        }
+    }
+}
+
+impl Validate for NewSession {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("batch_id", self.batch_id);
+        session_details(&mut errors, self.amount, &self.notes);
+        errors.into_result()
+    }
+}
+
+impl Validate for SessionChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if let Some(b) = self.batch_id {
+            errors.id("batch_id", b);
+        }
+        session_details(&mut errors, self.amount, &self.notes);
+        errors.into_result()
+    }
+}
+
+/// Ratings are whole stars, and a review body must stay readable
+fn review_details(errors: &mut ValidationErrors, rating: Option<i16>, body: &Option<String>) {
+    if let Some(r) = rating {
+        if !(MIN_RATING..=MAX_RATING).contains(&r) {
+            errors.add(
+                "rating",
+                format!("must be between {} and {}", MIN_RATING, MAX_RATING),
+            );
+        }
+    }
+    if let Some(b) = body {
+        if b.chars().count() > MAX_REVIEW_LENGTH {
+            errors.add(
+                "body",
+                format!("must be at most {} characters", MAX_REVIEW_LENGTH),
+            );
+        }
+    }
+}
+
+impl Validate for NewReview {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        review_details(&mut errors, Some(self.rating), &self.body);
+        errors.into_result()
+    }
+}
+
+impl Validate for ReviewChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        review_details(&mut errors, self.rating, &self.body);
+        errors.into_result()
+    }
+}
+
+/// Stock on hand is never negative
+fn inventory_quantity(errors: &mut ValidationErrors, quantity: Option<f32>) {
+    if let Some(q) = quantity {
+        if q < 0.0 {
+            errors.add("quantity", "must not be negative");
+        }
+    }
+}
+
+impl Validate for NewInventory {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        errors.id("batch_id", self.batch_id);
+        inventory_quantity(&mut errors, Some(self.quantity));
+        errors.into_result()
+    }
+}
+
+impl Validate for InventoryChanges {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        inventory_quantity(&mut errors, self.quantity);
+        errors.into_result()
+    }
+}
+
+/// Validate each record of a list, reporting broken fields under the record's index
+///
+/// Ex:
+///     `[{"field": "[3].name", "message": "must not be empty"}]`
+pub fn validate_each<T: Validate>(records: &[T]) -> ValidationErrors {
+    let mut errors = ValidationErrors::default();
+    for (i, r) in records.iter().enumerate() {
+        if let Err(e) = r.validate() {
+            errors.nest(&format!("[{}]", i), e);
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentinel_ids_and_ranges_rejected() {
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .thc_content(340.0)
+            .cbd_content(0.4)
+            .build();
+        let errors = batch.validate().unwrap_err();
+        let fields: Vec<&str> = errors.0.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["grower_id", "thc_content"]);
+    }
+
+    #[test]
+    fn potency_checked_in_format_units() {
+        let edible = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(1)
+            .format(ProductFormat::Edible)
+            .thc_content(250.0)
+            .build();
+        assert!(edible.validate().is_ok());
+
+        let concentrate = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(1)
+            .format(ProductFormat::Concentrate)
+            .thc_content(250.0)
+            .build();
+        let errors = concentrate.validate().unwrap_err();
+        assert_eq!(errors.0[0].field, "thc_content");
+
+        let changes = BatchChanges {
+            format: Some(ProductFormat::Edible),
+            thc_content: Some(1500.0),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_err());
+    }
+
+    #[test]
+    fn potency_bounded_by_format() {
+        let batch = |format, thc| {
+            NewBatch::builder()
+                .strain_id(3)
+                .grower_id(1)
+                .format(format)
+                .thc_content(thc)
+                .build()
+        };
+        let errors = batch(ProductFormat::Flower, 55.0).validate().unwrap_err();
+        assert_eq!(errors.0[0].message, "must be between 0 and 40 for Flower");
+        assert!(batch(ProductFormat::PreRoll, 55.0).validate().is_ok());
+        assert!(batch(ProductFormat::Vape, 88.0).validate().is_ok());
+        assert!(batch(ProductFormat::Concentrate, 88.0).validate().is_ok());
+
+        let mut errors = ValidationErrors::default();
+        errors.terpene_total("terpenes", ProductFormat::Concentrate, 30.0);
+        errors.terpene_total("terpenes", ProductFormat::Vape, 30.0);
+        assert!(errors.is_empty());
+        errors.terpene_total("terpenes", ProductFormat::Flower, 12.0);
+        errors.terpene_total("terpenes", ProductFormat::Edible, 12.0);
+        assert_eq!(errors.0.len(), 2);
+    }
+
+    #[test]
+    fn dates_out_of_order_rejected() {
+        let batch = NewBatch::builder()
+            .strain_id(3)
+            .grower_id(1)
+            .harvest_date(Some(NaiveDate::from_ymd(2022, 3, 1)))
+            .package_date(Some(NaiveDate::from_ymd(2022, 2, 1)))
+            .build();
+        let errors = batch.validate().unwrap_err();
+        assert_eq!(errors.0[0].field, "package_date");
+
+        let changes = BatchChanges {
+            final_test_date: Some(NaiveDate::from_ymd(2022, 3, 10)),
+            package_date: Some(NaiveDate::from_ymd(2022, 3, 15)),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_ok());
+    }
+
+    #[test]
+    fn blank_names_rejected() {
+        let strain = NewStrain {
+            name: "   ".to_owned(),
+            species: crate::models::Species::Hybrid,
+            breeder: None,
+            phenotype: None,
+            indica_percent: None,
+            sativa_percent: None,
+        };
+        assert!(strain.validate().is_err());
+        let grower = NewGrower {
+            name: "Tegridy Farms".to_owned(),
+            ..Default::default()
+        };
+        assert!(grower.validate().is_ok());
+    }
+
+    #[test]
+    fn license_checked_against_state() {
+        let grower = |state: Option<&str>, number: &str| NewGrower {
+            name: "Licensed Farms".to_owned(),
+            state: state.map(str::to_owned),
+            license_number: Some(number.to_owned()),
+            ..Default::default()
+        };
+        assert!(grower(Some("CA"), "CCL18-0003443").validate().is_ok());
+        assert!(grower(Some("CO"), "403r-00123").validate().is_ok());
+        assert!(grower(Some("OR"), "anything goes").validate().is_ok());
+        let errors = grower(Some("CA"), "403R-00123").validate().unwrap_err();
+        assert_eq!(errors.0[0].field, "license_number");
+        assert!(grower(None, "CCL18-0003443").validate().is_err());
+        assert!(grower(Some("ca"), "CCL18-0003443").validate().is_err());
+
+        let changes = GrowerChanges {
+            website: Some("cresco.com".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(changes.validate().unwrap_err().0[0].field, "website");
+    }
+
+    #[test]
+    fn ratio_must_add_up() {
+        let changes = StrainChanges {
+            indica_percent: Some(70),
+            sativa_percent: Some(30),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_ok());
+        let changes = StrainChanges {
+            indica_percent: Some(70),
+            sativa_percent: Some(40),
+            ..Default::default()
+        };
+        assert_eq!(changes.validate().unwrap_err().0[0].field, "sativa_percent");
+        let changes = StrainChanges {
+            indica_percent: Some(70),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_err());
+    }
+
+    #[test]
+    fn list_errors_indexed() {
+        let strains = vec![
+            NewStrain {
+                name: "Gelato".to_owned(),
+                species: crate::models::Species::Hybrid,
+                breeder: None,
+                phenotype: None,
+                indica_percent: None,
+                sativa_percent: None,
+            },
+            NewStrain {
+                name: "".to_owned(),
+                species: crate::models::Species::Hybrid,
+                breeder: None,
+                phenotype: None,
+                indica_percent: None,
+                sativa_percent: None,
+            },
+        ];
+        let errors = validate_each(&strains);
+        assert_eq!(errors.0[0].field, "[1].name");
+    }
+
+    #[test]
+    fn terpene_total_capped() {
+        let terps = NewTerpenes::builder()
+            .batch_id(1)
+            .limonene(Some(60.0))
+            .myrcene(Some(50.0))
+            .build();
+        let errors = terps.validate().unwrap_err();
+        assert_eq!(errors.0[0].field, "terpenes");
+    }
+
+    #[test]
+    fn session_and_inventory_amounts_checked() {
+        let session = NewSession {
+            user_id: 1,
+            batch_id: 0,
+            consumed_at: None,
+            method: crate::models::ConsumptionMethod::Vape,
+            amount: Some(-0.5),
+            notes: Some("x".repeat(MAX_NOTES_LENGTH + 1)),
+        };
+        let errors = session.validate().unwrap_err();
+        let fields: Vec<&str> = errors.0.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["batch_id", "amount", "notes"]);
+
+        let changes = InventoryChanges {
+            quantity: Some(-1.0),
+            ..Default::default()
+        };
+        assert_eq!(changes.validate().unwrap_err().0[0].field, "quantity");
+    }
+
+    #[test]
+    fn review_rating_and_body_checked() {
+        let review = NewReview {
+            user_id: 1,
+            batch_id: 1,
+            rating: 6,
+            body: Some("x".repeat(MAX_REVIEW_LENGTH + 1)),
+            effects: vec![],
+            flavors: vec![],
+        };
+        let errors = review.validate().unwrap_err();
+        let fields: Vec<&str> = errors.0.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["rating", "body"]);
+
+        let changes = ReviewChanges {
+            rating: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(changes.validate().unwrap_err().0[0].field, "rating");
+        let changes = ReviewChanges {
+            body: Some("Smooth and citrusy".to_owned()),
+            ..Default::default()
+        };
+        assert!(changes.validate().is_ok());
+    }
+}